@@ -0,0 +1,103 @@
+//! A small JSON API: a router shared by a pool of worker threads, with latency
+//! metrics and an overload limit.
+//!
+//! ```sh
+//! cargo run --example json_api 127.0.0.1:8080
+//! curl -H 'content-type: application/json' -d '{"title":"buy milk"}' localhost:8080/todos
+//! curl localhost:8080/todos/0
+//! curl localhost:8080/metrics
+//! ```
+
+use std::sync::mpsc;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+use blocking_http_server::metrics::Metrics;
+use blocking_http_server::router::Params;
+use blocking_http_server::*;
+use serde::Deserialize;
+use serde::Serialize;
+
+const WORKERS: usize = 4;
+
+fn main() -> anyhow::Result<()> {
+    let addr = std::env::args().nth(1).unwrap_or_else(|| "127.0.0.1:8080".into());
+    let mut server = Server::bind(&addr)?;
+    server.set_overload_limit(Some(WORKERS * 16));
+
+    let todos: Arc<Mutex<Vec<String>>> = Arc::default();
+    let metrics = Metrics::new();
+    metrics.instrument(&mut server);
+    let router = Arc::new(routes(todos, metrics));
+
+    let (tx, rx) = mpsc::channel::<HttpRequest>();
+    let rx = Arc::new(Mutex::new(rx));
+    let workers: Vec<_> = (0..WORKERS)
+        .map(|_| {
+            let rx = rx.clone();
+            let router = router.clone();
+            std::thread::spawn(move || loop {
+                let Ok(req) = rx.lock().unwrap().recv() else {
+                    return;
+                };
+                if let Err(e) = router.serve(req) {
+                    eprintln!("respond: {e}");
+                }
+            })
+        })
+        .collect();
+
+    println!("listening on http://{}", server.local_addr()?);
+    for req in server.incoming() {
+        match req {
+            Ok(req) => tx.send(req)?,
+            Err(e) => eprintln!("error: {e}"),
+        }
+    }
+    drop(tx);
+    for worker in workers {
+        let _ = worker.join();
+    }
+    Ok(())
+}
+
+#[derive(Deserialize)]
+struct NewTodo {
+    title: String,
+}
+
+#[derive(Serialize)]
+struct Created {
+    id: usize,
+}
+
+#[derive(Serialize)]
+struct Error {
+    error: &'static str,
+}
+
+fn routes(todos: Arc<Mutex<Vec<String>>>, metrics: Metrics) -> Router {
+    let list = todos.clone();
+    let get = todos.clone();
+    Router::new()
+        .get("/todos", move |_| Response::json(&*list.lock().unwrap()))
+        .get("/todos/:id", move |req| {
+            let params = req.extensions().get::<Params>();
+            let id = params.and_then(|p| p.get("id")).and_then(|id| id.parse::<usize>().ok());
+            match id.and_then(|id| get.lock().unwrap().get(id).cloned()) {
+                Some(todo) => Response::json(&todo),
+                None => Response::json(&Error { error: "no such todo" }).with_status(StatusCode::NOT_FOUND),
+            }
+        })
+        .post("/todos", move |req| {
+            let new: NewTodo = match req.json() {
+                Ok(new) => new,
+                Err(e) => return e.into(),
+            };
+            let mut todos = todos.lock().unwrap();
+            todos.push(new.title);
+            Response::json(&Created { id: todos.len() - 1 }).with_status(StatusCode::CREATED)
+        })
+        .get("/metrics", metrics.handler())
+        .wrap(metrics)
+}
@@ -0,0 +1,33 @@
+//! Forwards every request to one upstream server and relays its answer.
+//!
+//! ```sh
+//! cargo run --example reverse_proxy 127.0.0.1:8080 127.0.0.1:3000
+//! ```
+
+use blocking_http_server::proxy::UpstreamPool;
+use blocking_http_server::*;
+
+fn main() -> anyhow::Result<()> {
+    let mut args = std::env::args().skip(1);
+    let addr = args.next().unwrap_or_else(|| "127.0.0.1:8080".into());
+    let upstream = args.next().unwrap_or_else(|| "127.0.0.1:3000".into());
+
+    let mut server = Server::bind(&addr)?;
+    // So that chunked uploads can be forwarded.
+    server.set_chunked_body_limit(Some(16 << 20));
+    let pool = UpstreamPool::new();
+    println!("proxying http://{} to {upstream}", server.local_addr()?);
+    for req in server.incoming().flatten() {
+        let upstream = upstream.clone();
+        let pool = pool.clone();
+        std::thread::spawn(move || {
+            if let Err(e) = pool.forward(&req, &upstream) {
+                eprintln!("upstream: {e}");
+                let _ = req.respond(
+                    Response::new(b"502 Bad Gateway".to_vec()).with_status(StatusCode::BAD_GATEWAY),
+                );
+            }
+        });
+    }
+    Ok(())
+}
@@ -31,23 +31,15 @@ fn main() -> anyhow::Result<()> {
             }
             (&Method::GET, "/json") => {
                 let _ = req.respond(
-                    Response::builder()
-                        .header("Content-Type", "application/json")
-                        .body(r#"{"key":"value"}"#)
-                        .unwrap()
-                    );
+                    Response::new(r#"{"key":"value"}"#).with_content_type("application/json"),
+                );
             }
             (&Method::POST, "/json") => {
                 let body = req.body();
                 let _ = req.respond(Response::new(body));
             }
             _ => {
-                let _ = req.respond(
-                    Response::builder()
-                        .status(StatusCode::NOT_FOUND)
-                        .body("404 Not Found")
-                        .unwrap(),
-                );
+                let _ = req.respond(Response::new("404 Not Found").with_status(StatusCode::NOT_FOUND));
             }
         }
     }
@@ -0,0 +1,75 @@
+//! A dashboard fed by server-sent events: every open page gets the server's
+//! uptime and connection count once a second.
+//!
+//! ```sh
+//! cargo run --example sse 127.0.0.1:8080
+//! ```
+//!
+//! Each event stream is a chunked response written from its own thread.
+
+use std::io::Write;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::Duration;
+use std::time::Instant;
+
+use blocking_http_server::*;
+
+const PAGE: &str = r#"<!doctype html>
+<title>dashboard</title>
+<pre id="stats">connecting...</pre>
+<script>
+  new EventSource("/events").onmessage = (e) => {
+    document.getElementById("stats").textContent = e.data;
+  };
+</script>
+"#;
+
+fn main() -> anyhow::Result<()> {
+    let addr = std::env::args().nth(1).unwrap_or_else(|| "127.0.0.1:8080".into());
+    let mut server = Server::bind(&addr)?;
+    let started = Instant::now();
+    let watchers = Arc::new(AtomicUsize::new(0));
+
+    println!("open http://{}", server.local_addr()?);
+    for req in server.incoming().flatten() {
+        match req.uri().path() {
+            "/events" => {
+                let watchers = watchers.clone();
+                std::thread::spawn(move || stream_events(req, started, &watchers));
+            }
+            "/" => {
+                let _ = req.respond(
+                    Response::new(PAGE.as_bytes().to_vec()).with_content_type("text/html; charset=utf-8"),
+                );
+            }
+            _ => {
+                let _ = req.respond(Response::new(Vec::new()).with_status(StatusCode::NOT_FOUND));
+            }
+        }
+    }
+    Ok(())
+}
+
+fn stream_events(req: HttpRequest, started: Instant, watchers: &AtomicUsize) {
+    let head = Response::new(())
+        .with_content_type("text/event-stream")
+        .with_header(header::CACHE_CONTROL, HeaderValue::from_static("no-cache"));
+    let Ok(mut stream) = req.respond_chunked(head) else {
+        return;
+    };
+    watchers.fetch_add(1, Ordering::Relaxed);
+    loop {
+        let event = format!(
+            "data: uptime {}s, {} watching\n\n",
+            started.elapsed().as_secs(),
+            watchers.load(Ordering::Relaxed)
+        );
+        if stream.write_all(event.as_bytes()).and_then(|_| stream.flush()).is_err() {
+            break;
+        }
+        std::thread::sleep(Duration::from_secs(1));
+    }
+    watchers.fetch_sub(1, Ordering::Relaxed);
+}
@@ -0,0 +1,34 @@
+//! Serves the files under a directory, with kept-alive connections.
+//!
+//! ```sh
+//! cargo run --example static_files 127.0.0.1:8080 ./public
+//! ```
+
+use std::time::Duration;
+
+use blocking_http_server::files::ServeDir;
+use blocking_http_server::*;
+
+fn main() -> anyhow::Result<()> {
+    let mut args = std::env::args().skip(1);
+    let addr = args.next().unwrap_or_else(|| "127.0.0.1:8080".into());
+    let root = args.next().unwrap_or_else(|| ".".into());
+    let files = ServeDir::new(&root)?.with_listing(true).with_precompressed(true);
+
+    let mut server = Server::bind(&addr)?;
+    server.set_keep_alive(Some(Duration::from_secs(5)));
+    server.set_read_timeout(Some(Duration::from_secs(10)));
+
+    println!("serving {root} on http://{}", server.local_addr()?);
+    for req in server.incoming() {
+        let req = match req {
+            Ok(req) => req,
+            Err(e) => {
+                eprintln!("error: {e}");
+                continue;
+            }
+        };
+        let _ = files.send(&req);
+    }
+    Ok(())
+}
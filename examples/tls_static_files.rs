@@ -0,0 +1,56 @@
+//! Serves the files under a directory over HTTPS, and over HTTP/2 to clients
+//! that ask for it when built with the `h2` feature.
+//!
+//! ```sh
+//! cargo run --example tls_static_files --features h2 127.0.0.1:8443 ./public cert.pem key.pem
+//! ```
+//!
+//! Without a certificate and key it makes up a self-signed certificate for
+//! `localhost`, which clients will only accept when told to (`curl -k`).
+
+use std::time::Duration;
+
+use blocking_http_server::files::ServeDir;
+use blocking_http_server::rustls::pki_types::pem::PemObject;
+use blocking_http_server::rustls::pki_types::CertificateDer;
+use blocking_http_server::rustls::pki_types::PrivateKeyDer;
+use blocking_http_server::*;
+
+fn main() -> anyhow::Result<()> {
+    let mut args = std::env::args().skip(1);
+    let addr = args.next().unwrap_or_else(|| "127.0.0.1:8443".into());
+    let root = args.next().unwrap_or_else(|| ".".into());
+    let (certs, key) = match (args.next(), args.next()) {
+        (Some(cert), Some(key)) => (
+            CertificateDer::pem_file_iter(cert)?.collect::<std::result::Result<Vec<_>, _>>()?,
+            PrivateKeyDer::from_pem_file(key)?,
+        ),
+        _ => {
+            let generated = rcgen::generate_simple_self_signed(vec!["localhost".into()])?;
+            let key = PrivateKeyDer::Pkcs8(generated.key_pair.serialize_der().into());
+            (vec![generated.cert.der().clone()], key)
+        }
+    };
+    let config = rustls::ServerConfig::builder()
+        .with_no_client_auth()
+        .with_single_cert(certs, key)?;
+    let files = ServeDir::new(&root)?.with_listing(true).with_precompressed(true);
+
+    let mut server = Server::unbound()?;
+    server.set_keep_alive(Some(Duration::from_secs(5)));
+    server.set_read_timeout(Some(Duration::from_secs(10)));
+    let local_addr = server.bind_tls(&addr, config)?;
+
+    println!("serving {root} on https://{local_addr}");
+    for req in server.incoming() {
+        let req = match req {
+            Ok(req) => req,
+            Err(e) => {
+                eprintln!("error: {e}");
+                continue;
+            }
+        };
+        let _ = files.send(&req);
+    }
+    Ok(())
+}
@@ -0,0 +1,61 @@
+//! Echoes every WebSocket message back, each connection on a thread of its own.
+//!
+//! ```sh
+//! cargo run --example websocket_echo 127.0.0.1:8080
+//! ```
+//!
+//! The server answers the opening handshake; tungstenite speaks the protocol on
+//! the connection `into_upgraded` hands over.
+
+use blocking_http_server::*;
+use tungstenite::handshake::derive_accept_key;
+use tungstenite::protocol::Role;
+use tungstenite::WebSocket;
+
+fn main() -> anyhow::Result<()> {
+    let addr = std::env::args().nth(1).unwrap_or_else(|| "127.0.0.1:8080".into());
+    let mut server = Server::bind(&addr)?;
+
+    println!("echoing on ws://{}", server.local_addr()?);
+    for req in server.incoming().flatten() {
+        std::thread::spawn(move || {
+            if let Err(e) = echo(req) {
+                eprintln!("websocket: {e}");
+            }
+        });
+    }
+    Ok(())
+}
+
+fn echo(req: HttpRequest) -> anyhow::Result<()> {
+    let upgrade = req.headers().get(header::UPGRADE);
+    let key = req
+        .headers()
+        .get(header::SEC_WEBSOCKET_KEY)
+        .filter(|_| upgrade.is_some_and(|v| v.as_bytes().eq_ignore_ascii_case(b"websocket")));
+    let Some(key) = key else {
+        let refused = Response::new(b"WebSocket only\n".to_vec())
+            .with_status(StatusCode::UPGRADE_REQUIRED)
+            .with_header(header::UPGRADE, HeaderValue::from_static("websocket"));
+        req.respond(refused)?;
+        return Ok(());
+    };
+
+    let mut headers = HeaderMap::new();
+    let accept = derive_accept_key(key.as_bytes());
+    headers.insert(header::SEC_WEBSOCKET_ACCEPT, HeaderValue::from_str(&accept)?);
+    let stream = req.into_upgraded("websocket", &headers)?;
+    // Sockets stay open for as long as the client likes.
+    stream.set_read_timeout(None)?;
+
+    let mut socket = WebSocket::from_raw_socket(stream, Role::Server, None);
+    loop {
+        match socket.read() {
+            Ok(message) if message.is_text() || message.is_binary() => socket.send(message)?,
+            // Pings and the closing handshake are answered by tungstenite itself.
+            Ok(_) => {}
+            Err(tungstenite::Error::ConnectionClosed) => return Ok(()),
+            Err(e) => return Err(e.into()),
+        }
+    }
+}
@@ -0,0 +1,237 @@
+//! `Accept`-style lists: `gzip;q=0.8, br, *;q=0.1`.
+
+use crate::header;
+use crate::header::HeaderName;
+use crate::HeaderMap;
+use crate::HttpRequest;
+
+/// The entries of every `name` header, with their `q` (1 when not given).
+/// Malformed weights count as 0, as if refused.
+pub(crate) fn entries<'a>(headers: &'a HeaderMap, name: &HeaderName) -> impl Iterator<Item = (&'a str, f32)> {
+    headers
+        .get_all(name)
+        .into_iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .filter_map(|entry| {
+            let mut params = entry.split(';');
+            let token = params.next()?.trim();
+            if token.is_empty() {
+                return None;
+            }
+            let q = params
+                .filter_map(|param| param.split_once('='))
+                .find(|(key, _)| key.trim().eq_ignore_ascii_case("q"))
+                .map_or(1.0, |(_, q)| {
+                    let q = q.trim().parse::<f32>().unwrap_or(0.0);
+                    if (0.0..=1.0).contains(&q) { q } else { 0.0 }
+                });
+            Some((token, q))
+        })
+}
+
+/// The `q` that the `name` headers give `token`, named or through `*`; `None`
+/// if neither appears, including when there is no such header at all.
+pub(crate) fn quality(headers: &HeaderMap, name: &HeaderName, token: &str) -> Option<f32> {
+    let mut wildcard = None;
+    for (entry, q) in entries(headers, name) {
+        if entry.eq_ignore_ascii_case(token) {
+            return Some(q);
+        }
+        if entry == "*" {
+            wildcard = Some(q);
+        }
+    }
+    wildcard
+}
+
+/// One entry of an `Accept` header, as `HttpRequest::accepts` gives them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MediaRange {
+    /// `text/html`, `text/*` or `*/*`, lowercase.
+    pub media_type: String,
+    /// Parameters other than `q`, like `charset`, names lowercase.
+    pub params: Vec<(String, String)>,
+    pub q: f32,
+}
+
+impl MediaRange {
+    /// Whether `media_type`, e.g. `text/html; charset=utf-8`, falls in this
+    /// range, parameters included.
+    pub fn matches(&self, media_type: &str) -> bool {
+        let mut parts = media_type.split(';');
+        let essence = parts.next().unwrap_or("").trim();
+        let Some((ty, sub)) = essence.split_once('/') else {
+            return false;
+        };
+        let type_matches = match self.media_type.split_once('/') {
+            Some(("*", "*")) => true,
+            Some((range_ty, "*")) => range_ty.eq_ignore_ascii_case(ty),
+            Some((range_ty, range_sub)) => range_ty.eq_ignore_ascii_case(ty) && range_sub.eq_ignore_ascii_case(sub),
+            None => false,
+        };
+        let params: Vec<(String, String)> = parts.filter_map(param).collect();
+        type_matches
+            && self
+                .params
+                .iter()
+                .all(|(name, value)| params.iter().any(|(n, v)| n == name && v.eq_ignore_ascii_case(value)))
+    }
+
+    /// `*/*` < `text/*` < `text/html` < `text/html;level=1`: when several ranges
+    /// match, the most specific one's `q` counts.
+    fn specificity(&self) -> usize {
+        match self.media_type.as_str() {
+            "*/*" => 0,
+            ty if ty.ends_with("/*") => 1,
+            _ => 2 + self.params.len(),
+        }
+    }
+}
+
+fn param(param: &str) -> Option<(String, String)> {
+    let (name, value) = param.split_once('=')?;
+    Some((name.trim().to_ascii_lowercase(), value.trim().trim_matches('"').to_owned()))
+}
+
+impl HttpRequest {
+    /// The media ranges of the `Accept` header, highest `q` first, the more
+    /// specific first among equals. Empty without an `Accept` header, which
+    /// means anything goes.
+    pub fn accepts(&self) -> Vec<MediaRange> {
+        let mut ranges: Vec<MediaRange> = self
+            .headers()
+            .get_all(header::ACCEPT)
+            .into_iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .filter_map(|entry| {
+                let mut parts = entry.split(';');
+                let media_type = parts.next()?.trim().to_ascii_lowercase();
+                if !media_type.contains('/') {
+                    return None;
+                }
+                let mut range = MediaRange {
+                    media_type,
+                    params: Vec::new(),
+                    q: 1.0,
+                };
+                for (name, value) in parts.filter_map(param) {
+                    if name == "q" {
+                        let q = value.parse::<f32>().unwrap_or(0.0);
+                        range.q = if (0.0..=1.0).contains(&q) { q } else { 0.0 };
+                        // What follows `q` are extensions, not media type parameters.
+                        break;
+                    }
+                    range.params.push((name, value));
+                }
+                Some(range)
+            })
+            .collect();
+        ranges.sort_by(|a, b| b.q.total_cmp(&a.q).then(b.specificity().cmp(&a.specificity())));
+        ranges
+    }
+
+    /// Picks the media type from `available`, in the server's order of
+    /// preference, that the client's `Accept` rates highest. `None` if it accepts
+    /// none of them; the first one if it sent no `Accept` at all.
+    ///
+    /// ```no_run
+    /// use blocking_http_server::*;
+    ///
+    /// let router = Router::new().get("/report", |req| match req.negotiate(&["application/json", "text/html"]) {
+    ///     Some("text/html") => Response::new(b"<h1>Report</h1>".to_vec()),
+    ///     Some(_) => Response::new(br#"{"title":"Report"}"#.to_vec()),
+    ///     None => {
+    ///         let mut response = Response::new(Vec::new());
+    ///         *response.status_mut() = StatusCode::NOT_ACCEPTABLE;
+    ///         response
+    ///     }
+    /// });
+    /// ```
+    pub fn negotiate<'a>(&self, available: &[&'a str]) -> Option<&'a str> {
+        let ranges = self.accepts();
+        if ranges.is_empty() {
+            return available.first().copied();
+        }
+        let mut best: Option<(f32, &'a str)> = None;
+        for &media_type in available {
+            // The most specific matching range decides, even with a lower `q`.
+            let q = ranges
+                .iter()
+                .filter(|range| range.matches(media_type))
+                .max_by_key(|range| range.specificity())
+                .map_or(0.0, |range| range.q);
+            if q > 0.0 && best.is_none_or(|(best, _)| q > best) {
+                best = Some((q, media_type));
+            }
+        }
+        best.map(|(_, media_type)| media_type)
+    }
+
+    /// The language ranges of `Accept-Language`, lowercase, highest `q` first.
+    pub fn accept_languages(&self) -> Vec<(String, f32)> {
+        let mut ranges: Vec<(String, f32)> = entries(self.headers(), &header::ACCEPT_LANGUAGE)
+            .map(|(range, q)| (range.to_ascii_lowercase(), q))
+            .collect();
+        ranges.sort_by(|a, b| b.1.total_cmp(&a.1));
+        ranges
+    }
+
+    /// Picks the language tag from `supported`, in the server's order of
+    /// preference, that `Accept-Language` asks for first. `en` serves a request
+    /// for `en-GB` when no closer match is supported, and a request for `en` can
+    /// be served by `en-US`. `None` if nothing fits; the first one if the client
+    /// sent no `Accept-Language`.
+    ///
+    /// ```no_run
+    /// use blocking_http_server::files::ServeDir;
+    /// use blocking_http_server::*;
+    ///
+    /// let sites = [("en", ServeDir::new("site/en")?), ("de", ServeDir::new("site/de")?)];
+    /// let router = Router::new().get("/*", move |req| {
+    ///     let language = req.preferred_language(&["en", "de"]).unwrap_or("en");
+    ///     let (_, dir) = sites.iter().find(|(tag, _)| *tag == language).unwrap();
+    ///     dir.serve(req)
+    /// });
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    pub fn preferred_language<'a>(&self, supported: &[&'a str]) -> Option<&'a str> {
+        let ranges = self.accept_languages();
+        if ranges.is_empty() {
+            return supported.first().copied();
+        }
+        let excluded = |tag: &str| ranges.iter().any(|(range, q)| *q <= 0.0 && language_matches(range, tag));
+        let candidates: Vec<&str> = supported.iter().copied().filter(|tag| !excluded(tag)).collect();
+        for (range, q) in &ranges {
+            if *q <= 0.0 {
+                break;
+            }
+            if range == "*" {
+                return candidates.first().copied();
+            }
+            if let Some(tag) = candidates.iter().find(|tag| language_matches(range, tag)) {
+                return Some(tag);
+            }
+            // Falling back from `en-gb` to `en`, as RFC 4647's lookup does.
+            let mut fallback = range.as_str();
+            while let Some((shorter, _)) = fallback.rsplit_once('-') {
+                fallback = shorter;
+                if let Some(tag) = candidates.iter().find(|tag| tag.eq_ignore_ascii_case(fallback)) {
+                    return Some(tag);
+                }
+            }
+        }
+        None
+    }
+}
+
+/// Basic filtering (RFC 4647): `en` matches `en` and `en-US`, not `eng`.
+fn language_matches(range: &str, tag: &str) -> bool {
+    if range == "*" {
+        return true;
+    }
+    tag.len() >= range.len()
+        && tag.as_bytes()[..range.len()].eq_ignore_ascii_case(range.as_bytes())
+        && tag.as_bytes().get(range.len()).is_none_or(|&b| b == b'-')
+}
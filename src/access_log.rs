@@ -0,0 +1,200 @@
+//! Access logs in the Common or Combined Log Format, or as JSON lines, written to
+//! any `Write`.
+//!
+//! ```no_run
+//! use blocking_http_server::access_log::AccessLog;
+//! use blocking_http_server::*;
+//!
+//! let mut server = Server::bind("127.0.0.1:8080")?;
+//! let log = AccessLog::new(std::io::stdout());
+//! server.on_response(move |req, info| {
+//!     let _ = log.log(req, info);
+//! });
+//! # Ok::<(), std::io::Error>(())
+//! ```
+
+use std::fmt;
+use std::io;
+use std::io::Write;
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+use crate::date;
+use crate::header;
+use crate::header::HeaderName;
+use crate::HeaderValue;
+use crate::HttpRequest;
+use crate::ResponseInfo;
+
+/// The layout of each log line.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum LogFormat {
+    /// `host - - [time] "request line" status bytes`
+    #[default]
+    Common,
+    /// `Common`, followed by the quoted `Referer` and `User-Agent`.
+    Combined,
+    /// One JSON object per line, for log shippers:
+    ///
+    /// ```text
+    /// {"time":"2000-10-10T13:55:36Z","request_id":"f3a1","client":"192.0.2.7","method":"GET","path":"/","version":"HTTP/1.1","status":200,"bytes":512,"duration_ms":1.204,"referer":null,"user_agent":"curl/8.5.0"}
+    /// ```
+    ///
+    /// `request_id` is the request's `X-Request-Id`, as set by many load
+    /// balancers. Absent fields are `null`; `with_latency` doesn't apply, the
+    /// duration is always there.
+    Json,
+}
+
+pub struct AccessLog {
+    sink: Mutex<Box<dyn Write + Send>>,
+    format: LogFormat,
+    latency: bool,
+}
+
+impl AccessLog {
+    /// Logs in the Common Log Format to `sink`, flushing after every line.
+    pub fn new(sink: impl Write + Send + 'static) -> Self {
+        Self {
+            sink: Mutex::new(Box::new(sink)),
+            format: LogFormat::Common,
+            latency: false,
+        }
+    }
+
+    pub fn with_format(mut self, format: LogFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Ends each line with the time taken, in seconds with millisecond precision,
+    /// like nginx's `$request_time`.
+    pub fn with_latency(mut self) -> Self {
+        self.latency = true;
+        self
+    }
+
+    /// Writes the line for one request. The host is `HttpRequest::client_addr`,
+    /// or the peer address when there is none; bytes are the whole response,
+    /// head included.
+    pub fn log(&self, req: &HttpRequest, info: &ResponseInfo) -> io::Result<()> {
+        let line = self.format_line(req, info, SystemTime::now());
+        let mut sink = self.sink.lock().unwrap_or_else(|e| e.into_inner());
+        sink.write_all(line.as_bytes())?;
+        sink.flush()
+    }
+
+    fn format_line(&self, req: &HttpRequest, info: &ResponseInfo, now: SystemTime) -> String {
+        if self.format == LogFormat::Json {
+            return json_line(req, info, now);
+        }
+        let mut line = client(req);
+        line.push_str(" - - [");
+        line.push_str(&date::format_clf(now));
+        line.push_str("] ");
+        let target = req.uri().path_and_query().map_or("/", |p| p.as_str());
+        quote(&mut line, format!("{} {target} {:?}", req.method(), req.version()).as_bytes());
+        match info.status {
+            Some(status) => line.push_str(&format!(" {} ", status.as_u16())),
+            None => line.push_str(" - "),
+        }
+        match info.bytes_written {
+            0 => line.push('-'),
+            n => line.push_str(&n.to_string()),
+        }
+        if self.format == LogFormat::Combined {
+            for name in [header::REFERER, header::USER_AGENT] {
+                line.push(' ');
+                quote(&mut line, req.headers().get(name).map_or(b"-", |v| v.as_bytes()));
+            }
+        }
+        if self.latency {
+            line.push_str(&format!(" {:.3}", info.duration.as_secs_f64()));
+        }
+        line.push('\n');
+        line
+    }
+}
+
+fn client(req: &HttpRequest) -> String {
+    match req.client_addr() {
+        Some(ip) => ip.to_string(),
+        None => req.peer_addr.to_string(),
+    }
+}
+
+fn json_line(req: &HttpRequest, info: &ResponseInfo, now: SystemTime) -> String {
+    let header = |name| req.headers().get(name).map(|v: &HeaderValue| v.as_bytes());
+    let mut line = String::from("{\"time\":");
+    json_string(&mut line, Some(date::format_rfc3339(now).as_bytes()));
+    line.push_str(",\"request_id\":");
+    json_string(&mut line, header(HeaderName::from_static("x-request-id")));
+    line.push_str(",\"client\":");
+    json_string(&mut line, Some(client(req).as_bytes()));
+    line.push_str(",\"method\":");
+    json_string(&mut line, Some(req.method().as_str().as_bytes()));
+    line.push_str(",\"path\":");
+    json_string(&mut line, Some(req.uri().path_and_query().map_or("/", |p| p.as_str()).as_bytes()));
+    line.push_str(",\"version\":");
+    json_string(&mut line, Some(format!("{:?}", req.version()).as_bytes()));
+    match info.status {
+        Some(status) => line.push_str(&format!(",\"status\":{}", status.as_u16())),
+        None => line.push_str(",\"status\":null"),
+    }
+    line.push_str(&format!(
+        ",\"bytes\":{},\"duration_ms\":{:.3}",
+        info.bytes_written,
+        info.duration.as_secs_f64() * 1000.0
+    ));
+    line.push_str(",\"referer\":");
+    json_string(&mut line, header(header::REFERER));
+    line.push_str(",\"user_agent\":");
+    json_string(&mut line, header(header::USER_AGENT));
+    line.push_str("}\n");
+    line
+}
+
+/// Appends `value` as a JSON string, or `null`. Bytes that aren't UTF-8 are
+/// replaced.
+fn json_string(line: &mut String, value: Option<&[u8]>) {
+    let Some(value) = value else {
+        line.push_str("null");
+        return;
+    };
+    line.push('"');
+    for c in String::from_utf8_lossy(value).chars() {
+        match c {
+            '"' => line.push_str("\\\""),
+            '\\' => line.push_str("\\\\"),
+            c if c.is_control() => line.push_str(&format!("\\u{:04x}", c as u32)),
+            c => line.push(c),
+        }
+    }
+    line.push('"');
+}
+
+impl fmt::Debug for AccessLog {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AccessLog")
+            .field("format", &self.format)
+            .field("latency", &self.latency)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Appends `value` in double quotes, escaping what could break the line apart.
+fn quote(line: &mut String, value: &[u8]) {
+    line.push('"');
+    for &b in value {
+        match b {
+            b'"' | b'\\' => {
+                line.push('\\');
+                line.push(b as char);
+            }
+            b' '..=b'~' => line.push(b as char),
+            _ => line.push_str(&format!("\\x{b:02X}")),
+        }
+    }
+    line.push('"');
+}
@@ -0,0 +1,44 @@
+use std::io;
+use std::os::fd::RawFd;
+
+use crate::net::Listener;
+use crate::Server;
+
+/// The first inherited descriptor, per `sd_listen_fds(3)`.
+const LISTEN_FDS_START: RawFd = 3;
+
+impl Server {
+    /// Serves the sockets passed in by systemd (or any launcher following the
+    /// `LISTEN_FDS` protocol). Fails with `NotFound` when the process was not
+    /// socket-activated. Like `sd_listen_fds(3)` it clears the variables, so
+    /// child processes don't pick up the sockets too; call it at startup, before
+    /// other threads could be reading the environment.
+    pub fn from_env() -> io::Result<Self> {
+        let fds = listen_fds()?;
+        std::env::remove_var("LISTEN_PID");
+        std::env::remove_var("LISTEN_FDS");
+        std::env::remove_var("LISTEN_FDNAMES");
+
+        let listeners = (LISTEN_FDS_START..LISTEN_FDS_START + fds)
+            // SAFETY: the launcher hands these descriptors to this process to own.
+            .map(|fd| unsafe { Listener::inherit(fd) })
+            .collect::<io::Result<Vec<_>>>()?;
+        Self::from_listeners(listeners)
+    }
+}
+
+fn listen_fds() -> io::Result<RawFd> {
+    let not_activated = |what| io::Error::new(io::ErrorKind::NotFound, what);
+    let pid = std::env::var("LISTEN_PID").map_err(|_| not_activated("LISTEN_PID is not set"))?;
+    if pid.parse() != Ok(std::process::id()) {
+        return Err(not_activated("LISTEN_PID is for another process"));
+    }
+    let fds: RawFd = std::env::var("LISTEN_FDS")
+        .ok()
+        .and_then(|n| n.parse().ok())
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "invalid LISTEN_FDS"))?;
+    if fds < 1 {
+        return Err(not_activated("no sockets passed in LISTEN_FDS"));
+    }
+    Ok(fds)
+}
@@ -0,0 +1,110 @@
+use std::collections::hash_map::RandomState;
+use std::hash::BuildHasher;
+use std::io;
+use std::time::Duration;
+use std::time::Instant;
+
+/// Exponential backoff applied while `accept()` keeps failing, e.g. when the
+/// process has run out of file descriptors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AcceptBackoff {
+    pub initial: Duration,
+    pub max: Duration,
+}
+
+impl Default for AcceptBackoff {
+    fn default() -> Self {
+        Self {
+            initial: Duration::from_millis(5),
+            max: Duration::from_secs(1),
+        }
+    }
+}
+
+/// A run of consecutive accept failures.
+#[derive(Debug)]
+pub(crate) struct AcceptStorm {
+    started: Instant,
+    failures: usize,
+    delay: Duration,
+    last_error: io::Error,
+    reported: Instant,
+}
+
+impl AcceptStorm {
+    /// While the storm lasts, it is reported again this often.
+    const REPORT_INTERVAL: Duration = Duration::from_secs(60);
+
+    /// Also gives the warning that the storm has started.
+    pub(crate) fn new(backoff: &AcceptBackoff, err: io::Error) -> (Self, io::Error) {
+        let report = io::Error::new(err.kind(), format!("accept failed, backing off: {err}"));
+        let storm = Self {
+            started: Instant::now(),
+            failures: 1,
+            delay: backoff.initial,
+            last_error: err,
+            reported: Instant::now(),
+        };
+        (storm, report)
+    }
+
+    /// A warning when the delay first reaches `backoff.max`, and every
+    /// `REPORT_INTERVAL` after that, so that a storm that never ends is still heard of.
+    pub(crate) fn record(&mut self, backoff: &AcceptBackoff, err: io::Error) -> Option<io::Error> {
+        self.failures += 1;
+        let maxed = self.delay >= backoff.max;
+        self.delay = (self.delay * 2).min(backoff.max);
+        self.last_error = err;
+        if (!maxed && self.delay >= backoff.max) || self.reported.elapsed() >= Self::REPORT_INTERVAL {
+            self.reported = Instant::now();
+            return Some(self.summary("so far"));
+        }
+        None
+    }
+
+    /// How long to sleep before the next attempt: the current delay scaled by a
+    /// random factor in `[0.5, 1.0)` so that several servers don't retry in lockstep.
+    pub(crate) fn delay(&self) -> Duration {
+        let factor = 0.5 + (RandomState::new().hash_one(self.failures) % 1000) as f64 / 2000.0;
+        self.delay.mul_f64(factor)
+    }
+
+    /// The warning summing up the storm once it is over.
+    pub(crate) fn into_error(self) -> io::Error {
+        self.summary("before recovering")
+    }
+
+    fn summary(&self, when: &str) -> io::Error {
+        io::Error::new(
+            self.last_error.kind(),
+            format!(
+                "accept failed {} times in {:?} {when}, last error: {}",
+                self.failures,
+                self.started.elapsed(),
+                self.last_error
+            ),
+        )
+    }
+}
+
+/// Errors caused by the peer or by temporary resource exhaustion, which say
+/// nothing about the health of the listener itself.
+pub(crate) fn is_transient(err: &io::Error) -> bool {
+    #[cfg(unix)]
+    let exhausted = matches!(
+        err.raw_os_error(),
+        Some(libc::EMFILE | libc::ENFILE | libc::ENOBUFS | libc::ENOMEM)
+    );
+    #[cfg(not(unix))]
+    let exhausted = false;
+
+    exhausted
+        || matches!(
+            err.kind(),
+            io::ErrorKind::ConnectionAborted
+                | io::ErrorKind::ConnectionReset
+                | io::ErrorKind::Interrupted
+                | io::ErrorKind::TimedOut
+                | io::ErrorKind::OutOfMemory
+        )
+}
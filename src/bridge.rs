@@ -0,0 +1,85 @@
+//! Accept and parse on a dedicated blocking thread, handle in an async runtime.
+//!
+//! ```no_run
+//! # async fn run() -> std::io::Result<()> {
+//! use blocking_http_server::*;
+//!
+//! let server = Server::bind("127.0.0.1:8000")?;
+//! let (mut requests, _shutdown) = server.spawn_bridge(64)?;
+//! while let Some(req) = requests.recv().await {
+//!     tokio::spawn(async move {
+//!         let _ = req.respond(Response::new(b"hello".to_vec())).await;
+//!     });
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use std::io;
+use std::ops::Deref;
+use std::ops::DerefMut;
+
+use tokio::sync::mpsc;
+
+use crate::HttpRequest;
+use crate::Response;
+use crate::Server;
+use crate::ShutdownHandle;
+
+/// A parsed request handed over from the bridge thread. It owns its buffers and
+/// connection, so it can move freely between tasks.
+#[derive(Debug)]
+pub struct AsyncRequest {
+    request: HttpRequest,
+}
+
+impl AsyncRequest {
+    /// Writes the response on tokio's blocking pool. Must be called from within a
+    /// tokio runtime.
+    pub async fn respond(self, response: Response<Vec<u8>>) -> io::Result<()> {
+        tokio::task::spawn_blocking(move || self.request.respond(response))
+            .await
+            .map_err(io::Error::other)?
+    }
+
+    pub fn into_inner(self) -> HttpRequest {
+        self.request
+    }
+}
+
+impl Deref for AsyncRequest {
+    type Target = HttpRequest;
+    fn deref(&self) -> &Self::Target {
+        &self.request
+    }
+}
+
+impl DerefMut for AsyncRequest {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.request
+    }
+}
+
+impl Server {
+    /// Moves the server onto a new thread that feeds parsed requests into a bounded
+    /// channel of `buffer` slots. The thread ends when the server is shut down or the
+    /// receiver is dropped (noticed on the next request). Failed requests are only
+    /// reported to `on_error`.
+    pub fn spawn_bridge(
+        mut self,
+        buffer: usize,
+    ) -> io::Result<(mpsc::Receiver<AsyncRequest>, ShutdownHandle)> {
+        let shutdown = self.shutdown_handle()?;
+        let (tx, rx) = mpsc::channel(buffer);
+        std::thread::Builder::new()
+            .name("http-bridge".into())
+            .spawn(move || {
+                for request in self.incoming().flatten() {
+                    if tx.blocking_send(AsyncRequest { request }).is_err() {
+                        break;
+                    }
+                }
+            })?;
+        Ok((rx, shutdown))
+    }
+}
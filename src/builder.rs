@@ -0,0 +1,96 @@
+use std::io;
+use std::net::Ipv4Addr;
+use std::net::Ipv6Addr;
+use std::net::SocketAddr;
+use std::net::ToSocketAddrs;
+
+use crate::net::BindOptions;
+use crate::net::Listener;
+use crate::Server;
+
+#[cfg(unix)]
+const EAFNOSUPPORT: i32 = libc::EAFNOSUPPORT;
+/// `WSAEAFNOSUPPORT`.
+#[cfg(not(unix))]
+const EAFNOSUPPORT: i32 = 10047;
+
+/// Options that have to be set before the listening socket exists. Everything
+/// else is configured on the `Server` afterwards.
+///
+/// ```no_run
+/// # fn main() -> std::io::Result<()> {
+/// let mut server = blocking_http_server::Server::builder()
+///     .backlog(1024)
+///     .bind("0.0.0.0:8080")?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct ServerBuilder {
+    options: BindOptions,
+}
+
+impl ServerBuilder {
+    /// Length of the queue of connections the kernel completes before `accept()`
+    /// picks them up; 128 by default, as with `TcpListener::bind`. Bursts beyond it
+    /// are refused. The kernel may cap it (e.g. `net.core.somaxconn` on Linux).
+    pub fn backlog(mut self, backlog: u32) -> Self {
+        self.options.backlog = backlog;
+        self
+    }
+
+    /// See `Server::bind_reuseport`.
+    #[cfg(unix)]
+    pub fn reuse_port(mut self, reuse_port: bool) -> Self {
+        self.options.reuse_port = reuse_port;
+        self
+    }
+
+    pub fn bind(self, addr: impl ToSocketAddrs) -> io::Result<Server> {
+        Server::from_listeners(vec![Listener::Tcp(self.options.bind(addr)?)])
+    }
+
+    /// See `Server::bind_dual_stack`.
+    pub fn bind_dual_stack(self, port: u16) -> io::Result<Server> {
+        let v6 = SocketAddr::from((Ipv6Addr::UNSPECIFIED, port));
+        let v4 = SocketAddr::from((Ipv4Addr::UNSPECIFIED, port));
+
+        let mut options = self.options;
+        options.only_v6 = Some(false);
+        if let Ok(listener) = options.bind(v6) {
+            return Server::from_listeners(vec![Listener::Tcp(listener)]);
+        }
+
+        options.only_v6 = Some(true);
+        let v4 = options.bind(v4)?;
+        // Keep the port the v4 socket got when asked for any.
+        let v6 = SocketAddr::from((Ipv6Addr::UNSPECIFIED, v4.local_addr()?.port()));
+        let mut listeners = vec![Listener::Tcp(v4)];
+        match options.bind(v6) {
+            Ok(v6) => listeners.push(Listener::Tcp(v6)),
+            // A host without IPv6 still gets served over IPv4.
+            Err(e) if e.kind() == io::ErrorKind::AddrNotAvailable => {}
+            Err(e) if e.raw_os_error() == Some(EAFNOSUPPORT) => {}
+            Err(e) => return Err(e),
+        }
+        Server::from_listeners(listeners)
+    }
+
+    /// See `Server::bind_all`.
+    pub fn bind_all<A: ToSocketAddrs>(
+        self,
+        addrs: impl IntoIterator<Item = A>,
+    ) -> io::Result<Server> {
+        let listeners = addrs
+            .into_iter()
+            .map(|addr| self.options.bind(addr).map(Listener::Tcp))
+            .collect::<io::Result<Vec<_>>>()?;
+        if listeners.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "no addresses to bind",
+            ));
+        }
+        Server::from_listeners(listeners)
+    }
+}
@@ -0,0 +1,139 @@
+use std::io;
+use std::io::Write;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+
+use crate::header;
+use crate::header::HeaderName;
+use crate::hooks::CountingWriter;
+use crate::throttle::Throttled;
+use crate::HeaderMap;
+use crate::Stream;
+
+/// How the body of a streamed response is delimited.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Framing {
+    Chunked,
+    /// A `content-length` set by the caller, with what is left of it to send.
+    Length(u64),
+    /// HTTP/1.0 without a length: the body runs until the connection closes.
+    Close,
+    /// Responses to HEAD, `204` and `304` have no body to write.
+    None,
+}
+
+/// The body of a response started with `HttpRequest::respond_chunked`, sent as it
+/// is written.
+///
+/// Each `write` goes out as one chunk, so wrap it in a `BufWriter` to batch small
+/// writes. `finish` ends the body and sends the trailers; dropping the writer
+/// without it closes the connection, so the client sees a truncated body rather
+/// than a complete one.
+pub struct ChunkedWriter<'a> {
+    stream: CountingWriter<'a, Throttled<'a, &'a Stream>>,
+    /// Names listed in the response's `trailer` header.
+    declared: Vec<HeaderName>,
+    framing: Framing,
+    reusable: &'a AtomicBool,
+    close: bool,
+}
+
+impl<'a> ChunkedWriter<'a> {
+    pub(crate) fn new(
+        stream: CountingWriter<'a, Throttled<'a, &'a Stream>>,
+        headers: &HeaderMap,
+        framing: Framing,
+        reusable: &'a AtomicBool,
+        close: bool,
+    ) -> Self {
+        let declared = headers
+            .get_all(header::TRAILER)
+            .iter()
+            .flat_map(|v| v.as_bytes().split(|&b| b == b','))
+            .filter_map(|name| HeaderName::from_bytes(name.trim_ascii()).ok())
+            .collect();
+        Self {
+            stream,
+            declared,
+            framing,
+            reusable,
+            close,
+        }
+    }
+
+    /// Whether `name` was announced in the response's `trailer` header.
+    pub fn is_declared(&self, name: &HeaderName) -> bool {
+        self.declared.contains(name)
+    }
+
+    /// Ends the body, sending `trailers` after it. Every trailer must have been
+    /// announced in the response's `trailer` header. Only chunked bodies carry
+    /// trailers, so they are dropped for the others.
+    pub fn finish(mut self, trailers: &HeaderMap) -> io::Result<()> {
+        if let Some(name) = trailers.keys().find(|name| !self.is_declared(name)) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("trailer `{name}` was not declared in the `trailer` header"),
+            ));
+        }
+        match self.framing {
+            Framing::Chunked => {
+                let mut end = b"0\r\n".to_vec();
+                crate::write_headers(&mut end, trailers)?;
+                end.extend_from_slice(b"\r\n");
+                self.stream.write_all(&end)?;
+            }
+            Framing::Length(0) | Framing::Close | Framing::None => {}
+            Framing::Length(_) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "body is shorter than its content-length",
+                ));
+            }
+        }
+        self.stream.flush()?;
+        self.reusable.store(!self.close, Ordering::Relaxed);
+        Ok(())
+    }
+}
+
+impl Write for ChunkedWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match &mut self.framing {
+            // An empty chunk would end the body.
+            _ if buf.is_empty() => Ok(0),
+            Framing::None => Ok(buf.len()),
+            Framing::Close => self.stream.write(buf),
+            Framing::Length(left) => {
+                if buf.len() as u64 > *left {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        "body is longer than its content-length",
+                    ));
+                }
+                let n = self.stream.write(buf)?;
+                *left -= n as u64;
+                Ok(n)
+            }
+            Framing::Chunked => {
+                let size = format!("{:x}\r\n", buf.len());
+                crate::write_all_vectored(&mut self.stream, size.as_bytes(), buf)?;
+                self.stream.write_all(b"\r\n")?;
+                Ok(buf.len())
+            }
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.stream.flush()
+    }
+}
+
+impl std::fmt::Debug for ChunkedWriter<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ChunkedWriter")
+            .field("declared", &self.declared)
+            .field("framing", &self.framing)
+            .finish_non_exhaustive()
+    }
+}
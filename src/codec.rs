@@ -0,0 +1,81 @@
+use std::any::Any;
+use std::any::TypeId;
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+use std::sync::Arc;
+
+/// Converts request/response bodies of one `content-type` family to and from `T`.
+pub trait BodyCodec<T>: Send + Sync {
+    /// `content_type` is the lowercased media type without parameters, e.g. `application/json`.
+    fn matches(&self, content_type: &str) -> bool;
+    fn decode(&self, body: &[u8]) -> io::Result<T>;
+    fn encode(&self, value: &T) -> io::Result<Vec<u8>>;
+}
+
+#[derive(Clone, Default)]
+pub struct CodecRegistry {
+    // Every entry is an `Arc<dyn BodyCodec<T>>` where `T` is the key.
+    codecs: HashMap<TypeId, Vec<Arc<dyn Any + Send + Sync>>>,
+}
+
+impl CodecRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Codecs registered earlier for the same `T` take precedence.
+    pub fn register<T: 'static>(&mut self, codec: impl BodyCodec<T> + 'static) {
+        let codec: Arc<dyn BodyCodec<T>> = Arc::new(codec);
+        self.codecs
+            .entry(TypeId::of::<T>())
+            .or_default()
+            .push(Arc::new(codec));
+    }
+
+    pub fn find<T: 'static>(&self, content_type: &str) -> Option<&dyn BodyCodec<T>> {
+        let content_type = essence(content_type);
+        self.codecs
+            .get(&TypeId::of::<T>())?
+            .iter()
+            .filter_map(|codec| codec.downcast_ref::<Arc<dyn BodyCodec<T>>>())
+            .find(|codec| codec.matches(&content_type))
+            .map(|codec| codec.as_ref())
+    }
+
+    pub fn decode<T: 'static>(&self, content_type: &str, body: &[u8]) -> io::Result<T> {
+        self.find::<T>(content_type)
+            .ok_or_else(|| unsupported(content_type))?
+            .decode(body)
+    }
+
+    pub fn encode<T: 'static>(&self, content_type: &str, value: &T) -> io::Result<Vec<u8>> {
+        self.find::<T>(content_type)
+            .ok_or_else(|| unsupported(content_type))?
+            .encode(value)
+    }
+}
+
+impl fmt::Debug for CodecRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CodecRegistry")
+            .field("types", &self.codecs.len())
+            .finish()
+    }
+}
+
+fn essence(content_type: &str) -> String {
+    content_type
+        .split(';')
+        .next()
+        .unwrap_or("")
+        .trim()
+        .to_ascii_lowercase()
+}
+
+fn unsupported(content_type: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::Unsupported,
+        format!("no codec registered for content-type {content_type:?}"),
+    )
+}
@@ -0,0 +1,96 @@
+//! Conditional requests: answering `If-None-Match` and `If-Modified-Since` with
+//! `304 Not Modified` when the client's copy is still current, saving the body
+//! for repeat visitors.
+//!
+//! `files::ServeDir` does this by itself; `apply` does it for any response that
+//! carries an `ETag` or `Last-Modified`:
+//!
+//! ```no_run
+//! use blocking_http_server::*;
+//!
+//! let router = Router::new().get("/feed", |req| {
+//!     let response = Response::new(b"...".to_vec())
+//!         .with_header(header::ETAG, HeaderValue::from_static("\"v42\""));
+//!     conditional::apply(req, response)
+//! });
+//! ```
+
+use crate::date;
+use crate::header;
+use crate::HeaderMap;
+use crate::HeaderValue;
+use crate::HttpRequest;
+use crate::Method;
+use crate::Response;
+use crate::StatusCode;
+
+/// Headers a `304` repeats from the `200` it stands for.
+const KEPT: [header::HeaderName; 6] = [
+    header::CACHE_CONTROL,
+    header::CONTENT_LOCATION,
+    header::ETAG,
+    header::EXPIRES,
+    header::LAST_MODIFIED,
+    header::VARY,
+];
+
+/// Whether `req` is a `GET` or `HEAD` whose validators show the client already
+/// has the representation described by `headers`.
+pub fn is_fresh(req: &HttpRequest, headers: &HeaderMap) -> bool {
+    if req.method() != Method::GET && req.method() != Method::HEAD {
+        return false;
+    }
+    not_modified(req.headers(), headers.get(header::ETAG), headers.get(header::LAST_MODIFIED))
+}
+
+/// Replaces a `200` the client already has with a bodiless `304`.
+pub fn apply<T: Default>(req: &HttpRequest, response: Response<T>) -> Response<T> {
+    if response.status() != StatusCode::OK || !is_fresh(req, response.headers()) {
+        return response;
+    }
+    not_modified_response(response.headers())
+}
+
+/// The `304` standing for a `200` with `headers`.
+pub(crate) fn not_modified_response<T: Default>(headers: &HeaderMap) -> Response<T> {
+    let mut response = Response::new(T::default());
+    *response.status_mut() = StatusCode::NOT_MODIFIED;
+    for name in KEPT {
+        for value in headers.get_all(&name) {
+            response.headers_mut().append(&name, value.clone());
+        }
+    }
+    response
+}
+
+/// `If-None-Match` decides when present, with the weak comparison; otherwise
+/// `If-Modified-Since` does, to the second.
+pub(crate) fn not_modified(
+    request: &HeaderMap,
+    etag: Option<&HeaderValue>,
+    last_modified: Option<&HeaderValue>,
+) -> bool {
+    if let Some(if_none_match) = request.get(header::IF_NONE_MATCH) {
+        let Some(etag) = etag else {
+            return false;
+        };
+        let etag = weak_opaque(etag.as_bytes());
+        return if_none_match
+            .as_bytes()
+            .split(|&b| b == b',')
+            .any(|tag| tag.trim_ascii() == b"*" || weak_opaque(tag) == etag);
+    }
+    let since = request.get(header::IF_MODIFIED_SINCE).and_then(http_date);
+    let modified = last_modified.and_then(http_date);
+    matches!((since, modified), (Some(since), Some(modified)) if modified <= since)
+}
+
+fn http_date(value: &HeaderValue) -> Option<std::time::SystemTime> {
+    date::parse_http(value.to_str().ok()?)
+}
+
+/// The weak comparison function ignores the `W/` prefix.
+fn weak_opaque(tag: &[u8]) -> &[u8] {
+    let tag = tag.trim_ascii();
+    tag.strip_prefix(b"W/").unwrap_or(tag)
+}
@@ -0,0 +1,138 @@
+use std::cell::RefCell;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+
+const DAYS: [&str; 7] = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"];
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+thread_local! {
+    /// The current second and its formatted date, so that it is formatted at most
+    /// once a second per thread.
+    static NOW: RefCell<(u64, String)> = const { RefCell::new((u64::MAX, String::new())) };
+}
+
+/// Calls `f` with the current time as an HTTP date, for the `Date` header.
+pub(crate) fn now(f: impl FnOnce(&str)) {
+    let secs = unix_secs(SystemTime::now());
+    NOW.with_borrow_mut(|(cached, formatted)| {
+        if *cached != secs {
+            *cached = secs;
+            *formatted = format_secs(secs);
+        }
+        f(formatted);
+    })
+}
+
+/// IMF-fixdate, for headers like `Last-Modified`.
+pub(crate) fn format_http(time: SystemTime) -> String {
+    format_secs(unix_secs(time))
+}
+
+/// Common Log Format timestamp, in UTC: `10/Oct/2000:13:55:36 +0000`.
+pub(crate) fn format_clf(time: SystemTime) -> String {
+    let secs = unix_secs(time);
+    let (year, month, day) = civil_from_days((secs / 86400) as i64);
+    let rem = secs % 86400;
+    format!(
+        "{:02}/{}/{}:{:02}:{:02}:{:02} +0000",
+        day,
+        MONTHS[month as usize - 1],
+        year,
+        rem / 3600,
+        rem / 60 % 60,
+        rem % 60,
+    )
+}
+
+/// RFC 3339 timestamp, in UTC: `2000-10-10T13:55:36Z`.
+pub(crate) fn format_rfc3339(time: SystemTime) -> String {
+    let secs = unix_secs(time);
+    let (year, month, day) = civil_from_days((secs / 86400) as i64);
+    let rem = secs % 86400;
+    format!(
+        "{}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        rem / 3600,
+        rem / 60 % 60,
+        rem % 60,
+    )
+}
+
+/// Parses an IMF-fixdate, the only format senders may use; the obsolete ones
+/// are not worth the trouble for what only ever feeds a cache check.
+pub(crate) fn parse_http(s: &str) -> Option<SystemTime> {
+    // Sun, 06 Nov 1994 08:49:37 GMT
+    let mut parts = s.trim().split(' ');
+    let (_weekday, day, month, year, time, zone) = (
+        parts.next()?,
+        parts.next()?,
+        parts.next()?,
+        parts.next()?,
+        parts.next()?,
+        parts.next()?,
+    );
+    if parts.next().is_some() || zone != "GMT" || day.len() != 2 || year.len() != 4 {
+        return None;
+    }
+    let day: u32 = day.parse().ok()?;
+    let month = MONTHS.iter().position(|m| *m == month)? as u32 + 1;
+    let year: i64 = year.parse().ok()?;
+    let mut hms = time.split(':').map(|n| n.parse::<u64>().ok().filter(|_| n.len() == 2));
+    let (h, m, sec) = (hms.next()??, hms.next()??, hms.next()??);
+    if hms.next().is_some() || day == 0 || day > 31 || h > 23 || m > 59 || sec > 60 {
+        return None;
+    }
+    let days = u64::try_from(days_from_civil(year, month, day)).ok()?;
+    Some(UNIX_EPOCH + std::time::Duration::from_secs(days * 86400 + h * 3600 + m * 60 + sec))
+}
+
+fn unix_secs(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs())
+}
+
+/// IMF-fixdate, e.g. `Sun, 06 Nov 1994 08:49:37 GMT`.
+fn format_secs(secs: u64) -> String {
+    let days = secs / 86400;
+    let (year, month, day) = civil_from_days(days as i64);
+    let rem = secs % 86400;
+    format!(
+        "{}, {:02} {} {} {:02}:{:02}:{:02} GMT",
+        DAYS[(days % 7) as usize],
+        day,
+        MONTHS[month as usize - 1],
+        year,
+        rem / 3600,
+        rem / 60 % 60,
+        rem % 60,
+    )
+}
+
+/// Year, month and day of the `days`th day after 1970-01-01, after Howard
+/// Hinnant's `civil_from_days`.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+/// The inverse of `civil_from_days`.
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = year - i64::from(month <= 2);
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let mp = i64::from((month + 9) % 12);
+    let doy = (153 * mp + 2) / 5 + i64::from(day) - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
@@ -0,0 +1,89 @@
+//! Decoding request bodies sent with a `Content-Encoding`, for
+//! `Server::set_request_decompression`.
+
+use std::io;
+use std::io::Read;
+
+use bytes::BufMut;
+use bytes::BytesMut;
+
+use crate::StatusCode;
+
+/// Why a body could not be decoded, and what to answer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Failure {
+    Unsupported,
+    TooLarge,
+    Malformed,
+}
+
+impl Failure {
+    pub(crate) fn status(self) -> StatusCode {
+        match self {
+            Failure::Unsupported => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            Failure::TooLarge => StatusCode::PAYLOAD_TOO_LARGE,
+            Failure::Malformed => StatusCode::BAD_REQUEST,
+        }
+    }
+}
+
+/// Undoes the codings listed in a `Content-Encoding`, last applied first,
+/// giving up once the result would exceed `limit` bytes.
+pub(crate) fn decode(codings: &[u8], body: &[u8], limit: usize) -> Result<BytesMut, Failure> {
+    let mut decoded = BytesMut::from(body);
+    for coding in codings.rsplit(|&b| b == b',') {
+        let coding = coding.trim_ascii().to_ascii_lowercase();
+        let input = decoded.split().freeze();
+        let reader: Box<dyn Read + '_> = match coding.as_slice() {
+            b"identity" | b"" => {
+                decoded.extend_from_slice(&input);
+                continue;
+            }
+            #[cfg(feature = "compression-gzip")]
+            b"gzip" | b"x-gzip" => Box::new(flate2::read::MultiGzDecoder::new(&input[..])),
+            // HTTP's "deflate" is the zlib format, not raw deflate.
+            #[cfg(feature = "compression-gzip")]
+            b"deflate" => Box::new(flate2::read::ZlibDecoder::new(&input[..])),
+            #[cfg(feature = "compression-br")]
+            b"br" => Box::new(brotli::Decompressor::new(&input[..], 4096)),
+            #[cfg(feature = "compression-zstd")]
+            b"zstd" => Box::new(zstd::stream::read::Decoder::new(&input[..]).map_err(|_| Failure::Malformed)?),
+            _ => return Err(Failure::Unsupported),
+        };
+        let mut writer = BytesMut::new().writer();
+        // One byte past the limit tells a body that fits from one that doesn't.
+        let copied = io::copy(&mut reader.take((limit as u64).saturating_add(1)), &mut writer);
+        match copied {
+            Ok(n) if n > limit as u64 => return Err(Failure::TooLarge),
+            Ok(_) => decoded = writer.into_inner(),
+            Err(_) => return Err(Failure::Malformed),
+        }
+    }
+    Ok(decoded)
+}
+
+#[cfg(all(test, feature = "compression-gzip"))]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+
+    fn gzip(data: &[u8]) -> Vec<u8> {
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[test]
+    fn bodies_within_the_limit_are_decoded() {
+        let body = gzip(b"hello");
+        assert_eq!(decode(b"gzip", &body, 5).as_deref(), Ok(&b"hello"[..]));
+        assert_eq!(decode(b"gzip", &body, usize::MAX).as_deref(), Ok(&b"hello"[..]));
+    }
+
+    #[test]
+    fn bodies_over_the_limit_are_too_large() {
+        let body = gzip(&[b'a'; 1024]);
+        assert_eq!(decode(b"gzip", &body, 1023).map(drop), Err(Failure::TooLarge));
+    }
+}
@@ -0,0 +1,42 @@
+//! Internal diagnostics: sent to `tracing` or the `log` crate when either feature
+//! is enabled (`tracing` if both are), and compiled out otherwise.
+
+#[cfg(feature = "tracing")]
+macro_rules! debug {
+    ($($arg:tt)*) => { tracing::debug!(target: "blocking_http_server", $($arg)*) };
+}
+
+#[cfg(feature = "tracing")]
+macro_rules! warn {
+    ($($arg:tt)*) => { tracing::warn!(target: "blocking_http_server", $($arg)*) };
+}
+
+#[cfg(all(feature = "log", not(feature = "tracing")))]
+macro_rules! debug {
+    ($($arg:tt)*) => { log::debug!(target: "blocking_http_server", $($arg)*) };
+}
+
+#[cfg(all(feature = "log", not(feature = "tracing")))]
+macro_rules! warn {
+    ($($arg:tt)*) => { log::warn!(target: "blocking_http_server", $($arg)*) };
+}
+
+// Still type-checks the arguments, so that builds without either feature don't
+// warn about values only used for logging.
+#[cfg(not(any(feature = "log", feature = "tracing")))]
+macro_rules! debug {
+    ($($arg:tt)*) => {
+        if false {
+            let _ = format_args!($($arg)*);
+        }
+    };
+}
+
+#[cfg(not(any(feature = "log", feature = "tracing")))]
+macro_rules! warn {
+    ($($arg:tt)*) => {
+        if false {
+            let _ = format_args!($($arg)*);
+        }
+    };
+}
@@ -0,0 +1,49 @@
+use std::fmt;
+use std::io;
+
+/// Why `Server::recv` and friends returned without a request.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum RecvError {
+    /// The server was shut down (or never had a listener); no more requests will come.
+    Shutdown,
+    /// The deadline passed before a request arrived.
+    TimedOut,
+    /// Accepting the connection or reading the request failed.
+    Io(io::Error),
+}
+
+impl fmt::Display for RecvError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Shutdown => f.write_str("server is shut down"),
+            Self::TimedOut => f.write_str("no request before the deadline"),
+            Self::Io(e) => e.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for RecvError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for RecvError {
+    fn from(e: io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+impl From<RecvError> for io::Error {
+    fn from(e: RecvError) -> Self {
+        match e {
+            RecvError::Shutdown => io::Error::new(io::ErrorKind::NotConnected, e.to_string()),
+            RecvError::TimedOut => io::Error::new(io::ErrorKind::TimedOut, e.to_string()),
+            RecvError::Io(e) => e,
+        }
+    }
+}
@@ -0,0 +1,568 @@
+//! Serving the files under a directory.
+//!
+//! ```no_run
+//! use blocking_http_server::files::ServeDir;
+//! use blocking_http_server::*;
+//!
+//! let assets = ServeDir::new("./public")?;
+//! let router = Router::new().get("/static/*", move |req| assets.serve(req));
+//! # Ok::<(), std::io::Error>(())
+//! ```
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io;
+use std::io::Read;
+use std::io::Seek;
+use std::io::SeekFrom;
+use std::ops::Range;
+use std::path::Component;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+
+use crate::accept;
+use crate::conditional;
+use crate::date;
+use crate::header;
+use crate::html;
+use crate::html::el;
+use crate::mime;
+use crate::range;
+use crate::range::Ranged;
+use crate::router::Params;
+use crate::HeaderMap;
+use crate::HeaderValue;
+use crate::HttpRequest;
+use crate::Method;
+use crate::Response;
+use crate::StatusCode;
+
+/// A directory whose files are served as they are on disk, `index.html` standing
+/// in for directories. Answers `GET` and `HEAD`, including byte ranges and
+/// `304 Not Modified` for clients whose copy is current.
+///
+/// Mounted on a route ending in `*`, the rest of the path names the file;
+/// otherwise the whole request path does; see `resolve` for how it is kept
+/// inside the root. Files get a weak `ETag` unless `with_etag` says otherwise.
+#[derive(Debug, Clone)]
+pub struct ServeDir {
+    root: PathBuf,
+    etag: ETagStrategy,
+    listing: bool,
+    symlink_escapes: bool,
+    precompressed: bool,
+    buffer_limit: usize,
+    /// `Content-Type`s by lowercase extension, ahead of `mime::guess`.
+    mime_types: HashMap<String, HeaderValue>,
+    /// Strong tags by file, with the modification time they were computed for.
+    hashes: Arc<Mutex<HashMap<PathBuf, (SystemTime, HeaderValue)>>>,
+}
+
+/// A response, and the part of a file its body is still to be read from.
+type Prepared = (Response<Vec<u8>>, Option<(File, Range<u64>)>);
+
+/// How `ServeDir` computes the `ETag` of a file.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ETagStrategy {
+    /// No `ETag`; `Last-Modified` alone validates.
+    None,
+    /// A weak tag from the modification time and size: free, but a file rewritten
+    /// with the same content gets a new tag.
+    #[default]
+    Weak,
+    /// A strong tag from a hash of the content, remembered until the file's
+    /// modification time changes. The first request for each version of a file
+    /// reads it whole.
+    Strong,
+}
+
+impl ServeDir {
+    pub fn new(root: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(Self {
+            root: root.as_ref().canonicalize()?,
+            etag: ETagStrategy::default(),
+            listing: false,
+            symlink_escapes: false,
+            precompressed: false,
+            buffer_limit: 8 * 1024 * 1024,
+            mime_types: HashMap::new(),
+            hashes: Arc::default(),
+        })
+    }
+
+    pub fn with_etag(mut self, etag: ETagStrategy) -> Self {
+        self.etag = etag;
+        self
+    }
+
+    /// Lists directories that have no `index.html`, rather than answering `404`:
+    /// names, sizes and modification times, directories first. Names starting
+    /// with a dot are left out, as are symlinks that would be refused.
+    pub fn with_listing(mut self, listing: bool) -> Self {
+        self.listing = listing;
+        self
+    }
+
+    /// Lets symlinks under the root point outside of it, e.g. into a shared media
+    /// directory. Off by default: such links are answered with `403 Forbidden`.
+    pub fn with_symlink_escapes(mut self, allow: bool) -> Self {
+        self.symlink_escapes = allow;
+        self
+    }
+
+    /// Serves `file.br` or `file.gz` in place of `file` when they exist and the
+    /// client accepts that encoding, so assets can be compressed once, at build
+    /// time. Responses then carry `Vary: Accept-Encoding`.
+    pub fn with_precompressed(mut self, precompressed: bool) -> Self {
+        self.precompressed = precompressed;
+        self
+    }
+
+    /// Serves files ending in `.extension` as `content_type`, whatever
+    /// `mime::guess` would say. Panics if `content_type` is not a valid header
+    /// value.
+    #[track_caller]
+    pub fn with_mime_type(mut self, extension: &str, content_type: &str) -> Self {
+        let content_type = HeaderValue::from_str(content_type).expect("invalid content type");
+        self.mime_types.insert(extension.to_ascii_lowercase(), content_type);
+        self
+    }
+
+    /// Larger bodies are not read into memory by `serve`, which answers
+    /// `500 Internal Server Error` for them instead; `send` streams any size. 8 MiB
+    /// by default.
+    pub fn with_buffer_limit(mut self, bytes: usize) -> Self {
+        self.buffer_limit = bytes;
+        self
+    }
+
+    /// The response to `req`, file contents read into memory: fit for a `Router`
+    /// handler, but only for files within `with_buffer_limit`.
+    pub fn serve(&self, req: &HttpRequest) -> Response<Vec<u8>> {
+        let (mut response, body) = self.prepare(req);
+        if let Some((mut file, range)) = body {
+            let len = range.end - range.start;
+            if len > self.buffer_limit as u64 {
+                warn!("{len} bytes are too many to buffer; serve them with ServeDir::send");
+                return status_response(StatusCode::INTERNAL_SERVER_ERROR);
+            }
+            let mut body = Vec::with_capacity(len as usize);
+            let read = file
+                .seek(SeekFrom::Start(range.start))
+                .and_then(|_| file.take(len).read_to_end(&mut body));
+            if let Err(e) = read {
+                return error_response(e);
+            }
+            *response.body_mut() = body;
+        }
+        response
+    }
+
+    /// Answers `req` like `serve` would, but streams file contents from disk rather
+    /// than reading them into memory first.
+    ///
+    /// ```no_run
+    /// use blocking_http_server::files::ServeDir;
+    /// use blocking_http_server::*;
+    ///
+    /// let videos = ServeDir::new("./videos")?;
+    /// let mut server = Server::bind("127.0.0.1:8080")?;
+    /// for req in server.incoming().flatten() {
+    ///     let _ = videos.send(&req);
+    /// }
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    pub fn send(&self, req: &HttpRequest) -> io::Result<()> {
+        let (response, body) = self.prepare(req);
+        let Some((mut file, range)) = body else {
+            return req.respond(response);
+        };
+        let len = range.end - range.start;
+        let (parts, _) = response.into_parts();
+        let mut head = Response::from_parts(parts, ());
+        head.headers_mut().insert(header::CONTENT_LENGTH, len.into());
+        file.seek(SeekFrom::Start(range.start))?;
+        let mut writer = req.respond_chunked(head)?;
+        io::copy(&mut file.take(len), &mut writer)?;
+        writer.finish(&HeaderMap::new())
+    }
+
+    fn prepare(&self, req: &HttpRequest) -> Prepared {
+        if req.method() != Method::GET && req.method() != Method::HEAD {
+            let mut response = status_response(StatusCode::METHOD_NOT_ALLOWED);
+            response
+                .headers_mut()
+                .insert(header::ALLOW, HeaderValue::from_static("GET, HEAD"));
+            return (response, None);
+        }
+        let path = match req.extensions().get::<Params>().and_then(|params| params.get("*")) {
+            Some(rest) => rest.to_owned(),
+            None => req.uri().path().to_owned(),
+        };
+        let mut file = match self.resolve(&path) {
+            Ok(file) => file,
+            Err(e) => return (error_response(e), None),
+        };
+        if file.is_dir() {
+            if !req.uri().path().ends_with('/') {
+                // Relative links in the page would resolve against the parent.
+                return (add_slash(req), None);
+            }
+            let index = file.join("index.html");
+            if self.listing && !index.is_file() {
+                return (self.listing(&file).unwrap_or_else(error_response), None);
+            }
+            file = match self.jail(index) {
+                Ok(file) => file,
+                Err(e) => return (error_response(e), None),
+            };
+        }
+        self.serve_file(req, &file).unwrap_or_else(|e| (error_response(e), None))
+    }
+
+    /// The file under the root that a request for `path` would be served from.
+    ///
+    /// `path` is percent-decoded first, then must consist of plain names only:
+    /// `..`, encoded or not, and anything else that could climb out of the root
+    /// fails with `NotFound`. Symlinks are followed, but one leading outside the
+    /// root fails with `PermissionDenied` unless `with_symlink_escapes` allows it.
+    ///
+    /// ```
+    /// use blocking_http_server::files::ServeDir;
+    /// use std::io::ErrorKind;
+    ///
+    /// let root = std::env::temp_dir().join(format!("serve-dir-resolve-{}", std::process::id()));
+    /// # let _ = std::fs::remove_dir_all(&root);
+    /// std::fs::create_dir_all(root.join("public/docs"))?;
+    /// std::fs::write(root.join("secret"), "")?;
+    /// std::fs::write(root.join("public/docs/a.txt"), "")?;
+    ///
+    /// let dir = ServeDir::new(root.join("public"))?;
+    /// assert!(dir.resolve("/docs/a.txt").is_ok());
+    /// assert!(dir.resolve("/docs/%61.txt").is_ok());
+    /// for path in [
+    ///     "/../secret",
+    ///     "/docs/../../secret",
+    ///     "/%2e%2e/secret",
+    ///     "/%2E%2E%2fsecret",
+    ///     "/docs/..%2F..%2Fsecret",
+    ///     "/%252e%252e/secret",
+    ///     "/..%5csecret",
+    ///     "//secret",
+    ///     "/docs/a.txt%00.png",
+    ///     "/%ff",
+    /// ] {
+    ///     assert_eq!(dir.resolve(path).unwrap_err().kind(), ErrorKind::NotFound, "{path}");
+    /// }
+    ///
+    /// #[cfg(unix)]
+    /// {
+    ///     std::os::unix::fs::symlink(root.join("secret"), root.join("public/link"))?;
+    ///     assert_eq!(dir.resolve("/link").unwrap_err().kind(), ErrorKind::PermissionDenied);
+    ///     assert!(dir.with_symlink_escapes(true).resolve("/link").is_ok());
+    /// }
+    /// # std::fs::remove_dir_all(&root)?;
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    pub fn resolve(&self, path: &str) -> io::Result<PathBuf> {
+        let path = percent_decode(path).filter(|path| !path.contains('\0'));
+        let Some(path) = path else {
+            return Err(io::ErrorKind::NotFound.into());
+        };
+        let mut file = self.root.clone();
+        for component in Path::new(path.trim_start_matches('/')).components() {
+            match component {
+                Component::Normal(name) => file.push(name),
+                _ => return Err(io::ErrorKind::NotFound.into()),
+            }
+        }
+        self.jail(file)
+    }
+
+    /// `file`, unless symlinks lead it out of the root. Canonicalizing needs the
+    /// file to exist, so a missing one is `NotFound` here already.
+    fn jail(&self, file: PathBuf) -> io::Result<PathBuf> {
+        let real = file.canonicalize()?;
+        if !self.symlink_escapes && !real.starts_with(&self.root) {
+            return Err(io::ErrorKind::PermissionDenied.into());
+        }
+        Ok(file)
+    }
+
+    fn serve_file(&self, req: &HttpRequest, path: &Path) -> io::Result<Prepared> {
+        let (variant, encoding) = if self.precompressed {
+            self.precompressed_variant(req, path)
+        } else {
+            (None, None)
+        };
+        let served = variant.as_deref().unwrap_or(path);
+        let mut file = File::open(served)?;
+        let metadata = file.metadata()?;
+        if !metadata.is_file() {
+            return Err(io::ErrorKind::NotFound.into());
+        }
+        let len = metadata.len();
+
+        let modified = metadata.modified().ok();
+
+        let mut response = Response::new(Vec::new());
+        if let Some(etag) = self.etag(served, &mut file, len, modified, encoding)? {
+            response.headers_mut().insert(header::ETAG, etag);
+        }
+        let headers = response.headers_mut();
+        headers.insert(header::CONTENT_TYPE, self.content_type(path));
+        if let Some(encoding) = encoding {
+            headers.insert(header::CONTENT_ENCODING, HeaderValue::from_static(encoding));
+        }
+        if self.precompressed {
+            headers.insert(header::VARY, HeaderValue::from_static("accept-encoding"));
+        }
+        if let Some(modified) = modified {
+            if let Ok(modified) = HeaderValue::try_from(date::format_http(modified)) {
+                headers.insert(header::LAST_MODIFIED, modified);
+            }
+        }
+        headers.insert(header::ACCEPT_RANGES, HeaderValue::from_static("bytes"));
+
+        if conditional::is_fresh(req, response.headers()) {
+            return Ok((conditional::not_modified_response(response.headers()), None));
+        }
+        let range = match range::evaluate(req, len, response.headers()) {
+            Ranged::Full => 0..len,
+            Ranged::Partial(range) => {
+                range::set_partial(&mut response, &range, len);
+                range
+            }
+            Ranged::Unsatisfiable => {
+                range::set_unsatisfiable(&mut response, len);
+                return Ok((response, None));
+            }
+        };
+
+        if req.method() == Method::HEAD {
+            // Nothing to read; the length is all the response needs.
+            response
+                .headers_mut()
+                .insert(header::CONTENT_LENGTH, (range.end - range.start).into());
+            return Ok((response, None));
+        }
+        Ok((response, Some((file, range))))
+    }
+
+    /// The compressed sibling of `path` the client likes best, with its
+    /// `Content-Encoding`; `br` wins ties.
+    fn precompressed_variant(
+        &self,
+        req: &HttpRequest,
+        path: &Path,
+    ) -> (Option<PathBuf>, Option<&'static str>) {
+        let mut best: Option<(f32, PathBuf, &'static str)> = None;
+        for (suffix, encoding) in [("br", "br"), ("gz", "gzip")] {
+            let q = accept::quality(req.headers(), &header::ACCEPT_ENCODING, encoding).unwrap_or(0.0);
+            if q <= 0.0 || best.as_ref().is_some_and(|(best, _, _)| *best >= q) {
+                continue;
+            }
+            let mut variant = path.as_os_str().to_owned();
+            variant.push(".");
+            variant.push(suffix);
+            match self.jail(variant.into()) {
+                Ok(variant) if variant.is_file() => best = Some((q, variant, encoding)),
+                _ => {}
+            }
+        }
+        match best {
+            Some((_, variant, encoding)) => (Some(variant), Some(encoding)),
+            None => (None, None),
+        }
+    }
+
+    fn listing(&self, dir: &Path) -> io::Result<Response<Vec<u8>>> {
+        let mut entries = Vec::new();
+        for entry in std::fs::read_dir(dir)? {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if name.starts_with('.') {
+                continue;
+            }
+            // Follows symlinks, like serving does; dangling ones are left out, and so
+            // are those leading out of the root unless it may be left.
+            let Ok(path) = self.jail(entry.path()) else {
+                continue;
+            };
+            let Ok(metadata) = std::fs::metadata(path) else {
+                continue;
+            };
+            entries.push((!metadata.is_dir(), name, metadata));
+        }
+        entries.sort_by(|a, b| (a.0, &a.1).cmp(&(b.0, &b.1)));
+
+        let title = match dir.strip_prefix(&self.root) {
+            Ok(relative) if relative.as_os_str().is_empty() => "/".to_owned(),
+            Ok(relative) => format!("/{}/", relative.to_string_lossy()),
+            Err(_) => "/".to_owned(),
+        };
+        let cell = |s: String| el("td").text(s);
+        let mut rows = vec![el("tr").children(["Name", "Size", "Modified"].map(|h| el("th").text(h)))];
+        if dir != self.root {
+            rows.push(el("tr").child(el("td").child(el("a").attr("href", "../").text("../"))));
+        }
+        for (is_file, name, metadata) in entries {
+            let (href, name, size) = if is_file {
+                (percent_encode(&name), name, metadata.len().to_string())
+            } else {
+                (percent_encode(&name) + "/", name + "/", "-".to_owned())
+            };
+            let modified = metadata.modified().map_or_else(|_| "-".to_owned(), date::format_http);
+            rows.push(el("tr").children([
+                el("td").child(el("a").attr("href", href).text(name)),
+                cell(size),
+                cell(modified),
+            ]));
+        }
+        let page = html::page(
+            &format!("Index of {title}"),
+            [el("h1").text(format!("Index of {title}")), el("table").children(rows)],
+        );
+
+        let mut response = Response::new(page.into_bytes());
+        response
+            .headers_mut()
+            .insert(header::CONTENT_TYPE, HeaderValue::from_static("text/html; charset=utf-8"));
+        Ok(response)
+    }
+
+    fn content_type(&self, path: &Path) -> HeaderValue {
+        let extension = path.extension().and_then(|ext| ext.to_str()).map(str::to_ascii_lowercase);
+        match extension.and_then(|ext| self.mime_types.get(&ext)) {
+            Some(content_type) => content_type.clone(),
+            None => HeaderValue::from_static(mime::guess(path)),
+        }
+    }
+
+    fn etag(
+        &self,
+        path: &Path,
+        file: &mut File,
+        len: u64,
+        modified: Option<SystemTime>,
+        encoding: Option<&str>,
+    ) -> io::Result<Option<HeaderValue>> {
+        let etag = match (self.etag, modified) {
+            (ETagStrategy::None, _) | (ETagStrategy::Weak, None) => return Ok(None),
+            (ETagStrategy::Weak, Some(modified)) => {
+                let nanos = modified.duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos();
+                // Compressing tools tend to keep the original's modification time.
+                match encoding {
+                    Some(encoding) => format!("W/\"{nanos:x}-{len:x}-{encoding}\""),
+                    None => format!("W/\"{nanos:x}-{len:x}\""),
+                }
+            }
+            (ETagStrategy::Strong, _) => {
+                let cached = modified.and_then(|modified| match self.hashes.lock().unwrap().get(path) {
+                    Some((at, etag)) if *at == modified => Some(etag.clone()),
+                    _ => None,
+                });
+                if let Some(etag) = cached {
+                    return Ok(Some(etag));
+                }
+                format!("\"{:016x}-{len:x}\"", content_hash(file)?)
+            }
+        };
+        let Ok(etag) = HeaderValue::try_from(etag) else {
+            return Ok(None);
+        };
+        if let (ETagStrategy::Strong, Some(modified)) = (self.etag, modified) {
+            self.hashes
+                .lock()
+                .unwrap()
+                .insert(path.to_owned(), (modified, etag.clone()));
+        }
+        Ok(Some(etag))
+    }
+}
+
+/// FNV-1a over the whole file, leaving it at its start. Not cryptographic, but an
+/// entity tag only has to tell versions of one file apart.
+fn content_hash(file: &mut File) -> io::Result<u64> {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    let mut buf = [0; 8192];
+    loop {
+        let n = match file.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        for &b in &buf[..n] {
+            hash = (hash ^ u64::from(b)).wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+    file.rewind()?;
+    Ok(hash)
+}
+
+fn error_response(e: io::Error) -> Response<Vec<u8>> {
+    match e.kind() {
+        io::ErrorKind::NotFound => status_response(StatusCode::NOT_FOUND),
+        io::ErrorKind::PermissionDenied => status_response(StatusCode::FORBIDDEN),
+        _ => status_response(StatusCode::INTERNAL_SERVER_ERROR),
+    }
+}
+
+/// Sends a directory requested without its trailing slash to the path with one.
+fn add_slash(req: &HttpRequest) -> Response<Vec<u8>> {
+    let location = match req.uri().query() {
+        Some(query) => format!("{}/?{query}", req.uri().path()),
+        None => format!("{}/", req.uri().path()),
+    };
+    let mut response = Response::new(Vec::new());
+    *response.status_mut() = StatusCode::MOVED_PERMANENTLY;
+    if let Ok(location) = HeaderValue::try_from(location) {
+        response.headers_mut().insert(header::LOCATION, location);
+    }
+    response
+}
+
+fn status_response(status: StatusCode) -> Response<Vec<u8>> {
+    let mut response = Response::new(status.to_string().into_bytes());
+    *response.status_mut() = status;
+    response
+}
+
+/// Percent-encodes a file name for use as a relative link.
+fn percent_encode(name: &str) -> String {
+    let mut out = String::with_capacity(name.len());
+    for &b in name.as_bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => out.push(b as char),
+            b => out.push_str(&format!("%{b:02X}")),
+        }
+    }
+    out
+}
+
+/// Percent-decodes a path; unlike a query, `+` stays as is. `None` if it is not
+/// UTF-8 once decoded.
+fn percent_decode(path: &str) -> Option<String> {
+    let bytes = path.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = |b: u8| (b as char).to_digit(16);
+        match (bytes[i], bytes.get(i + 1).copied().and_then(hex), bytes.get(i + 2).copied().and_then(hex)) {
+            (b'%', Some(hi), Some(lo)) => {
+                out.push((hi << 4 | lo) as u8);
+                i += 3;
+            }
+            (b, _, _) => {
+                out.push(b);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8(out).ok()
+}
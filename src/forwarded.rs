@@ -0,0 +1,132 @@
+use std::fmt;
+use std::io;
+use std::net::IpAddr;
+use std::str::FromStr;
+
+use crate::header;
+use crate::HeaderMap;
+
+/// A block of IP addresses, e.g. `10.0.0.0/8` or `2001:db8::/32`. A bare address
+/// is a block of one.
+///
+/// ```
+/// use blocking_http_server::IpCidr;
+///
+/// let private: IpCidr = "10.0.0.0/8".parse()?;
+/// assert!(private.contains("10.1.2.3".parse().unwrap()));
+/// assert!(!private.contains("192.0.2.1".parse().unwrap()));
+/// # Ok::<(), std::io::Error>(())
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct IpCidr {
+    addr: IpAddr,
+    prefix: u8,
+}
+
+impl IpCidr {
+    pub fn new(addr: IpAddr, prefix: u8) -> io::Result<Self> {
+        let bits = if addr.is_ipv4() { 32 } else { 128 };
+        if prefix > bits {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "prefix longer than the address"));
+        }
+        Ok(Self { addr, prefix })
+    }
+
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        // IPv4 clients of a dual-stack socket show up as `::ffff:a.b.c.d`.
+        let ip = match ip {
+            IpAddr::V6(v6) => v6.to_ipv4_mapped().map_or(ip, IpAddr::V4),
+            v4 => v4,
+        };
+        match (self.addr, ip) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - u32::from(self.prefix)).unwrap_or(0);
+                u32::from(net) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - u32::from(self.prefix)).unwrap_or(0);
+                u128::from(net) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+impl FromStr for IpCidr {
+    type Err = io::Error;
+
+    fn from_str(s: &str) -> io::Result<Self> {
+        let invalid = || io::Error::new(io::ErrorKind::InvalidInput, format!("invalid CIDR `{s}`"));
+        let (addr, prefix) = match s.split_once('/') {
+            Some((addr, prefix)) => (addr, Some(prefix.parse().map_err(|_| invalid())?)),
+            None => (s, None),
+        };
+        let addr: IpAddr = addr.parse().map_err(|_| invalid())?;
+        let prefix = prefix.unwrap_or(if addr.is_ipv4() { 32 } else { 128 });
+        Self::new(addr, prefix).map_err(|_| invalid())
+    }
+}
+
+impl fmt::Display for IpCidr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.addr, self.prefix)
+    }
+}
+
+/// The client behind `peer`, according to the forwarding headers added by the
+/// trusted proxies in front of it. The chain is walked from the nearest hop
+/// back, stopping at the first address that is not a trusted proxy. `peer` is
+/// `None` for connections over a Unix socket, which only a local proxy can make,
+/// so they are trusted as soon as any proxy is.
+pub(crate) fn client_ip(peer: Option<IpAddr>, headers: &HeaderMap, trusted: &[IpCidr]) -> Option<IpAddr> {
+    let is_trusted = |ip: IpAddr| trusted.iter().any(|cidr| cidr.contains(ip));
+    if trusted.is_empty() || peer.is_some_and(|ip| !is_trusted(ip)) {
+        return peer;
+    }
+    // `Forwarded` supersedes `X-Forwarded-For` when a proxy sends both.
+    let chain: Vec<&str> = if headers.contains_key(header::FORWARDED) {
+        headers
+            .get_all(header::FORWARDED)
+            .iter()
+            .filter_map(|v| v.to_str().ok())
+            .flat_map(|v| v.split(','))
+            .map(|element| {
+                let pairs = element.split(';').filter_map(|pair| pair.split_once('='));
+                let mut nodes = pairs.filter(|(key, _)| key.trim().eq_ignore_ascii_case("for"));
+                nodes.next().map_or("", |(_, node)| node)
+            })
+            .collect()
+    } else {
+        headers
+            .get_all("x-forwarded-for")
+            .iter()
+            .filter_map(|v| v.to_str().ok())
+            .flat_map(|v| v.split(','))
+            .collect()
+    };
+
+    let mut client = peer;
+    for node in chain.iter().rev() {
+        // `unknown` and obfuscated identifiers end the trail at the last known hop.
+        let Some(ip) = parse_node(node) else {
+            break;
+        };
+        client = Some(ip);
+        if !is_trusted(ip) {
+            break;
+        }
+    }
+    client
+}
+
+/// The address in a `Forwarded` node or `X-Forwarded-For` entry, port and
+/// quoting removed.
+fn parse_node(node: &str) -> Option<IpAddr> {
+    let node = node.trim().trim_matches('"');
+    if let Some(bracketed) = node.strip_prefix('[') {
+        return bracketed.split(']').next()?.parse().ok();
+    }
+    node.parse()
+        .ok()
+        .or_else(|| node.rsplit_once(':')?.0.parse().ok())
+}
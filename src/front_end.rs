@@ -0,0 +1,228 @@
+//! TLS, and HTTP/2 with the `h2` feature, served on a small tokio runtime in
+//! front of the blocking server. Each HTTP/1.1 connection or HTTP/2 stream they
+//! carry comes out of the `Gateway` as a plaintext HTTP/1.1 connection, which
+//! `Server` polls and accepts like any other listener.
+//!
+//! ```no_run
+//! # fn main() -> std::io::Result<()> {
+//! use blocking_http_server::*;
+//!
+//! let certs = vec![/* rustls::pki_types::CertificateDer */];
+//! let key = rustls::pki_types::PrivateKeyDer::Pkcs8(Vec::new().into());
+//! let config = rustls::ServerConfig::builder()
+//!     .with_no_client_auth()
+//!     .with_single_cert(certs, key)
+//!     .map_err(std::io::Error::other)?;
+//!
+//! let mut server = Server::bind("0.0.0.0:80")?;
+//! server.bind_tls("0.0.0.0:443", config)?;
+//! for req in server.incoming().flatten() {
+//!     let _ = req.respond(Response::new(b"hello".to_vec()));
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::VecDeque;
+use std::io;
+use std::io::Read;
+use std::io::Write;
+use std::net::SocketAddr;
+use std::net::TcpListener;
+use std::net::ToSocketAddrs;
+use std::os::fd::AsRawFd;
+use std::os::fd::RawFd;
+use std::os::unix::net::UnixStream;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use tokio::sync::oneshot;
+use tokio_rustls::TlsAcceptor;
+
+use crate::net::Listener;
+use crate::PeerAddr;
+use crate::Server;
+use crate::Stream;
+
+/// Protocols offered through ALPN when the config names none.
+#[cfg(feature = "h2")]
+const ALPN: &[&[u8]] = &[b"h2", b"http/1.1"];
+#[cfg(not(feature = "h2"))]
+const ALPN: &[&[u8]] = &[b"http/1.1"];
+
+/// Hands connections made by the front end to the server thread.
+#[derive(Debug)]
+pub(crate) struct Gateway {
+    queue: Mutex<VecDeque<(Stream, PeerAddr)>>,
+    /// Holds a byte while the queue is not empty, so that polling it works.
+    waker: (UnixStream, UnixStream),
+}
+
+impl Gateway {
+    fn new() -> io::Result<Self> {
+        let (tx, rx) = UnixStream::pair()?;
+        tx.set_nonblocking(true)?;
+        rx.set_nonblocking(true)?;
+        Ok(Self {
+            queue: Mutex::default(),
+            waker: (tx, rx),
+        })
+    }
+
+    /// Queues a new connection from `peer` for the server, returning the front
+    /// end's side of it.
+    pub(crate) fn connect(&self, peer: PeerAddr) -> io::Result<UnixStream> {
+        let (ours, theirs) = UnixStream::pair()?;
+        let mut queue = self.queue.lock().unwrap();
+        queue.push_back((Stream::Unix(theirs), peer));
+        if queue.len() == 1 {
+            let _ = (&self.waker.0).write(&[1]);
+        }
+        Ok(ours)
+    }
+
+    pub(crate) fn accept(&self) -> io::Result<(Stream, PeerAddr)> {
+        let mut queue = self.queue.lock().unwrap();
+        let accepted = queue.pop_front();
+        if queue.is_empty() {
+            let _ = (&self.waker.1).read(&mut [0]);
+        }
+        accepted.ok_or_else(|| io::ErrorKind::WouldBlock.into())
+    }
+}
+
+impl AsRawFd for Gateway {
+    fn as_raw_fd(&self) -> RawFd {
+        self.waker.1.as_raw_fd()
+    }
+}
+
+/// The runtime, on a thread of its own that stops when this is dropped.
+pub(crate) struct FrontEnd {
+    runtime: tokio::runtime::Handle,
+    gateway: Arc<Gateway>,
+    /// Listener indices with their TLS config.
+    acceptors: Vec<(usize, TlsAcceptor)>,
+    _stop: oneshot::Sender<()>,
+}
+
+impl FrontEnd {
+    fn start() -> io::Result<Self> {
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build()?;
+        let handle = runtime.handle().clone();
+        let (stop, stopped) = oneshot::channel::<()>();
+        std::thread::Builder::new()
+            .name("http-front-end".into())
+            .spawn(move || {
+                let _ = runtime.block_on(stopped);
+                runtime.shutdown_background();
+            })?;
+        Ok(Self {
+            runtime: handle,
+            gateway: Arc::new(Gateway::new()?),
+            acceptors: Vec::new(),
+            _stop: stop,
+        })
+    }
+
+    fn terminate_tls(&self, stream: std::net::TcpStream, peer: PeerAddr, acceptor: TlsAcceptor, timeout: Option<Duration>) {
+        let gateway = self.gateway.clone();
+        self.runtime.spawn(async move {
+            if let Err(e) = serve_tls(stream, &peer, acceptor, timeout, gateway).await {
+                debug!("{peer}: {e}");
+            }
+        });
+    }
+
+    /// Serves HTTP/2 without TLS on a connection the server has read `read` from;
+    /// see `http2::serve_cleartext`.
+    #[cfg(feature = "h2")]
+    pub(crate) fn serve_h2c(&self, stream: Stream, read: bytes::Bytes, upgraded: Option<Vec<u8>>, peer: PeerAddr) {
+        let gateway = self.gateway.clone();
+        self.runtime.spawn(async move {
+            if let Err(e) = crate::http2::serve_cleartext(stream, read, upgraded, peer.clone(), gateway).await {
+                debug!("{peer}: {e}");
+            }
+        });
+    }
+}
+
+async fn serve_tls(
+    stream: std::net::TcpStream,
+    peer: &PeerAddr,
+    acceptor: TlsAcceptor,
+    timeout: Option<Duration>,
+    gateway: Arc<Gateway>,
+) -> io::Result<()> {
+    stream.set_nonblocking(true)?;
+    let handshake = acceptor.accept(tokio::net::TcpStream::from_std(stream)?);
+    let mut tls = match timeout {
+        Some(timeout) => tokio::time::timeout(timeout, handshake).await??,
+        None => handshake.await?,
+    };
+    #[cfg(feature = "h2")]
+    if tls.get_ref().1.alpn_protocol() == Some(b"h2") {
+        crate::http2::serve(tls, peer.clone(), gateway).await;
+        return Ok(());
+    }
+    let plain = gateway.connect(peer.clone())?;
+    plain.set_nonblocking(true)?;
+    let mut plain = tokio::net::UnixStream::from_std(plain)?;
+    tokio::io::copy_bidirectional(&mut tls, &mut plain).await?;
+    Ok(())
+}
+
+impl Server {
+    /// Adds a listener on `addr` serving HTTPS with `config`, and returns the
+    /// bound address. Requests come out of `incoming()` like any other; the TLS
+    /// itself runs on a small tokio runtime in a thread of its own.
+    ///
+    /// ALPN offers `h2` and `http/1.1` with the `h2` feature, `http/1.1` only
+    /// without it, unless `config` names protocols itself. The handshake has
+    /// `header_read_timeout` to finish, or `read_timeout` if that is unset.
+    ///
+    /// The requests' streams are Unix sockets to the front end, and their
+    /// `peer_addr` the client's address. `hand_off` does not pass TLS listeners
+    /// on.
+    pub fn bind_tls(&mut self, addr: impl ToSocketAddrs, mut config: rustls::ServerConfig) -> io::Result<SocketAddr> {
+        if config.alpn_protocols.is_empty() {
+            config.alpn_protocols = ALPN.iter().map(|p| p.to_vec()).collect();
+        }
+        let listener = TcpListener::bind(addr)?;
+        let local_addr = listener.local_addr()?;
+        let index = self.listeners.len();
+        self.listeners.push(Listener::Tcp(listener));
+        self.front_end()?.acceptors.push((index, TlsAcceptor::from(Arc::new(config))));
+        Ok(local_addr)
+    }
+
+    /// Starts the front end on first use, adding its gateway to the listeners.
+    pub(crate) fn front_end(&mut self) -> io::Result<&mut FrontEnd> {
+        if self.front_end.is_none() {
+            let front_end = FrontEnd::start()?;
+            self.listeners.push(Listener::Gateway(front_end.gateway.clone()));
+            self.front_end = Some(front_end);
+        }
+        Ok(self.front_end.as_mut().unwrap())
+    }
+
+    pub(crate) fn terminates_tls(&self, listener: usize) -> bool {
+        self.front_end
+            .as_ref()
+            .is_some_and(|front_end| front_end.acceptors.iter().any(|(i, _)| *i == listener))
+    }
+
+    /// Hands a connection accepted on a `bind_tls` listener to the front end.
+    pub(crate) fn start_tls(&self, listener: usize, stream: Stream, peer: PeerAddr) {
+        let (Some(front_end), Stream::Tcp(stream)) = (&self.front_end, stream) else {
+            return;
+        };
+        let Some((_, acceptor)) = front_end.acceptors.iter().find(|(i, _)| *i == listener) else {
+            return;
+        };
+        let _ = stream.set_nodelay(self.nodelay);
+        let timeout = self.header_read_timeout.or(self.read_timeout);
+        front_end.terminate_tls(stream, peer, acceptor.clone(), timeout);
+    }
+}
@@ -0,0 +1,121 @@
+use std::fmt;
+use std::io;
+use std::io::Write;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::HttpRequest;
+use crate::PeerAddr;
+use crate::StatusCode;
+
+/// What happened on a connection, reported to `Server::on_connection_close`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ConnectionStats {
+    /// Time since the connection was accepted.
+    pub duration: Duration,
+    pub bytes_read: usize,
+    pub bytes_written: usize,
+}
+
+/// How a request was answered, reported to `Server::on_response`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ResponseInfo {
+    /// `None` if the request was dropped without a response.
+    pub status: Option<StatusCode>,
+    /// Bytes written for the response, head included.
+    pub bytes_written: usize,
+    /// Time from the request's header being read until it was dropped.
+    pub duration: Duration,
+}
+
+type AcceptHook = dyn Fn(&PeerAddr) + Send + Sync;
+type CloseHook = dyn Fn(&PeerAddr, &ConnectionStats) + Send + Sync;
+type ErrorHook = dyn Fn(&io::Error) + Send + Sync;
+type RequestHook = dyn Fn(&HttpRequest) + Send + Sync;
+type ResponseHook = dyn Fn(&HttpRequest, &ResponseInfo) + Send + Sync;
+
+#[derive(Clone, Default)]
+pub(crate) struct Hooks {
+    pub(crate) accept: Option<Arc<AcceptHook>>,
+    pub(crate) close: Option<Arc<CloseHook>>,
+    pub(crate) error: Option<Arc<ErrorHook>>,
+    pub(crate) request: Option<Arc<RequestHook>>,
+    pub(crate) response: Option<Arc<ResponseHook>>,
+}
+
+impl Hooks {
+    pub(crate) fn accept(&self, peer: &PeerAddr) {
+        if let Some(hook) = &self.accept {
+            hook(peer);
+        }
+    }
+
+    pub(crate) fn close(&self, peer: &PeerAddr, stats: &ConnectionStats) {
+        if let Some(hook) = &self.close {
+            hook(peer, stats);
+        }
+    }
+
+    pub(crate) fn error(&self, err: &io::Error) {
+        if let Some(hook) = &self.error {
+            hook(err);
+        }
+    }
+
+    pub(crate) fn request(&self, req: &HttpRequest) {
+        if let Some(hook) = &self.request {
+            hook(req);
+        }
+    }
+
+    pub(crate) fn response(&self, req: &HttpRequest, info: &ResponseInfo) {
+        if let Some(hook) = &self.response {
+            hook(req, info);
+        }
+    }
+}
+
+impl fmt::Debug for Hooks {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Hooks")
+            .field("accept", &self.accept.is_some())
+            .field("close", &self.close.is_some())
+            .field("error", &self.error.is_some())
+            .field("request", &self.request.is_some())
+            .field("response", &self.response.is_some())
+            .finish()
+    }
+}
+
+/// Counts the bytes that make it to the underlying writer.
+pub(crate) struct CountingWriter<'a, W> {
+    inner: W,
+    count: &'a AtomicUsize,
+}
+
+impl<'a, W> CountingWriter<'a, W> {
+    pub(crate) fn new(inner: W, count: &'a AtomicUsize) -> Self {
+        Self { inner, count }
+    }
+}
+
+impl<W: Write> Write for CountingWriter<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.count.fetch_add(n, Ordering::Relaxed);
+        Ok(n)
+    }
+
+    fn write_vectored(&mut self, bufs: &[io::IoSlice<'_>]) -> io::Result<usize> {
+        let n = self.inner.write_vectored(bufs)?;
+        self.count.fetch_add(n, Ordering::Relaxed);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
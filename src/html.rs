@@ -0,0 +1,162 @@
+//! A tiny escaping-aware HTML builder for status pages and listings.
+//!
+//! ```
+//! use blocking_http_server::html::{self, el, text};
+//!
+//! let page = html::page(
+//!     "Status",
+//!     [
+//!         el("h1").child(text("<ok>")),
+//!         el("a").attr("href", "/metrics?a=1&b=2").text("metrics"),
+//!     ],
+//! );
+//! assert!(page.contains("<h1>&lt;ok&gt;</h1>"));
+//! assert!(page.contains(r#"<a href="/metrics?a=1&amp;b=2">"#));
+//! ```
+
+use std::borrow::Cow;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Node {
+    /// Escaped when rendered.
+    Text(String),
+    /// Rendered verbatim; the caller is responsible for its safety.
+    Raw(String),
+    Element(Element),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Element {
+    tag: String,
+    attrs: Vec<(String, String)>,
+    children: Vec<Node>,
+}
+
+pub fn text(s: impl Into<String>) -> Node {
+    Node::Text(s.into())
+}
+
+pub fn raw(s: impl Into<String>) -> Node {
+    Node::Raw(s.into())
+}
+
+pub fn el(tag: impl Into<String>) -> Element {
+    Element {
+        tag: tag.into(),
+        attrs: Vec::new(),
+        children: Vec::new(),
+    }
+}
+
+impl Element {
+    /// Attribute values are escaped; names are expected to be literals.
+    pub fn attr(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.attrs.push((name.into(), value.into()));
+        self
+    }
+
+    pub fn child(mut self, node: impl Into<Node>) -> Self {
+        self.children.push(node.into());
+        self
+    }
+
+    pub fn children<I>(mut self, nodes: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: Into<Node>,
+    {
+        self.children.extend(nodes.into_iter().map(Into::into));
+        self
+    }
+
+    pub fn text(self, s: impl Into<String>) -> Self {
+        self.child(text(s))
+    }
+}
+
+impl From<Element> for Node {
+    fn from(e: Element) -> Self {
+        Node::Element(e)
+    }
+}
+
+impl From<&str> for Node {
+    fn from(s: &str) -> Self {
+        text(s)
+    }
+}
+
+impl From<String> for Node {
+    fn from(s: String) -> Self {
+        text(s)
+    }
+}
+
+const VOID_ELEMENTS: &[&str] = &[
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "source",
+    "track", "wbr",
+];
+
+impl fmt::Display for Node {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Node::Text(s) => f.write_str(&escape(s)),
+            Node::Raw(s) => f.write_str(s),
+            Node::Element(e) => e.fmt(f),
+        }
+    }
+}
+
+impl fmt::Display for Element {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "<{}", self.tag)?;
+        for (name, value) in self.attrs.iter() {
+            write!(f, " {}=\"{}\"", name, escape(value))?;
+        }
+        f.write_str(">")?;
+        if VOID_ELEMENTS.contains(&self.tag.as_str()) {
+            return Ok(());
+        }
+        for child in self.children.iter() {
+            child.fmt(f)?;
+        }
+        write!(f, "</{}>", self.tag)
+    }
+}
+
+/// A complete UTF-8 HTML5 document.
+pub fn page<I>(title: &str, body: I) -> String
+where
+    I: IntoIterator,
+    I::Item: Into<Node>,
+{
+    let head = el("head")
+        .child(el("meta").attr("charset", "utf-8"))
+        .child(
+            el("meta")
+                .attr("name", "viewport")
+                .attr("content", "width=device-width, initial-scale=1"),
+        )
+        .child(el("title").text(title));
+    let html = el("html").child(head).child(el("body").children(body));
+    format!("<!DOCTYPE html>\n{html}\n")
+}
+
+pub fn escape(s: &str) -> Cow<'_, str> {
+    if !s.contains(['&', '<', '>', '"', '\'']) {
+        return Cow::Borrowed(s);
+    }
+    let mut out = String::with_capacity(s.len() + 8);
+    for c in s.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            c => out.push(c),
+        }
+    }
+    Cow::Owned(out)
+}
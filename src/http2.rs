@@ -0,0 +1,472 @@
+//! HTTP/2 connections, served with the `h2` crate on the front end's runtime:
+//! negotiated through ALPN, or in cleartext (h2c) with prior knowledge or an
+//! `Upgrade: h2c`. Each stream becomes an HTTP/1.1 exchange of its own through
+//! the gateway, so handlers see an ordinary `HttpRequest`.
+
+use std::io;
+use std::io::BufReader;
+use std::io::Read;
+use std::io::Write;
+use std::net::Shutdown;
+use std::os::unix::net::UnixStream;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::Context;
+use std::task::Poll;
+
+use bytes::Bytes;
+use h2::server::SendResponse;
+use h2::Reason;
+use h2::RecvStream;
+use h2::SendStream;
+use tokio::io::AsyncRead;
+use tokio::io::AsyncReadExt;
+use tokio::io::AsyncWrite;
+use tokio::io::ReadBuf;
+use tokio::sync::mpsc;
+
+use crate::front_end::Gateway;
+use crate::header;
+use crate::proxy;
+use crate::unread;
+use crate::HeaderMap;
+use crate::HeaderValue;
+use crate::Method;
+use crate::PeerAddr;
+use crate::Request;
+use crate::Response;
+use crate::StatusCode;
+use crate::Stream;
+use crate::Uri;
+
+/// Streams a client may have open at once. Each takes a connection, and two
+/// blocking threads, while it is.
+const MAX_CONCURRENT_STREAMS: u32 = 128;
+
+/// What a client sends first, with prior knowledge or after an upgrade.
+const PREFACE: &[u8] = b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n";
+
+/// Frame payloads every peer must accept.
+const MAX_FRAME_SIZE: usize = 16 * 1024;
+
+/// Fields that only mean something on an HTTP/1.1 connection.
+const CONNECTION_SPECIFIC: [&str; 8] = [
+    "connection",
+    "content-length",
+    "host",
+    "http2-settings",
+    "keep-alive",
+    "proxy-connection",
+    "transfer-encoding",
+    "upgrade",
+];
+
+/// Serves an HTTP/2 connection on `io` until either side ends it.
+pub(crate) async fn serve<T>(io: T, peer: PeerAddr, gateway: Arc<Gateway>)
+where
+    T: AsyncRead + AsyncWrite + Unpin,
+{
+    let handshake = h2::server::Builder::new()
+        .max_concurrent_streams(MAX_CONCURRENT_STREAMS)
+        .handshake::<_, Bytes>(io);
+    let mut conn = match handshake.await {
+        Ok(conn) => conn,
+        Err(e) => {
+            debug!("{peer}: HTTP/2 handshake failed: {e}");
+            return;
+        }
+    };
+    while let Some(accepted) = conn.accept().await {
+        match accepted {
+            Ok((request, respond)) => {
+                let (peer, gateway) = (peer.clone(), gateway.clone());
+                tokio::spawn(async move {
+                    if let Err(e) = exchange(request, respond, &peer, &gateway).await {
+                        debug!("{peer}: HTTP/2 stream failed: {e}");
+                    }
+                });
+            }
+            Err(e) => {
+                debug!("{peer}: HTTP/2 connection failed: {e}");
+                return;
+            }
+        }
+    }
+}
+
+/// Serves h2c on a connection the server has already read `read` from.
+/// `upgraded` is the HEADERS frame from `upgrade_request` when the client
+/// switched from HTTP/1.1, for the request that asked to.
+pub(crate) async fn serve_cleartext(
+    stream: Stream,
+    read: Bytes,
+    upgraded: Option<Vec<u8>>,
+    peer: PeerAddr,
+    gateway: Arc<Gateway>,
+) -> io::Result<()> {
+    stream.set_nonblocking(true)?;
+    match stream {
+        Stream::Tcp(stream) => {
+            let io = Rewind::new(read, tokio::net::TcpStream::from_std(stream)?);
+            serve_rewound(io, upgraded, peer, gateway).await
+        }
+        Stream::Unix(stream) => {
+            let io = Rewind::new(read, tokio::net::UnixStream::from_std(stream)?);
+            serve_rewound(io, upgraded, peer, gateway).await
+        }
+        Stream::Stdio => Err(io::ErrorKind::Unsupported.into()),
+    }
+}
+
+async fn serve_rewound<T>(mut io: Rewind<T>, upgraded: Option<Vec<u8>>, peer: PeerAddr, gateway: Arc<Gateway>) -> io::Result<()>
+where
+    T: AsyncRead + AsyncWrite + Unpin,
+{
+    let Some(headers) = upgraded else {
+        serve(io, peer, gateway).await;
+        return Ok(());
+    };
+    // The upgraded request is stream 1, as if sent right after the client's
+    // preface and SETTINGS. HTTP2-Settings is ignored: that frame repeats it.
+    let mut start = vec![0; PREFACE.len() + 9];
+    io.read_exact(&mut start).await?;
+    let (preface, frame) = start.split_at(PREFACE.len());
+    let length = u32::from_be_bytes([0, frame[0], frame[1], frame[2]]) as usize;
+    if preface != PREFACE || frame[3] != 0x4 || length > MAX_FRAME_SIZE {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "no HTTP/2 preface after the upgrade"));
+    }
+    start.resize(start.len() + length, 0);
+    io.read_exact(&mut start[PREFACE.len() + 9..]).await?;
+    start.extend_from_slice(&headers);
+    serve(Rewind::new(start.into(), io), peer, gateway).await;
+    Ok(())
+}
+
+/// The request asking to upgrade to h2c, as the HEADERS frame that opens
+/// stream 1. `None` if it did not ask, or does not fit in a frame, in which
+/// case it is served over HTTP/1.1 as usual.
+pub(crate) fn upgrade_request(method: &Method, uri: &Uri, headers: &[httparse::Header]) -> Option<Vec<u8>> {
+    let named = |name: &'static str| headers.iter().filter(move |h| h.name.eq_ignore_ascii_case(name));
+    let tokens = |name| named(name).flat_map(|h| h.value.split(|&b| b == b',')).map(|t| t.trim_ascii());
+    let asked = tokens("upgrade").any(|t| t.eq_ignore_ascii_case(b"h2c"))
+        && tokens("connection").any(|t| t.eq_ignore_ascii_case(b"upgrade"))
+        && named("http2-settings").count() == 1;
+    if !asked || method == Method::CONNECT {
+        return None;
+    }
+    let host = named("host").next().map(|h| h.value);
+    let authority = uri.authority().map(|a| a.as_str().as_bytes()).or(host).filter(|a| !a.is_empty())?;
+
+    let mut block = Vec::new();
+    literal(&mut block, b":method", method.as_str().as_bytes());
+    literal(&mut block, b":scheme", b"http");
+    literal(&mut block, b":path", uri.path_and_query().map_or("/", |p| p.as_str()).as_bytes());
+    literal(&mut block, b":authority", authority);
+    let listed: Vec<&[u8]> = tokens("connection").collect();
+    for h in headers {
+        let name = h.name.to_ascii_lowercase();
+        let dropped = CONNECTION_SPECIFIC.contains(&name.as_str())
+            || listed.iter().any(|l| l.eq_ignore_ascii_case(name.as_bytes()))
+            || (name == "te" && !h.value.eq_ignore_ascii_case(b"trailers"));
+        if !dropped {
+            literal(&mut block, name.as_bytes(), h.value);
+        }
+    }
+    if block.len() > MAX_FRAME_SIZE {
+        return None;
+    }
+    let mut frame = (block.len() as u32).to_be_bytes()[1..].to_vec();
+    // HEADERS, with END_STREAM and END_HEADERS, on stream 1.
+    frame.extend_from_slice(&[0x1, 0x1 | 0x4, 0, 0, 0, 1]);
+    frame.extend_from_slice(&block);
+    Some(frame)
+}
+
+/// An HPACK "literal header field without indexing", with a new name and
+/// neither string Huffman-coded.
+fn literal(block: &mut Vec<u8>, name: &[u8], value: &[u8]) {
+    block.push(0);
+    for string in [name, value] {
+        integer(block, 7, string.len());
+        block.extend_from_slice(string);
+    }
+}
+
+/// An HPACK integer with a `prefix`-bit prefix, the other bits left clear.
+fn integer(block: &mut Vec<u8>, prefix: u32, mut n: usize) {
+    let max = (1 << prefix) - 1;
+    if n < max {
+        block.push(n as u8);
+        return;
+    }
+    block.push(max as u8);
+    n -= max;
+    while n >= 0x80 {
+        block.push(n as u8 | 0x80);
+        n >>= 7;
+    }
+    block.push(n as u8);
+}
+
+/// `inner`, with `prefix` read first.
+struct Rewind<T> {
+    prefix: Bytes,
+    inner: T,
+}
+
+impl<T> Rewind<T> {
+    fn new(prefix: Bytes, inner: T) -> Self {
+        Self { prefix, inner }
+    }
+}
+
+impl<T: AsyncRead + Unpin> AsyncRead for Rewind<T> {
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        if self.prefix.is_empty() {
+            return Pin::new(&mut self.inner).poll_read(cx, buf);
+        }
+        let n = self.prefix.len().min(buf.remaining());
+        buf.put_slice(&self.prefix.split_to(n));
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<T: AsyncWrite + Unpin> AsyncWrite for Rewind<T> {
+    fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.inner).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+/// A piece of the request body, from the client.
+enum Upload {
+    Data(Bytes),
+    Trailers(HeaderMap),
+    End,
+}
+
+/// A piece of the response, from the server.
+enum Event {
+    /// The head, and whether it is all there is.
+    Head(Response<()>, bool),
+    Data(Bytes),
+    Trailers(HeaderMap),
+    End,
+}
+
+async fn exchange(
+    request: Request<RecvStream>,
+    mut respond: SendResponse<Bytes>,
+    peer: &PeerAddr,
+    gateway: &Gateway,
+) -> io::Result<()> {
+    let (parts, body) = request.into_parts();
+    if parts.method == Method::CONNECT {
+        // Tunnels don't fit an HTTP/1.1 exchange.
+        let mut refused = Response::new(());
+        *refused.status_mut() = StatusCode::METHOD_NOT_ALLOWED;
+        respond.send_response(refused, true).map_err(io::Error::other)?;
+        return Ok(());
+    }
+    let chunked = !body.is_end_stream() && !parts.headers.contains_key(header::CONTENT_LENGTH);
+    let head = request_head(&parts, chunked)?;
+    let head_request = parts.method == Method::HEAD;
+
+    let conn = gateway.connect(peer.clone())?;
+    let writer = conn.try_clone()?;
+    let (upload_tx, upload_rx) = mpsc::channel(4);
+    tokio::task::spawn_blocking(move || {
+        if write_request(&writer, &head, chunked, upload_rx).is_err() {
+            // Whatever was sent of the body must not pass for all of it.
+            let _ = writer.shutdown(Shutdown::Write);
+        }
+    });
+    let (events_tx, mut events) = mpsc::channel(4);
+    tokio::task::spawn_blocking(move || read_response(&conn, head_request, events_tx));
+
+    // The body goes up while the response comes down; a response that is done
+    // first leaves the rest of the body unsent.
+    let upload = tokio::spawn(upload_body(body, upload_tx));
+    let relayed = relay(&mut respond, &mut events).await;
+    upload.abort();
+    relayed
+}
+
+/// The HTTP/1.1 request head for an HTTP/2 request.
+fn request_head(parts: &http::request::Parts, chunked: bool) -> io::Result<Vec<u8>> {
+    let target = parts.uri.path_and_query().map_or("/", |p| p.as_str());
+    let mut head = format!("{} {target} HTTP/1.1\r\n", parts.method).into_bytes();
+    let mut headers = parts.headers.clone();
+    if let Some(authority) = parts.uri.authority() {
+        headers.insert(header::HOST, HeaderValue::from_str(authority.as_str()).map_err(io::Error::other)?);
+    }
+    // HTTP/2 clients may split cookies into several fields; HTTP/1.1 wants one.
+    let cookies: Vec<&[u8]> = headers.get_all(header::COOKIE).iter().map(|v| v.as_bytes()).collect();
+    if cookies.len() > 1 {
+        let joined = HeaderValue::from_bytes(&cookies.join(&b"; "[..])).map_err(io::Error::other)?;
+        headers.insert(header::COOKIE, joined);
+    }
+    crate::write_headers(&mut head, &headers)?;
+    if chunked {
+        head.extend_from_slice(b"transfer-encoding: chunked\r\n");
+    }
+    head.extend_from_slice(b"connection: close\r\n\r\n");
+    Ok(head)
+}
+
+async fn upload_body(mut body: RecvStream, upload: mpsc::Sender<Upload>) -> Result<(), h2::Error> {
+    while let Some(data) = body.data().await {
+        let data = data?;
+        let _ = body.flow_control().release_capacity(data.len());
+        if upload.send(Upload::Data(data)).await.is_err() {
+            return Ok(());
+        }
+    }
+    if let Some(trailers) = body.trailers().await? {
+        let _ = upload.send(Upload::Trailers(trailers)).await;
+    }
+    let _ = upload.send(Upload::End).await;
+    Ok(())
+}
+
+/// Writes the request to the server, its body as it arrives. Fails if the body
+/// ends without `Upload::End`, i.e. the client reset the stream.
+fn write_request(mut conn: &UnixStream, head: &[u8], chunked: bool, mut upload: mpsc::Receiver<Upload>) -> io::Result<()> {
+    conn.write_all(head)?;
+    let mut trailers = HeaderMap::new();
+    while let Some(part) = upload.blocking_recv() {
+        match part {
+            Upload::Data(data) if data.is_empty() => {}
+            Upload::Data(data) if chunked => {
+                write!(conn, "{:x}\r\n", data.len())?;
+                conn.write_all(&data)?;
+                conn.write_all(b"\r\n")?;
+            }
+            Upload::Data(data) => conn.write_all(&data)?,
+            Upload::Trailers(fields) => trailers = fields,
+            Upload::End => {
+                if chunked {
+                    conn.write_all(b"0\r\n")?;
+                    crate::write_headers(conn, &trailers)?;
+                    conn.write_all(b"\r\n")?;
+                }
+                return Ok(());
+            }
+        }
+    }
+    Err(io::Error::new(io::ErrorKind::UnexpectedEof, "request body cut short"))
+}
+
+/// Reads the server's response into `events`. Ends without `Event::End` if the
+/// response is malformed or cut short.
+fn read_response(conn: &UnixStream, head_request: bool, events: mpsc::Sender<Event>) -> io::Result<()> {
+    let mut reader = BufReader::new(conn);
+    let mut upstream = proxy::read_head(&mut reader)?;
+    // `100 Continue` is for HTTP/1.1 clients; this one sends its body regardless.
+    while upstream.status().is_informational() {
+        upstream = proxy::read_head(&mut reader)?;
+    }
+    let chunked = upstream
+        .headers()
+        .get(header::TRANSFER_ENCODING)
+        .is_some_and(|v| v.as_bytes().trim_ascii_end().to_ascii_lowercase().ends_with(b"chunked"));
+    let bodiless =
+        head_request || upstream.status() == StatusCode::NO_CONTENT || upstream.status() == StatusCode::NOT_MODIFIED;
+
+    let mut head = Response::new(());
+    *head.status_mut() = upstream.status();
+    *head.headers_mut() = proxy::without_hop_by_hop(upstream.headers());
+    if chunked {
+        head.headers_mut().remove(header::CONTENT_LENGTH);
+    }
+    let length = head
+        .headers()
+        .get(header::CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok()?.parse::<u64>().ok());
+    send(&events, Event::Head(head, bodiless))?;
+
+    let mut body = Sink(&events);
+    if bodiless {
+        // Nothing follows the head.
+    } else if chunked {
+        let mut trailers = HeaderMap::new();
+        if !unread::decode_chunks(&mut reader, &mut body, Some(&mut trailers))? {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "malformed chunked response body"));
+        }
+        if !trailers.is_empty() {
+            send(&events, Event::Trailers(trailers))?;
+        }
+    } else if let Some(length) = length {
+        if io::copy(&mut (&mut reader).take(length), &mut body)? != length {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "response body cut short"));
+        }
+    } else {
+        io::copy(&mut reader, &mut body)?;
+    }
+    send(&events, Event::End)
+}
+
+fn send(events: &mpsc::Sender<Event>, event: Event) -> io::Result<()> {
+    events
+        .blocking_send(event)
+        .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "stream closed"))
+}
+
+/// Passes response body data on as `Event::Data`.
+struct Sink<'a>(&'a mpsc::Sender<Event>);
+
+impl Write for Sink<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        send(self.0, Event::Data(Bytes::copy_from_slice(buf)))?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Sends the response down the stream as `events` come in, resetting the
+/// stream if the server's response is cut short.
+async fn relay(respond: &mut SendResponse<Bytes>, events: &mut mpsc::Receiver<Event>) -> io::Result<()> {
+    let Some(Event::Head(head, bodiless)) = events.recv().await else {
+        respond.send_reset(Reason::INTERNAL_ERROR);
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "no response from the server"));
+    };
+    let mut stream = respond.send_response(head, bodiless).map_err(io::Error::other)?;
+    if bodiless {
+        return Ok(());
+    }
+    loop {
+        match events.recv().await {
+            Some(Event::Data(data)) => send_data(&mut stream, data).await?,
+            Some(Event::Trailers(trailers)) => return stream.send_trailers(trailers).map_err(io::Error::other),
+            Some(Event::End) => return stream.send_data(Bytes::new(), true).map_err(io::Error::other),
+            Some(Event::Head(..)) | None => {
+                stream.send_reset(Reason::INTERNAL_ERROR);
+                return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "response cut short"));
+            }
+        }
+    }
+}
+
+/// Sends `data` as the client's flow control window allows.
+async fn send_data(stream: &mut SendStream<Bytes>, mut data: Bytes) -> io::Result<()> {
+    while !data.is_empty() {
+        stream.reserve_capacity(data.len());
+        let granted = match std::future::poll_fn(|cx| stream.poll_capacity(cx)).await {
+            Some(granted) => granted.map_err(io::Error::other)?,
+            None => return Err(io::Error::new(io::ErrorKind::BrokenPipe, "stream closed")),
+        };
+        let chunk = data.split_to(granted.min(data.len()));
+        stream.send_data(chunk, false).map_err(io::Error::other)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,61 @@
+use crate::IpCidr;
+use crate::PeerAddr;
+
+/// What a connection filter decides about a newly accepted connection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Admission {
+    Accept,
+    /// Closed straight away, without a byte being read.
+    Reject,
+}
+
+/// Allow and deny lists of IP blocks, for `Server::set_connection_filter`.
+///
+/// A peer in a denied block is rejected. Once anything is allowed, so is every
+/// peer that is in an allowed block and nothing else. Peers without an IP
+/// address, e.g. over a Unix socket, are always accepted.
+///
+/// ```no_run
+/// use blocking_http_server::*;
+///
+/// let filter = IpFilter::new()
+///     .allow("10.0.0.0/8".parse()?)
+///     .deny("10.0.13.0/24".parse()?);
+/// let mut server = Server::bind("0.0.0.0:8080")?;
+/// server.set_connection_filter(move |peer| filter.check(peer));
+/// # Ok::<(), std::io::Error>(())
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct IpFilter {
+    allow: Vec<IpCidr>,
+    deny: Vec<IpCidr>,
+}
+
+impl IpFilter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn allow(mut self, block: IpCidr) -> Self {
+        self.allow.push(block);
+        self
+    }
+
+    pub fn deny(mut self, block: IpCidr) -> Self {
+        self.deny.push(block);
+        self
+    }
+
+    pub fn check(&self, peer: &PeerAddr) -> Admission {
+        let Some(ip) = peer.as_tcp().map(|addr| addr.ip()) else {
+            return Admission::Accept;
+        };
+        let denied = self.deny.iter().any(|block| block.contains(ip));
+        let allowed = self.allow.is_empty() || self.allow.iter().any(|block| block.contains(ip));
+        if allowed && !denied {
+            Admission::Accept
+        } else {
+            Admission::Reject
+        }
+    }
+}
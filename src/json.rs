@@ -0,0 +1,138 @@
+//! JSON bodies with `serde`, behind the `serde` feature.
+//!
+//! ```no_run
+//! use blocking_http_server::*;
+//! use serde::{Deserialize, Serialize};
+//!
+//! #[derive(Deserialize)]
+//! struct NewTodo {
+//!     title: String,
+//! }
+//!
+//! #[derive(Serialize)]
+//! struct Todo {
+//!     id: u64,
+//!     title: String,
+//! }
+//!
+//! let router = Router::new().post("/todos", |req| {
+//!     let new: NewTodo = match req.json() {
+//!         Ok(new) => new,
+//!         Err(e) => return e.into(),
+//!     };
+//!     Response::json(&Todo { id: 1, title: new.title }).with_status(StatusCode::CREATED)
+//! });
+//! ```
+
+use std::fmt;
+use std::io;
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::header;
+use crate::HeaderValue;
+use crate::HttpRequest;
+use crate::Response;
+use crate::StatusCode;
+
+/// Why `HttpRequest::json` could not give the value asked for. Converts into
+/// the response to send.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InvalidJson {
+    /// The `Content-Type` is missing or not JSON: `415 Unsupported Media Type`.
+    ContentType,
+    /// The body is not JSON of the expected shape: `400 Bad Request`.
+    Body(String),
+}
+
+impl InvalidJson {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            InvalidJson::ContentType => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            InvalidJson::Body(_) => StatusCode::BAD_REQUEST,
+        }
+    }
+}
+
+impl fmt::Display for InvalidJson {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InvalidJson::ContentType => f.write_str("expected content-type application/json"),
+            InvalidJson::Body(e) => write!(f, "invalid JSON body: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for InvalidJson {}
+
+impl From<InvalidJson> for Response<Vec<u8>> {
+    fn from(e: InvalidJson) -> Self {
+        let mut response = Response::new(e.to_string().into_bytes());
+        *response.status_mut() = e.status();
+        response
+    }
+}
+
+impl From<InvalidJson> for io::Error {
+    fn from(e: InvalidJson) -> Self {
+        io::Error::new(io::ErrorKind::InvalidData, e)
+    }
+}
+
+impl HttpRequest {
+    /// Deserializes the body, which must be sent as `application/json` or
+    /// another `+json` type.
+    pub fn json<T: DeserializeOwned>(&self) -> Result<T, InvalidJson> {
+        let content_type = self
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or("");
+        if !is_json(content_type) {
+            return Err(InvalidJson::ContentType);
+        }
+        serde_json::from_slice(self.body()).map_err(|e| InvalidJson::Body(e.to_string()))
+    }
+
+    /// Responds `200` with `value` as JSON. Fails without sending anything if it
+    /// cannot be serialized, e.g. a map with non-string keys.
+    pub fn respond_json<T: Serialize + ?Sized>(&self, value: &T) -> io::Result<()> {
+        let body = serde_json::to_vec(value).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        self.respond(json_response(body))
+    }
+}
+
+/// `Response::json`, for responses built in handlers.
+pub trait JsonResponse: Sized {
+    /// `value` as an `application/json` body. A value that cannot be serialized
+    /// gives a `500` instead.
+    fn json<T: Serialize + ?Sized>(value: &T) -> Self;
+}
+
+impl JsonResponse for Response<Vec<u8>> {
+    fn json<T: Serialize + ?Sized>(value: &T) -> Self {
+        match serde_json::to_vec(value) {
+            Ok(body) => json_response(body),
+            Err(e) => {
+                let mut response = Response::new(format!("cannot serialize response: {e}").into_bytes());
+                *response.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
+                response
+            }
+        }
+    }
+}
+
+fn json_response(body: Vec<u8>) -> Response<Vec<u8>> {
+    let mut response = Response::new(body);
+    response
+        .headers_mut()
+        .insert(header::CONTENT_TYPE, HeaderValue::from_static("application/json"));
+    response
+}
+
+/// `application/json`, or a structured syntax like `application/problem+json`.
+fn is_json(content_type: &str) -> bool {
+    let essence = content_type.split(';').next().unwrap_or("").trim().to_ascii_lowercase();
+    essence == "application/json" || (essence.starts_with("application/") && essence.ends_with("+json"))
+}
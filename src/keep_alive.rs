@@ -0,0 +1,211 @@
+use std::io;
+use std::sync::Mutex;
+use std::time::Duration;
+use std::time::Instant;
+
+use crate::header;
+use crate::header::HeaderValue;
+use crate::throttle::Throttles;
+use crate::ConnectionStats;
+use crate::Method;
+use crate::PeerAddr;
+use crate::Request;
+use crate::Stream;
+use crate::Uri;
+
+/// Per-connection bookkeeping that survives from one request to the next.
+#[derive(Debug, Clone)]
+pub(crate) struct ConnState {
+    pub(crate) addr: PeerAddr,
+    pub(crate) accepted_at: Instant,
+    pub(crate) bytes_read: usize,
+    pub(crate) bytes_written: usize,
+    /// Requests already answered on this connection.
+    pub(crate) requests: usize,
+    /// Requests read from this connection since a new one was last let in ahead of it.
+    pub(crate) streak: usize,
+    /// Accepted on a priority listener.
+    pub(crate) priority: bool,
+    pub(crate) memo: Option<Memo>,
+    pub(crate) reads: Throttles,
+    pub(crate) writes: Throttles,
+    /// Parent of the spans of the requests read from this connection.
+    #[cfg(feature = "tracing")]
+    pub(crate) span: tracing::Span,
+}
+
+impl ConnState {
+    pub(crate) fn new(addr: PeerAddr) -> Self {
+        Self {
+            #[cfg(feature = "tracing")]
+            span: tracing::info_span!("connection", peer = %addr),
+            addr,
+            accepted_at: Instant::now(),
+            bytes_read: 0,
+            bytes_written: 0,
+            requests: 0,
+            streak: 0,
+            priority: false,
+            memo: None,
+            reads: Throttles::default(),
+            writes: Throttles::default(),
+        }
+    }
+
+    /// Runs `f` within this connection's span, with the `tracing` feature.
+    pub(crate) fn in_span<R>(&self, f: impl FnOnce() -> R) -> R {
+        #[cfg(feature = "tracing")]
+        let _entered = self.span.enter();
+        f()
+    }
+
+    pub(crate) fn stats(&self) -> ConnectionStats {
+        ConnectionStats {
+            duration: self.accepted_at.elapsed(),
+            bytes_read: self.bytes_read,
+            bytes_written: self.bytes_written,
+        }
+    }
+}
+
+/// Validators of the last `200` answer to a `GET` on a connection.
+#[derive(Debug, Clone)]
+pub(crate) struct Memo {
+    uri: Uri,
+    etag: Option<HeaderValue>,
+    last_modified: Option<HeaderValue>,
+    stored_at: Instant,
+}
+
+impl Memo {
+    pub(crate) fn new<B, T>(request: &Request<B>, response: &crate::Response<T>) -> Option<Self> {
+        if request.method() != Method::GET || response.status() != crate::StatusCode::OK {
+            return None;
+        }
+        let etag = response.headers().get(header::ETAG).cloned();
+        let last_modified = response.headers().get(header::LAST_MODIFIED).cloned();
+        if etag.is_none() && last_modified.is_none() {
+            return None;
+        }
+        Some(Self {
+            uri: request.uri().clone(),
+            etag,
+            last_modified,
+            stored_at: Instant::now(),
+        })
+    }
+
+    /// Whether `request` is a conditional `GET`/`HEAD` that the memoized response,
+    /// no older than `ttl`, already satisfies.
+    pub(crate) fn matches<B>(&self, request: &Request<B>, ttl: Duration) -> bool {
+        if request.method() != Method::GET && request.method() != Method::HEAD {
+            return false;
+        }
+        if self.stored_at.elapsed() >= ttl || request.uri() != &self.uri {
+            return false;
+        }
+
+        crate::conditional::not_modified(request.headers(), self.etag.as_ref(), self.last_modified.as_ref())
+    }
+
+    pub(crate) fn validators(&self) -> impl Iterator<Item = (header::HeaderName, &HeaderValue)> {
+        let etag = self.etag.as_ref().map(|v| (header::ETAG, v));
+        let last_modified = self.last_modified.as_ref().map(|v| (header::LAST_MODIFIED, v));
+        etag.into_iter().chain(last_modified)
+    }
+}
+
+/// Kept-alive connections waiting for their next request.
+#[derive(Debug)]
+pub(crate) struct IdleSet {
+    conns: Mutex<Vec<(Stream, ConnState, Instant)>>,
+    #[cfg(unix)]
+    waker: (std::os::unix::net::UnixStream, std::os::unix::net::UnixStream),
+}
+
+impl IdleSet {
+    pub(crate) fn new() -> io::Result<Self> {
+        Ok(Self {
+            conns: Mutex::default(),
+            #[cfg(unix)]
+            waker: {
+                let (tx, rx) = std::os::unix::net::UnixStream::pair()?;
+                tx.set_nonblocking(true)?;
+                rx.set_nonblocking(true)?;
+                (tx, rx)
+            },
+        })
+    }
+
+    /// Hands a connection back, possibly from another thread.
+    pub(crate) fn park(&self, stream: Stream, conn: ConnState) {
+        self.conns
+            .lock()
+            .unwrap()
+            .push((stream, conn, Instant::now()));
+        #[cfg(unix)]
+        {
+            use std::io::Write;
+            let _ = (&self.waker.0).write(&[1]);
+        }
+    }
+
+    pub(crate) fn take_all(&self) -> Vec<(Stream, ConnState, Instant)> {
+        std::mem::take(&mut *self.conns.lock().unwrap())
+    }
+
+    pub(crate) fn put_back(&self, conns: Vec<(Stream, ConnState, Instant)>) {
+        self.conns.lock().unwrap().extend(conns);
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.conns.lock().unwrap().len()
+    }
+
+    /// Removes the connection that has been waiting the longest.
+    pub(crate) fn take_oldest(&self) -> Option<(Stream, ConnState)> {
+        let mut conns = self.conns.lock().unwrap();
+        let oldest = (0..conns.len()).min_by_key(|&i| conns[i].2)?;
+        let (stream, conn, _) = conns.swap_remove(oldest);
+        Some((stream, conn))
+    }
+}
+
+#[cfg(unix)]
+impl IdleSet {
+    pub(crate) fn waker_fd(&self) -> std::os::fd::RawFd {
+        use std::os::fd::AsRawFd;
+        self.waker.1.as_raw_fd()
+    }
+
+    pub(crate) fn drain_waker(&self) {
+        use std::io::Read;
+        let mut buf = [0; 64];
+        while matches!((&self.waker.1).read(&mut buf), Ok(n) if n > 0) {}
+    }
+}
+
+/// Waits until one of `fds` is readable (or hung up), for at most `timeout`.
+#[cfg(unix)]
+pub(crate) fn poll_readable(
+    fds: &[std::os::fd::RawFd],
+    timeout: Option<Duration>,
+) -> io::Result<Vec<bool>> {
+    let mut pollfds: Vec<libc::pollfd> = fds
+        .iter()
+        .map(|&fd| libc::pollfd {
+            fd,
+            events: libc::POLLIN,
+            revents: 0,
+        })
+        .collect();
+    // Round up so that a deadline is never reported before it has passed.
+    let timeout_ms = timeout.map_or(-1, |t| {
+        t.as_nanos().div_ceil(1_000_000).min(i32::MAX as u128) as libc::c_int
+    });
+    let n = unsafe { libc::poll(pollfds.as_mut_ptr(), pollfds.len() as libc::nfds_t, timeout_ms) };
+    if n < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(pollfds.iter().map(|p| p.revents != 0).collect())
+}
@@ -1,59 +1,768 @@
 #![doc = include_str!("../README.md")]
 
+#[macro_use]
+mod diag;
+mod accept;
+pub mod access_log;
+#[cfg(all(feature = "socket-activation", unix))]
+mod activation;
+mod backoff;
+mod builder;
+mod chunked;
+pub mod clock;
+#[cfg(feature = "tokio-bridge")]
+pub mod bridge;
+mod codec;
+pub mod conditional;
+mod date;
+#[cfg(any(feature = "compression-gzip", feature = "compression-br", feature = "compression-zstd"))]
+mod decompress;
+mod error;
+pub mod files;
+mod forwarded;
+#[cfg(all(feature = "tls", unix))]
+mod front_end;
+mod hooks;
+mod ip_filter;
+mod keep_alive;
+mod net;
+mod pool;
+mod preview;
+mod redirect;
+mod response_ext;
+pub mod html;
+#[cfg(all(feature = "h2", unix))]
+mod http2;
+#[cfg(feature = "serde")]
+pub mod json;
+pub mod metrics;
+pub mod middleware;
+pub mod mime;
+pub mod proxy;
+pub mod query;
+pub mod range;
+#[cfg(unix)]
+pub mod restart;
+pub mod router;
+pub mod stub;
+mod shutdown;
+mod throttle;
+mod unread;
+
+use std::borrow::Cow;
+use std::collections::HashMap;
 use std::ops::Deref;
+use std::ops::Range;
 use std::ops::DerefMut;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::AtomicU16;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::OnceLock;
+use std::time::Duration;
+use std::time::Instant;
 
+pub use accept::MediaRange;
+pub use backoff::AcceptBackoff;
+pub use builder::ServerBuilder;
+pub use chunked::ChunkedWriter;
+use chunked::Framing;
+use backoff::AcceptStorm;
 use bytes::BytesMut;
+pub use codec::*;
+pub use error::RecvError;
+pub use forwarded::IpCidr;
+pub use hooks::ConnectionStats;
+pub use hooks::ResponseInfo;
+pub use ip_filter::Admission;
+pub use ip_filter::IpFilter;
+#[cfg(feature = "tls")]
+pub use rustls;
+#[cfg(feature = "serde")]
+pub use json::JsonResponse;
+use hooks::CountingWriter;
+use hooks::Hooks;
 pub use http::*;
+use keep_alive::ConnState;
+use keep_alive::IdleSet;
+use keep_alive::Memo;
+use net::Listener;
+use pool::BufferPool;
+use pool::PooledBuf;
+pub use net::PeerAddr;
+pub use net::Stream;
+pub use preview::Preview;
+pub use query::DuplicatePolicy;
+pub use query::Query;
+pub use response_ext::ResponseExt;
+pub use router::Router;
+pub use shutdown::ConnectionLimit;
+pub use shutdown::DrainPolicy;
+pub use shutdown::ShutdownHandle;
+pub use unread::UnreadBody;
+use unread::Decoded;
+use shutdown::ConnectionGuard;
+use shutdown::Connections;
+use throttle::Throttle;
+use throttle::Throttled;
 use io::Read;
 use io::Write;
 use std::io;
+use std::net::IpAddr;
 use std::net::SocketAddr;
 use std::net::TcpListener;
-use std::net::TcpStream;
 use std::net::ToSocketAddrs;
 
+type ConnectionFilter = dyn Fn(&PeerAddr) -> Admission + Send + Sync;
+type PreviewFilter = dyn Fn(&Preview) -> Option<Response<Vec<u8>>> + Send + Sync;
+
 pub struct Server {
-    listener: TcpListener,
+    listeners: Vec<Listener>,
+    /// Indices into `listeners` whose connections are served first.
+    priority_listeners: Vec<usize>,
     req_size_limit: usize,
+    request_line_limit: usize,
+    header_count_limit: usize,
+    read_timeout: Option<Duration>,
+    write_timeout: Option<Duration>,
+    header_read_timeout: Option<Duration>,
+    nodelay: bool,
+    lazy_headers: bool,
+    tcp_keepalive: Option<Duration>,
+    linger: Option<Duration>,
+    codecs: Arc<CodecRegistry>,
+    trusted_proxies: Arc<[IpCidr]>,
+    shutdown: Arc<AtomicBool>,
+    connections: Arc<Connections>,
+    hooks: Arc<Hooks>,
+    accept_backoff: Option<AcceptBackoff>,
+    drain_policies: HashMap<String, DrainPolicy>,
+    connection_filter: Option<Arc<ConnectionFilter>>,
+    preview_filter: Option<Arc<PreviewFilter>>,
+    buffers: Arc<BufferPool>,
+    memory_budget: Option<usize>,
+    keep_alive: Option<Duration>,
+    keep_alive_fairness: Option<usize>,
+    overload_limit: Option<usize>,
+    connection_limit: Option<ConnectionLimit>,
+    read_rate_limit: Option<u64>,
+    total_read_throttle: Option<Throttle>,
+    write_rate_limit: Option<u64>,
+    unread_body: UnreadBody,
+    chunked_body_limit: Option<usize>,
+    #[cfg(any(feature = "compression-gzip", feature = "compression-br", feature = "compression-zstd"))]
+    request_decompression: bool,
+    validator_memo: Option<Duration>,
+    idle: Arc<IdleSet>,
+    #[cfg(all(feature = "tls", unix))]
+    front_end: Option<front_end::FrontEnd>,
 
-    buf: BytesMut,
 }
 
 impl Server {
     const DEFAULT_REQ_SIZE_LIMIT: usize = 4096;
-    const HEADER_COUNT_LIMIT: usize = 64;
+    const DEFAULT_REQUEST_LINE_LIMIT: usize = 8192;
+    const DEFAULT_HEADER_COUNT_LIMIT: usize = 64;
+    /// Header slots kept on the stack; requests with more fall back to the heap.
+    const INLINE_HEADER_COUNT: usize = 32;
+    const DEFAULT_KEEP_ALIVE_FAIRNESS: usize = 16;
 
     pub fn bind(addr: impl ToSocketAddrs) -> io::Result<Self> {
-        let listener = TcpListener::bind(addr)?;
+        Self::from_listeners(vec![Listener::Tcp(TcpListener::bind(addr)?)])
+    }
+
+    /// For socket options that must be set before binding, like the backlog.
+    pub fn builder() -> ServerBuilder {
+        ServerBuilder::default()
+    }
+
+    /// Binds every address in `addrs` and serves them all from one `incoming()`.
+    ///
+    /// Binding `0.0.0.0` and `[::]` to the same port fails where IPv6 sockets
+    /// also accept IPv4 by default (e.g. Linux); see `bind_dual_stack`.
+    pub fn bind_all<A: ToSocketAddrs>(addrs: impl IntoIterator<Item = A>) -> io::Result<Self> {
+        Self::builder().bind_all(addrs)
+    }
+
+    /// Accepts IPv4 and IPv6 clients on `port`: one `[::]` socket with
+    /// `IPV6_V6ONLY` off where the OS allows it, otherwise separate `0.0.0.0` and
+    /// `[::]` listeners (just the former on hosts without IPv6).
+    pub fn bind_dual_stack(port: u16) -> io::Result<Self> {
+        Self::builder().bind_dual_stack(port)
+    }
+
+    /// Binds with `SO_REUSEPORT`, so several processes, or threads each with their
+    /// own `Server`, can share `addr` and let the kernel spread connections between
+    /// them. Every socket sharing the port must be bound this way.
+    #[cfg(unix)]
+    pub fn bind_reuseport(addr: impl ToSocketAddrs) -> io::Result<Self> {
+        Self::builder().reuse_port(true).bind(addr)
+    }
+
+    /// Serves an already bound listener, e.g. one set up with custom socket options.
+    /// Any non-blocking mode on it is reset, as the server relies on blocking `accept()`.
+    pub fn from_listener(listener: TcpListener) -> io::Result<Self> {
+        listener.set_nonblocking(false)?;
+        Self::from_listeners(vec![Listener::Tcp(listener)])
+    }
+
+    /// A server without a listener, for handling connections accepted elsewhere with
+    /// `recv_from` or `recv_stdio`. Its `incoming()` yields nothing.
+    pub fn unbound() -> io::Result<Self> {
+        Self::from_listeners(Vec::new())
+    }
+
+    /// Serves HTTP over a Unix domain socket at `path`. A stale socket file left
+    /// behind by a previous run makes this fail with `AddrInUse`; remove it first.
+    #[cfg(unix)]
+    pub fn bind_unix(path: impl AsRef<std::path::Path>) -> io::Result<Self> {
+        let listener = std::os::unix::net::UnixListener::bind(path)?;
+        Self::from_listeners(vec![Listener::Unix(listener)])
+    }
+
+    fn from_listeners(listeners: Vec<Listener>) -> io::Result<Self> {
         Ok(Self {
-            listener,
+            listeners,
+            priority_listeners: Vec::new(),
             req_size_limit: Self::DEFAULT_REQ_SIZE_LIMIT,
-            buf: BytesMut::with_capacity(Self::DEFAULT_REQ_SIZE_LIMIT),
+            request_line_limit: Self::DEFAULT_REQUEST_LINE_LIMIT,
+            header_count_limit: Self::DEFAULT_HEADER_COUNT_LIMIT,
+            read_timeout: None,
+            write_timeout: None,
+            header_read_timeout: None,
+            nodelay: true,
+            lazy_headers: false,
+            tcp_keepalive: None,
+            linger: None,
+            codecs: Arc::default(),
+            trusted_proxies: Arc::new([]),
+            shutdown: Arc::default(),
+            connections: Arc::default(),
+            hooks: Arc::default(),
+            accept_backoff: Some(AcceptBackoff::default()),
+            drain_policies: HashMap::new(),
+            connection_filter: None,
+            preview_filter: None,
+            buffers: BufferPool::new(Self::DEFAULT_REQ_SIZE_LIMIT),
+            memory_budget: None,
+            keep_alive: None,
+            keep_alive_fairness: Some(Self::DEFAULT_KEEP_ALIVE_FAIRNESS),
+            overload_limit: None,
+            connection_limit: None,
+            read_rate_limit: None,
+            total_read_throttle: None,
+            write_rate_limit: None,
+            unread_body: UnreadBody::Close,
+            chunked_body_limit: None,
+            #[cfg(any(feature = "compression-gzip", feature = "compression-br", feature = "compression-zstd"))]
+            request_decompression: false,
+            validator_memo: None,
+            idle: Arc::new(IdleSet::new()?),
+            #[cfg(all(feature = "tls", unix))]
+            front_end: None,
         })
     }
 
     pub fn set_request_size_limit(&mut self, limit: usize) {
-        self.buf = BytesMut::with_capacity(limit);
+        self.buffers = self.buffers.resized(limit);
         self.req_size_limit = limit;
     }
 
-    pub fn incoming(&mut self) -> Incoming {
+    /// Caps the memory held by request buffers across all requests in flight. Each
+    /// takes `set_request_size_limit` bytes while it is read and until it is dropped,
+    /// plus what its body needs beyond that once decoded from chunks or a
+    /// `Content-Encoding`. A request that would push the total past `bytes` is
+    /// answered `503 Service Unavailable`, without being read if it can be told
+    /// beforehand. `None` (the default) means no cap.
+    pub fn set_memory_budget(&mut self, bytes: Option<usize>) {
+        self.memory_budget = bytes;
+    }
+
+    /// Requests whose request line (method, URI and version) is longer than `limit`
+    /// are answered with `414 URI Too Long` before the rest of the header is read.
+    pub fn set_request_line_limit(&mut self, limit: usize) {
+        self.request_line_limit = limit;
+    }
+
+    /// Maximum number of header fields accepted in a single request. Up to 32 are
+    /// parsed without allocating.
+    pub fn set_header_count_limit(&mut self, limit: usize) {
+        self.header_count_limit = limit;
+    }
+
+    /// Applied to every accepted connection. A client that stays silent for longer
+    /// than `timeout` gets `408 Request Timeout` instead of stalling `incoming()`.
+    pub fn set_read_timeout(&mut self, timeout: Option<Duration>) {
+        self.read_timeout = timeout;
+    }
+
+    /// Applied to every accepted connection, bounding how long `respond` may block.
+    pub fn set_write_timeout(&mut self, timeout: Option<Duration>) {
+        self.write_timeout = timeout;
+    }
+
+    /// Wall-clock budget for receiving the complete request header, however slowly
+    /// the bytes trickle in. Clients exceeding it get `408 Request Timeout`.
+    pub fn set_header_read_timeout(&mut self, timeout: Option<Duration>) {
+        self.header_read_timeout = timeout;
+    }
+
+    /// Defers building each request's `HeaderMap` until it is first needed, i.e.
+    /// until the request is used as an `http::Request`. `method()`, `uri()`,
+    /// `version()` and `raw_header()` don't need it, which saves the per-header
+    /// allocations for handlers that only look at a header or two.
+    pub fn set_lazy_headers(&mut self, lazy: bool) {
+        self.lazy_headers = lazy;
+    }
+
+    /// `TCP_NODELAY` for accepted connections, on by default so that small
+    /// responses go out immediately. Turning it off lets Nagle's algorithm
+    /// coalesce writes, which can suit bulk transfers.
+    pub fn set_nodelay(&mut self, nodelay: bool) {
+        self.nodelay = nodelay;
+    }
+
+    /// Enables TCP keepalive on accepted connections: after `idle` without traffic
+    /// the kernel probes the peer every `idle`, and drops the connection once the
+    /// probes go unanswered. Reaps clients that vanished without closing.
+    pub fn set_tcp_keepalive(&mut self, idle: Option<Duration>) {
+        self.tcp_keepalive = idle;
+    }
+
+    /// Sets `SO_LINGER` on accepted connections. `Some(Duration::ZERO)` makes closing
+    /// a connection reset it, discarding unsent data; a longer timeout makes closing
+    /// wait for unsent data up to that long. `None` (the default) closes in the
+    /// background.
+    pub fn set_linger(&mut self, linger: Option<Duration>) {
+        self.linger = linger;
+    }
+
+    /// While `accept()` fails with transient errors (peer resets, running out of file
+    /// descriptors), retry with exponential backoff instead of yielding every failure.
+    /// The run is reported through `on_error` when it starts, when the delay reaches
+    /// `max`, every minute after that, and once more when it ends. `None` yields
+    /// them all.
+    pub fn set_accept_backoff(&mut self, backoff: Option<AcceptBackoff>) {
+        self.accept_backoff = backoff;
+    }
+
+    /// Keeps HTTP/1.1 connections open between requests for up to `idle_timeout`,
+    /// and HTTP/1.0 ones whose requests carry `Connection: keep-alive`.
+    /// `incoming()` keeps serving everyone in between by waiting on the listener and
+    /// all idle connections at once. Only available on Unix; elsewhere, and with
+    /// `None` (the default), every response closes its connection.
+    pub fn set_keep_alive(&mut self, idle_timeout: Option<Duration>) {
+        self.keep_alive = idle_timeout;
+    }
+
+    /// After `max_consecutive` requests in a row from one kept-alive connection, a
+    /// pending new connection is accepted before that one is read again, so a chatty
+    /// client can't starve newcomers. 16 by default; `None` always favours
+    /// kept-alive connections.
+    pub fn set_keep_alive_fairness(&mut self, max_consecutive: Option<usize>) {
+        self.keep_alive_fairness = max_consecutive;
+    }
+
+    /// What to do with a kept-alive connection when the handler did not read a
+    /// chunked request body; see `UnreadBody`. Handlers that read such bodies
+    /// themselves should keep the default, `UnreadBody::Close`.
+    pub fn set_unread_body(&mut self, policy: UnreadBody) {
+        self.unread_body = policy;
+    }
+
+    /// Proxies whose `Forwarded` and `X-Forwarded-For` headers
+    /// `HttpRequest::client_addr` believes, e.g. `"10.0.0.0/8".parse()?`. None by
+    /// default, so that it returns the peer's own address.
+    pub fn set_trusted_proxies(&mut self, proxies: impl IntoIterator<Item = IpCidr>) {
+        self.trusted_proxies = proxies.into_iter().collect();
+    }
+
+    /// Reads `Transfer-Encoding: chunked` request bodies of up to `limit` bytes on
+    /// the wire before handing requests out, so that `body()` holds them decoded
+    /// and `trailers()` their trailer fields. Longer ones are answered `413`. With
+    /// `None`, the default, such bodies are left on the connection for the handler.
+    pub fn set_chunked_body_limit(&mut self, limit: Option<usize>) {
+        self.chunked_body_limit = limit;
+    }
+
+    /// Decodes request bodies sent with a `Content-Encoding` before handing
+    /// requests out, so `body()` holds them as the client meant them; the
+    /// `Content-Encoding` and `Content-Length` headers are dropped. `gzip` and
+    /// `deflate` need the `compression-gzip` feature, `br` and `zstd` theirs.
+    ///
+    /// Decoded bodies are held to the same limit as plain ones,
+    /// `set_request_size_limit` or `set_chunked_body_limit`, or answered `413`;
+    /// unknown codings get `415`, corrupt ones `400`. Chunked bodies left on the
+    /// connection are not touched.
+    #[cfg(any(feature = "compression-gzip", feature = "compression-br", feature = "compression-zstd"))]
+    pub fn set_request_decompression(&mut self, enabled: bool) {
+        self.request_decompression = enabled;
+    }
+
+    /// Remembers the validators (`ETag`, `Last-Modified`) of the last `200` sent for
+    /// a `GET` on each kept-alive connection. A conditional request for the same URI
+    /// within `ttl` is answered `304 Not Modified` by `incoming()` itself, without
+    /// being yielded. Suits polling clients; only enable it when a resource staying
+    /// unchanged for `ttl` is acceptable.
+    pub fn set_validator_memo(&mut self, ttl: Option<Duration>) {
+        self.validator_memo = ttl;
+    }
+
+    fn keep_alive_enabled(&self) -> bool {
+        cfg!(unix) && self.keep_alive.is_some()
+    }
+
+    /// Makes `HttpRequest::decode` and `HttpRequest::respond_encoded` aware of `codec`.
+    pub fn register_codec<T: 'static>(&mut self, codec: impl BodyCodec<T> + 'static) {
+        Arc::make_mut(&mut self.codecs).register(codec);
+    }
+
+    /// Called with the peer address right after a connection is accepted.
+    pub fn on_accept(&mut self, hook: impl Fn(&PeerAddr) + Send + Sync + 'static) {
+        Arc::make_mut(&mut self.hooks).accept = Some(Arc::new(hook));
+    }
+
+    /// Called when a connection is done with: after its `HttpRequest` is dropped,
+    /// or right away when the request could not be read.
+    pub fn on_connection_close(
+        &mut self,
+        hook: impl Fn(&PeerAddr, &ConnectionStats) + Send + Sync + 'static,
+    ) {
+        Arc::make_mut(&mut self.hooks).close = Some(Arc::new(hook));
+    }
+
+    /// Called with each request about to be handed out, after `incoming()` has
+    /// dealt with the ones it answers itself. `HttpRequest::received_at` tells
+    /// how long it took to get here.
+    pub fn on_request(&mut self, hook: impl Fn(&HttpRequest) + Send + Sync + 'static) {
+        Arc::make_mut(&mut self.hooks).request = Some(Arc::new(hook));
+    }
+
+    /// Called when each request is done with, i.e. dropped, with how it was
+    /// answered. See `access_log::AccessLog` for a ready-made one.
+    pub fn on_response(&mut self, hook: impl Fn(&HttpRequest, &ResponseInfo) + Send + Sync + 'static) {
+        Arc::make_mut(&mut self.hooks).response = Some(Arc::new(hook));
+    }
+
+    /// Looks at each request as soon as its header is parsed, before the
+    /// `http::Request` is built. Returning a response sends it and closes the
+    /// connection without the request ever being yielded, which keeps health checks
+    /// and early rejections cheap. A client sending `Expect: 100-continue` is only
+    /// told to go ahead with its body after the filter has let the request through,
+    /// so it can also turn away unwanted uploads before they are sent:
+    ///
+    /// ```no_run
+    /// # let mut server = blocking_http_server::Server::bind("127.0.0.1:8000").unwrap();
+    /// use blocking_http_server::*;
+    ///
+    /// server.set_preview_filter(|req| match req.path() {
+    ///     "/healthz" => Some(Response::new(b"ok".to_vec())),
+    ///     _ => None,
+    /// });
+    /// ```
+    pub fn set_preview_filter(
+        &mut self,
+        filter: impl Fn(&Preview) -> Option<Response<Vec<u8>>> + Send + Sync + 'static,
+    ) {
+        self.preview_filter = Some(Arc::new(filter));
+    }
+
+    /// Decides whether to serve each connection right after it is accepted, before
+    /// anything is read from it or `on_accept` is called. Rejected connections are
+    /// closed and never show up in `incoming()`; `recv_from` fails with
+    /// `PermissionDenied` for them. See `IpFilter` for allow and deny lists.
+    pub fn set_connection_filter(&mut self, filter: impl Fn(&PeerAddr) -> Admission + Send + Sync + 'static) {
+        self.connection_filter = Some(Arc::new(filter));
+    }
+
+    /// Called for every error `incoming()` yields, before it is handed to the caller.
+    pub fn on_error(&mut self, hook: impl Fn(&io::Error) + Send + Sync + 'static) {
+        Arc::make_mut(&mut self.hooks).error = Some(Arc::new(hook));
+    }
+
+    pub fn incoming(&mut self) -> Incoming<'_> {
         Incoming { server: self }
     }
 
-    pub fn recv(&mut self) -> io::Result<HttpRequest> {
-        self.incoming().next().unwrap()
+    /// Blocks until the next request. Fails with `RecvError::Shutdown` once the
+    /// server is shut down.
+    pub fn recv(&mut self) -> std::result::Result<HttpRequest, RecvError> {
+        self.recv_inner(None)
+    }
+
+    /// Like `recv`, but gives up with `RecvError::TimedOut` at `deadline`.
+    pub fn recv_deadline(&mut self, deadline: Instant) -> std::result::Result<HttpRequest, RecvError> {
+        self.recv_inner(Some(deadline))
+    }
+
+    /// Like `recv`, but gives up with `RecvError::TimedOut` after `timeout`.
+    pub fn recv_timeout(&mut self, timeout: Duration) -> std::result::Result<HttpRequest, RecvError> {
+        self.recv_deadline(Instant::now() + timeout)
+    }
+
+    fn recv_inner(&mut self, deadline: Option<Instant>) -> std::result::Result<HttpRequest, RecvError> {
+        match self.next_request(deadline) {
+            Some(Ok(Some(req))) => Ok(req),
+            Some(Ok(None)) => Err(RecvError::TimedOut),
+            Some(Err(e)) => Err(RecvError::Io(e)),
+            None => Err(RecvError::Shutdown),
+        }
+    }
+
+    /// Number of requests handed out by `incoming()` that are still alive.
+    pub fn active_connections(&self) -> usize {
+        self.connections.active()
+    }
+
+    /// Requests answered `503` so far by `set_overload_limit` or a
+    /// `ConnectionLimit::Shed`.
+    pub fn shed_requests(&self) -> u64 {
+        self.connections.shed()
+    }
+
+    /// Once `limit` requests handed out are still alive (see `active_connections`),
+    /// further requests are answered `503 Service Unavailable` by `incoming()` itself
+    /// instead of being yielded. The `Retry-After` sent along estimates when capacity
+    /// frees up from how long requests have been taking. `None` by default.
+    pub fn set_overload_limit(&mut self, limit: Option<usize>) {
+        self.overload_limit = limit;
+    }
+
+    /// Caps the connections held open at once, so that a flood of them cannot run
+    /// the process out of threads or file descriptors. Checked as each one is
+    /// accepted, before anything is read from it. `None` by default.
+    pub fn set_connection_limit(&mut self, limit: Option<ConnectionLimit>) {
+        self.connection_limit = limit;
+    }
+
+    /// Caps how fast each connection's requests are read, in bytes per second, so
+    /// that one client uploading at line rate cannot starve the others. Applies
+    /// to everything the server reads: headers, bodies, and leftover chunked
+    /// bodies being drained. `None` by default.
+    pub fn set_read_rate_limit(&mut self, bytes_per_second: Option<u64>) {
+        self.read_rate_limit = bytes_per_second;
+    }
+
+    /// Like `set_read_rate_limit`, but shared by all connections together.
+    pub fn set_total_read_rate_limit(&mut self, bytes_per_second: Option<u64>) {
+        self.total_read_throttle = bytes_per_second.map(Throttle::new);
+    }
+
+    /// Caps how fast responses are sent on each connection, in bytes per second,
+    /// e.g. to share the uplink fairly between downloads of large files. `None`
+    /// by default.
+    pub fn set_write_rate_limit(&mut self, bytes_per_second: Option<u64>) {
+        self.write_rate_limit = bytes_per_second;
+    }
+
+    /// Stops accepting, waits up to `timeout` for requests still being handled on
+    /// other threads to be dropped, then shuts down the sockets of the stragglers.
+    /// Returns the number of connections that were closed forcibly.
+    ///
+    /// Connections tagged (see `HttpRequest::tag_connection`) with a tag whose
+    /// policy is `DrainPolicy::Close` are closed first, without waiting.
+    pub fn drain(&self, timeout: Duration) -> usize {
+        self.shutdown.store(true, Ordering::SeqCst);
+        self.connections
+            .drain(Instant::now() + timeout, &self.drain_policies)
+    }
+
+    /// How `drain` treats connections tagged `tag`. Untagged connections, and tags
+    /// without a policy, are waited for.
+    pub fn set_drain_policy(&mut self, tag: impl Into<String>, policy: DrainPolicy) {
+        self.drain_policies.insert(tag.into(), policy);
+    }
+
+    /// Shuts down every live connection tagged `tag` now, after writing `farewell`
+    /// to each. Returns how many were closed.
+    pub fn close_tagged(&self, tag: &str, farewell: &[u8]) -> usize {
+        self.connections.close_tagged(tag, farewell)
+    }
+
+    /// Ends `incoming()` cleanly on SIGINT/SIGTERM; see `ShutdownHandle::shutdown_on_signals`.
+    #[cfg(all(feature = "signals", unix))]
+    pub fn shutdown_on_signals(&self) -> io::Result<()> {
+        self.shutdown_handle()?.shutdown_on_signals()
+    }
+
+    /// Fails with `RecvError::TimedOut` right away when no connection is pending, for
+    /// embedding the server in an existing event or game loop. Once a connection is
+    /// picked up, its request is still read with the usual (blocking) timeouts.
+    pub fn try_recv(&mut self) -> std::result::Result<HttpRequest, RecvError> {
+        self.recv_timeout(Duration::ZERO)
+    }
+
+    /// Hands at most `max_requests` requests to `handler`, returning early once
+    /// `max_duration` has passed without waiting for more. Returns how many were
+    /// handled; failed requests are not counted and only reach `on_error`.
+    pub fn poll_once<F>(&mut self, max_requests: usize, max_duration: Duration, mut handler: F) -> usize
+    where
+        F: FnMut(HttpRequest),
+    {
+        let deadline = Instant::now() + max_duration;
+        let mut served = 0;
+        while served < max_requests {
+            match self.next_request(Some(deadline)) {
+                None | Some(Ok(None)) => break,
+                Some(Ok(Some(req))) => {
+                    handler(req);
+                    served += 1;
+                }
+                Some(Err(_)) => {}
+            }
+        }
+        served
+    }
+
+    pub fn incoming_timeout(&mut self, timeout: Duration) -> IncomingTimeout<'_> {
+        IncomingTimeout {
+            server: self,
+            timeout,
+        }
+    }
+
+    /// Address of the first TCP listener, e.g. to find the port picked for
+    /// `bind("127.0.0.1:0")`.
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listeners
+            .iter()
+            .find_map(|listener| match listener {
+                Listener::Tcp(listener) => Some(listener.local_addr()),
+                #[cfg(unix)]
+                Listener::Unix(_) => None,
+                #[cfg(all(feature = "tls", unix))]
+                Listener::Gateway(_) => None,
+            })
+            .unwrap_or_else(|| {
+                Err(io::Error::new(io::ErrorKind::NotConnected, "no TCP listener"))
+            })
+    }
+
+    /// Adds a listener on `addr` whose connections, kept-alive ones included, are
+    /// served ahead of everyone else's whenever several are waiting. Put health checks
+    /// and admin endpoints there to keep them responsive under load. Returns the
+    /// bound address.
+    pub fn bind_priority(&mut self, addr: impl ToSocketAddrs) -> io::Result<SocketAddr> {
+        let listener = TcpListener::bind(addr)?;
+        let local_addr = listener.local_addr()?;
+        self.priority_listeners.push(self.listeners.len());
+        self.listeners.push(Listener::Tcp(listener));
+        Ok(local_addr)
+    }
+
+    pub fn shutdown_handle(&self) -> io::Result<ShutdownHandle> {
+        let listener = self
+            .listeners
+            .first()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotConnected, "server is unbound"))?;
+        Ok(ShutdownHandle::new(self.shutdown.clone(), listener.wake_addr()?))
+    }
+
+    /// Reads exactly one request from a connection accepted elsewhere, using this
+    /// server's limits, timeouts and hooks. The connection is closed after the
+    /// response, whatever the keep-alive setting.
+    pub fn recv_from(&mut self, stream: Stream, peer: PeerAddr) -> io::Result<HttpRequest> {
+        if !self.admits(&peer) {
+            return Err(io::Error::new(io::ErrorKind::PermissionDenied, "connection refused by filter"));
+        }
+        let (stream, mut conn) = self.new_connection(stream, peer)?;
+        let read = self.read_request(stream, &mut conn).and_then(|req| {
+            req.ok_or_else(|| io::Error::other("request answered before it was handed out"))
+        });
+        match read {
+            Ok(mut req) => {
+                req.idle = None;
+                req.memo = None;
+                self.hooks.request(&req);
+                Ok(req)
+            }
+            Err(e) => {
+                conn.in_span(|| log_read_error(&conn.addr, &e));
+                self.hooks.error(&e);
+                self.hooks.close(&conn.addr, &conn.stats());
+                Err(e)
+            }
+        }
+    }
+
+    /// inetd-style: the connection is stdin/stdout. When stdin is a TCP socket, as
+    /// under inetd, it is used directly so that timeouts and the peer address work.
+    pub fn recv_stdio(&mut self) -> io::Result<HttpRequest> {
+        #[cfg(unix)]
+        {
+            use std::net::TcpStream;
+            use std::os::fd::FromRawFd;
+
+            let fd = unsafe { libc::dup(libc::STDIN_FILENO) };
+            if fd >= 0 {
+                // SAFETY: `fd` was just duplicated and is owned by nobody else.
+                let stream = unsafe { TcpStream::from_raw_fd(fd) };
+                if let Ok(peer) = stream.peer_addr() {
+                    return self.recv_from(Stream::Tcp(stream), PeerAddr::Tcp(peer));
+                }
+            }
+        }
+        self.recv_from(Stream::Stdio, PeerAddr::Unknown)
+    }
+}
+
+impl TryFrom<TcpListener> for Server {
+    type Error = io::Error;
+
+    fn try_from(listener: TcpListener) -> io::Result<Self> {
+        Self::from_listener(listener)
     }
 }
 
+/// A parsed request. It owns its buffers and connection, so it can be moved to a
+/// worker thread while the server goes on accepting.
 #[derive(Debug)]
 pub struct HttpRequest {
-    pub peer_addr: SocketAddr,
+    pub peer_addr: PeerAddr,
 
-    header_buf: BytesMut,
-    request: Request<BytesMut>,
-    stream: TcpStream,
+    header_buf: PooledBuf,
+    head: Head,
+    /// Moved into `request` once that is built.
+    body: Mutex<Option<BytesMut>>,
+    request: OnceLock<Request<BytesMut>>,
+    stream: Stream,
+    codecs: Arc<CodecRegistry>,
+    trusted_proxies: Arc<[IpCidr]>,
+    hooks: Arc<Hooks>,
+    conn: ConnState,
+    bytes_written: AtomicUsize,
+    received_at: Instant,
+    /// The status sent, or 0 before a response.
+    status: AtomicU16,
+    #[cfg(feature = "tracing")]
+    span: tracing::Span,
+    /// Where the connection goes after the response if it may be kept alive.
+    idle: Option<Arc<IdleSet>>,
+    reusable: AtomicBool,
+    memo: Option<Mutex<Option<Memo>>>,
+    connection: ConnectionGuard,
+    /// A chunked body left on the connection, with what of it was read ahead and
+    /// how much to drain.
+    unread: Option<(BytesMut, usize)>,
+    trailers: HeaderMap,
+}
+
+// Worker pools depend on this; keep it from regressing silently.
+const _: fn() = || {
+    fn assert_send<T: Send + 'static>() {}
+    assert_send::<HttpRequest>();
+};
+
+/// What is known about a request without building its `HeaderMap`.
+#[derive(Debug)]
+struct Head {
+    method: Method,
+    uri: Uri,
+    version: Version,
+    /// Name and value of every header, as ranges of `header_buf`.
+    headers: Vec<(Range<usize>, Range<usize>)>,
 }
 
 impl HttpRequest {
@@ -61,70 +770,432 @@ impl HttpRequest {
         &self.header_buf
     }
 
-    pub unsafe fn stream(&self) -> &TcpStream {
+    // These shadow the `Request` methods reached through `Deref`, so that they work
+    // without building the request when its headers are lazy.
+
+    pub fn method(&self) -> &Method {
+        self.request.get().map_or(&self.head.method, Request::method)
+    }
+
+    pub fn uri(&self) -> &Uri {
+        self.request.get().map_or(&self.head.uri, Request::uri)
+    }
+
+    pub fn version(&self) -> Version {
+        self.request.get().map_or(self.head.version, Request::version)
+    }
+
+    /// The first header named `name` (case-insensitive) as received. Unlike
+    /// `headers()`, this never builds the `HeaderMap` of a lazily parsed request.
+    pub fn raw_header(&self, name: &str) -> Option<&[u8]> {
+        self.head
+            .headers
+            .iter()
+            .find(|(n, _)| self.header_buf[n.clone()].eq_ignore_ascii_case(name.as_bytes()))
+            .map(|(_, v)| &self.header_buf[v.clone()])
+    }
+
+    /// The user and password of an `Authorization: Basic` header.
+    pub fn basic_auth(&self) -> Option<(String, String)> {
+        middleware::auth::basic_credentials(self.raw_header("authorization")?)
+    }
+
+    /// The token of an `Authorization: Bearer` header.
+    pub fn bearer_token(&self) -> Option<&str> {
+        let token = middleware::auth::strip_scheme(self.raw_header("authorization")?, b"bearer")?;
+        std::str::from_utf8(token).ok().filter(|token| !token.is_empty())
+    }
+
+    fn request(&self) -> &Request<BytesMut> {
+        self.request.get_or_init(|| {
+            let body = self.body.lock().unwrap().take().unwrap_or_default();
+            let mut request = Request::new(body);
+            *request.method_mut() = self.head.method.clone();
+            *request.uri_mut() = self.head.uri.clone();
+            *request.version_mut() = self.head.version;
+            let headers = request.headers_mut();
+            for (name, value) in &self.head.headers {
+                // The parser has already vetted these bytes.
+                let name = header::HeaderName::from_bytes(&self.header_buf[name.clone()]);
+                let value = header::HeaderValue::from_bytes(&self.header_buf[value.clone()]);
+                if let (Ok(name), Ok(value)) = (name, value) {
+                    headers.append(name, value);
+                }
+            }
+            request
+        })
+    }
+
+    /// The client's IP address: `peer_addr`, or, when that is a proxy trusted
+    /// with `Server::set_trusted_proxies`, the address the proxies forwarded the
+    /// request for. `None` for peers without an IP address and no trusted
+    /// forwarding headers.
+    pub fn client_addr(&self) -> Option<IpAddr> {
+        let peer = self.peer_addr.as_tcp().map(|addr| addr.ip());
+        forwarded::client_ip(peer, self.headers(), &self.trusted_proxies)
+    }
+
+    /// When the request's header was read; `ResponseInfo::duration` is measured
+    /// from here.
+    pub fn received_at(&self) -> Instant {
+        self.received_at
+    }
+
+    /// This request's span, a child of its connection's, carrying its method, path,
+    /// peer and, once answered, status. Enter it in the handler so that its events
+    /// are tied to the request:
+    ///
+    /// ```no_run
+    /// # fn handle(req: blocking_http_server::HttpRequest) {
+    /// let _entered = req.span().enter();
+    /// tracing::info!("handling");
+    /// # }
+    /// ```
+    #[cfg(feature = "tracing")]
+    pub fn span(&self) -> &tracing::Span {
+        &self.span
+    }
+
+    /// Trailer fields sent after a chunked body, e.g. `content-md5`. Empty unless
+    /// `Server::set_chunked_body_limit` had the body read.
+    pub fn trailers(&self) -> &HeaderMap {
+        &self.trailers
+    }
+
+    /// # Safety
+    ///
+    /// Reading from or writing to the stream directly can corrupt the HTTP
+    /// exchange that `respond` relies on.
+    pub unsafe fn stream(&self) -> &Stream {
         &self.stream
     }
 
+    /// Accepts a `CONNECT` request: sends `200 Connection Established` and hands
+    /// over the client connection, to relay bytes to and from the requested
+    /// authority. Refuse a tunnel with `respond` instead. `proxy::tunnel` does
+    /// the relaying for plain TCP targets.
+    ///
+    /// The server stops tracking the connection once it is handed over, so
+    /// `Server::drain` doesn't wait for it.
+    pub fn into_tunnel(self) -> io::Result<Stream> {
+        if self.method() != Method::CONNECT {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "not a CONNECT request"));
+        }
+        let stream = self.stream.try_clone()?;
+        let mut established = self.writer();
+        established.write_all(b"HTTP/1.1 200 Connection Established\r\n\r\n")?;
+        established.flush()?;
+        self.sent(StatusCode::OK);
+        Ok(stream)
+    }
+
+    /// Accepts an `Upgrade` request, e.g. to `websocket`: sends `101 Switching
+    /// Protocols` to `protocol`, with `headers`, and hands over the client
+    /// connection to speak it. Refuse the upgrade with `respond` instead.
+    ///
+    /// As with `into_tunnel`, the server stops tracking the connection once it is
+    /// handed over.
+    pub fn into_upgraded(self, protocol: &str, headers: &HeaderMap) -> io::Result<Stream> {
+        if self.version() != Version::HTTP_11 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "upgrades need HTTP/1.1"));
+        }
+        let stream = self.stream.try_clone()?;
+        let mut switching = self.writer();
+        write!(switching, "HTTP/1.1 101 Switching Protocols\r\nconnection: upgrade\r\nupgrade: {protocol}\r\n")?;
+        write_headers(&mut switching, headers)?;
+        switching.write_all(b"\r\n")?;
+        switching.flush()?;
+        self.sent(StatusCode::SWITCHING_PROTOCOLS);
+        Ok(stream)
+    }
+
+    /// Labels this connection, e.g. `"websocket"`, for the lifetime of this
+    /// request, so that `Server::drain` and `Server::close_tagged` can treat it
+    /// specially.
+    pub fn tag_connection(&self, tag: impl Into<String>) {
+        self.connection.tag(tag.into());
+    }
+
+    /// Decoded query string parameters. Use `Query::with_policy` to pick how
+    /// repeated keys are resolved.
+    pub fn query_params(&self) -> Query {
+        Query::parse(self.uri().query().unwrap_or(""))
+    }
+
+    /// The decoded query string pairs, in order, repeated keys included. Borrows
+    /// from the URI, so nothing is copied unless it had to be decoded.
+    pub fn query_pairs(&self) -> impl Iterator<Item = (Cow<'_, str>, Cow<'_, str>)> {
+        query::pairs(self.uri().query().unwrap_or(""))
+    }
+
+    /// The decoded value of the first `name` query parameter; `?flag` gives an
+    /// empty one.
+    pub fn query(&self, name: &str) -> Option<Cow<'_, str>> {
+        self.query_pairs().find(|(key, _)| key == name).map(|(_, value)| value)
+    }
+
+    /// Decodes the body with the codec registered for `T` and the request's `content-type`.
+    pub fn decode<T: 'static>(&self) -> io::Result<T> {
+        let content_type = self
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or("");
+        self.codecs.decode(content_type, self.body())
+    }
+
+    pub fn respond_encoded<T: 'static>(&self, value: &T, content_type: &str) -> io::Result<()> {
+        let body = self.codecs.encode(content_type, value)?;
+        let response = Response::builder()
+            .header(header::CONTENT_TYPE, content_type)
+            .body(body)
+            .map_err(io::Error::other)?;
+        self.respond(response)
+    }
+
+    /// Sends an interim `1xx` response, e.g. `103 Early Hints` with `link` headers,
+    /// ahead of the final one from `respond`. Skipped for HTTP/1.0 clients, which
+    /// don't expect them.
+    ///
+    /// ```no_run
+    /// # fn handle(req: blocking_http_server::HttpRequest) -> std::io::Result<()> {
+    /// use blocking_http_server::*;
+    ///
+    /// let mut hints = HeaderMap::new();
+    /// hints.insert(header::LINK, HeaderValue::from_static("</style.css>; rel=preload; as=style"));
+    /// let early_hints = StatusCode::from_u16(103).unwrap();
+    /// req.send_informational(early_hints, &hints)?;
+    /// // ... render the page ...
+    /// req.respond(Response::new(b"<html>...</html>".to_vec()))
+    /// # }
+    /// ```
+    pub fn send_informational(&self, status: StatusCode, headers: &HeaderMap) -> io::Result<()> {
+        if !status.is_informational() || status == StatusCode::SWITCHING_PROTOCOLS {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "not an interim status; 101 goes through `into_upgraded`",
+            ));
+        }
+        if self.version() < Version::HTTP_11 {
+            return Ok(());
+        }
+        // `http` predates 103 Early Hints and has no reason phrase for it.
+        let reason = match status.as_u16() {
+            103 => "Early Hints",
+            _ => status.canonical_reason().unwrap_or("Unknown"),
+        };
+        let mut stream = self.writer();
+        HEAD_BUF.with_borrow_mut(|head| {
+            head.clear();
+            write!(head, "HTTP/1.1 {} {}\r\n", status.as_str(), reason)?;
+            write_headers(&mut *head, headers)?;
+            head.extend_from_slice(b"\r\n");
+            stream.write_all(head)?;
+            stream.flush()
+        })
+    }
+
     pub fn respond<T: AsRef<[u8]>>(
         &self,
         response: impl std::borrow::Borrow<Response<T>>,
     ) -> io::Result<()> {
-        let version = self.version();
-        let mut stream = &self.stream;
+        let mut stream = self.writer();
 
         let response: &Response<T> = response.borrow();
-        // let version = response.version();
         let status = response.status();
         let headers = response.headers();
         let body = response.body().as_ref();
 
+        let close = self.closes_after(headers);
+        HEAD_BUF.with_borrow_mut(|head| {
+            // An empty response to HEAD says nothing about the length of the GET one.
+            let unknown = self.method() == Method::HEAD && body.is_empty();
+            let length = !bodiless(status) && !headers.contains_key(header::CONTENT_LENGTH) && !unknown;
+            self.write_head(head, status, headers, close, |head| {
+                if length {
+                    write!(head, "content-length: {}\r\n", body.len())?;
+                }
+                Ok(())
+            })?;
+
+            // A response to HEAD describes the body, content-length included, without
+            // sending it.
+            let body = if self.method() == Method::HEAD { &[] } else { body };
+            write_all_vectored(&mut stream, head, body)?;
+            stream.flush()
+        })?;
+
+        if let Some(memo) = &self.memo {
+            *memo.lock().unwrap() = Memo::new(self.request(), response);
+        }
+        self.sent(status);
+        self.reusable.store(!close, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Sends the status and headers of `head`, leaving the body to be streamed
+    /// through the returned writer with `Transfer-Encoding: chunked`. Trailers
+    /// sent by `ChunkedWriter::finish` must be announced in a `trailer` header
+    /// here.
+    ///
+    /// If `head` has a `content-length`, the body is sent as is instead and must
+    /// be exactly that long. HTTP/1.0 clients get a body without one unframed,
+    /// ended by closing the connection.
+    ///
+    /// ```no_run
+    /// # fn handle(req: blocking_http_server::HttpRequest) -> std::io::Result<()> {
+    /// use std::io::Write;
+    /// use blocking_http_server::*;
+    ///
+    /// let head = Response::new(()).with_header(header::TRAILER, HeaderValue::from_static("x-checksum"));
+    /// let mut body = req.respond_chunked(head)?;
+    /// body.write_all(b"hello, ")?;
+    /// body.write_all(b"world")?;
+    /// let mut trailers = HeaderMap::new();
+    /// trailers.insert("x-checksum", HeaderValue::from_static("2a"));
+    /// body.finish(&trailers)
+    /// # }
+    /// ```
+    pub fn respond_chunked(&self, head: Response<()>) -> io::Result<ChunkedWriter<'_>> {
+        let mut stream = self.writer();
+        let headers = head.headers();
+        let length = headers.get(header::CONTENT_LENGTH).map(|v| {
+            let length = v.to_str().ok().and_then(|v| v.parse().ok());
+            length.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "invalid content-length"))
+        });
+        let length: Option<u64> = length.transpose()?;
+        let chunked = length.is_none() && !bodiless(head.status()) && self.version() >= Version::HTTP_11;
+        let framing = match length {
+            _ if bodiless(head.status()) || self.method() == Method::HEAD => Framing::None,
+            Some(length) => Framing::Length(length),
+            None if chunked => Framing::Chunked,
+            None => Framing::Close,
+        };
+        let close = framing == Framing::Close || self.closes_after(headers);
+        HEAD_BUF.with_borrow_mut(|buf| {
+            // A HEAD response describes the GET one, chunking included.
+            self.write_head(buf, head.status(), headers, close, |buf| {
+                if chunked {
+                    buf.extend_from_slice(b"transfer-encoding: chunked\r\n");
+                }
+                Ok(())
+            })?;
+            stream.write_all(buf)?;
+            stream.flush()
+        })?;
+
+        if let Some(memo) = &self.memo {
+            *memo.lock().unwrap() = Memo::new(self.request(), &head);
+        }
+        self.sent(head.status());
+        Ok(ChunkedWriter::new(stream, headers, framing, &self.reusable, close))
+    }
+
+    /// The connection as responses are written to it: counted and throttled.
+    fn writer(&self) -> CountingWriter<'_, Throttled<'_, &Stream>> {
+        CountingWriter::new(self.conn.writes.writer(&self.stream), &self.bytes_written)
+    }
+
+    /// Notes the status of the response just sent, for `on_response` and the
+    /// request's span.
+    fn sent(&self, status: StatusCode) {
+        self.status.store(status.as_u16(), Ordering::Relaxed);
+        #[cfg(feature = "tracing")]
+        self.span.record("status", status.as_u16());
+    }
+
+    fn closes_after(&self, headers: &HeaderMap) -> bool {
+        match headers.get(header::CONNECTION) {
+            Some(v) => v.as_bytes().eq_ignore_ascii_case(b"close"),
+            None => self.idle.is_none(),
+        }
+    }
+
+    /// Serializes a response head into `head`, with the `connection` and `date`
+    /// headers `headers` lacks and whatever `framing` writes.
+    fn write_head(
+        &self,
+        head: &mut Vec<u8>,
+        status: StatusCode,
+        headers: &HeaderMap,
+        close: bool,
+        framing: impl FnOnce(&mut Vec<u8>) -> io::Result<()>,
+    ) -> io::Result<()> {
+        head.clear();
         write!(
-            stream,
+            head,
             "{:?} {} {}\r\n",
-            version,
+            self.version(),
             status.as_str(),
             status.canonical_reason().unwrap_or("Unknown"),
         )?;
-
-        // println!("write_response: {}", text);
-
-        // if !headers.contains_key(header::DATE) {
-        //     let date = time::strftime("%a, %d %b %Y %H:%M:%S GMT", &time::now_utc()).unwrap();
-        //     write!(stream, "date: {}\r\n", date)?;
-        // }
         if !headers.contains_key(header::CONNECTION) {
-            write!(stream, "connection: close\r\n")?;
+            if close {
+                head.extend_from_slice(b"connection: close\r\n");
+            } else if self.version() == Version::HTTP_10 {
+                // HTTP/1.0 clients assume the connection closes unless told otherwise.
+                head.extend_from_slice(b"connection: keep-alive\r\n");
+            }
         }
-        if !headers.contains_key(header::CONTENT_LENGTH) {
-            write!(stream, "content-length: {}\r\n", body.len())?;
+        framing(head)?;
+        if !headers.contains_key(header::DATE) {
+            write_date(head);
         }
-        for (k, v) in headers.iter() {
-            write!(
-                stream,
-                "{}: {}\r\n",
-                k.as_str(),
-                v.to_str().unwrap_or("unknown")
-            )?;
+        write_headers(&mut *head, headers)?;
+        head.extend_from_slice(b"\r\n");
+        Ok(())
+    }
+}
+
+impl Drop for HttpRequest {
+    fn drop(&mut self) {
+        if self.hooks.response.is_some() {
+            let info = ResponseInfo {
+                status: StatusCode::from_u16(self.status.load(Ordering::Relaxed)).ok(),
+                bytes_written: self.bytes_written.load(Ordering::Relaxed),
+                duration: self.received_at.elapsed(),
+            };
+            self.hooks.response(self, &info);
         }
+        // The body shares the header buffer's allocation; release it first so the
+        // buffer can be reclaimed whole by the pool.
+        if let Ok(body) = self.body.get_mut() {
+            body.take();
+        }
+        self.request.take();
 
-        stream.write_all(b"\r\n")?;
-        stream.write_all(body)?;
-        stream.flush()?;
+        let mut conn = self.conn.clone();
+        conn.bytes_written += self.bytes_written.load(Ordering::Relaxed);
+        conn.requests += 1;
 
-        Ok(())
+        let mut reusable = self.reusable.load(Ordering::Relaxed);
+        if let Some((pending, limit)) = self.unread.take() {
+            reusable = reusable && unread::drain_chunked(self.conn.reads.reader(&self.stream), &pending, limit);
+        }
+        if let (Some(idle), true) = (&self.idle, reusable) {
+            if let Some(memo) = &self.memo {
+                conn.memo = memo.lock().unwrap().take();
+            }
+            if let Ok(stream) = self.stream.try_clone() {
+                idle.park(stream, conn);
+                return;
+            }
+        }
+        self.hooks.close(&conn.addr, &conn.stats());
     }
 }
 
 impl Deref for HttpRequest {
     type Target = Request<BytesMut>;
     fn deref(&self) -> &Self::Target {
-        &self.request
+        self.request()
     }
 }
 
 impl DerefMut for HttpRequest {
     fn deref_mut(&mut self) -> &mut Self::Target {
-        &mut self.request
+        self.request();
+        self.request.get_mut().expect("request was just built")
     }
 }
 
@@ -135,51 +1206,531 @@ pub struct Incoming<'a> {
 impl Iterator for Incoming<'_> {
     type Item = io::Result<HttpRequest>;
     fn next(&mut self) -> Option<Self::Item> {
-        let (mut stream, addr) = match self.server.listener.accept() {
-            Ok((stream, addr)) => {
-                let _ = stream.set_nodelay(true);
-                (stream, addr)
+        match self.server.next_request(None)? {
+            Ok(req) => req.map(Ok),
+            Err(e) => Some(Err(e)),
+        }
+    }
+}
+
+/// Like `Incoming`, but yields `Ok(None)` whenever no request arrives within the
+/// timeout, giving the loop a chance to do periodic work.
+pub struct IncomingTimeout<'a> {
+    server: &'a mut Server,
+    timeout: Duration,
+}
+
+impl Iterator for IncomingTimeout<'_> {
+    type Item = io::Result<Option<HttpRequest>>;
+    fn next(&mut self) -> Option<Self::Item> {
+        self.server.next_request(Some(Instant::now() + self.timeout))
+    }
+}
+
+// Short-lived and never stored, so the size difference does not matter.
+#[allow(clippy::large_enum_variant)]
+enum Ready {
+    Listener(usize),
+    Idle(Stream, ConnState),
+    #[cfg(not(unix))]
+    Accepted(usize, io::Result<(Stream, PeerAddr)>),
+    TimedOut,
+}
+
+impl Server {
+    /// `Ok(None)` once `deadline` has passed; `None` once the server is shut down.
+    fn next_request(&mut self, deadline: Option<Instant>) -> Option<io::Result<Option<HttpRequest>>> {
+        if self.listeners.is_empty() {
+            return None;
+        }
+        loop {
+            let accepted = match self.wait_readable(deadline)? {
+                Ready::TimedOut => return Some(Ok(None)),
+                Ready::Idle(stream, conn) => Ok((stream, conn)),
+                Ready::Listener(i) => match self.accept(i)? {
+                    #[cfg(all(feature = "tls", unix))]
+                    Ok((stream, addr)) if self.terminates_tls(i) => {
+                        if self.admits(&addr) {
+                            self.start_tls(i, stream, addr);
+                        }
+                        continue;
+                    }
+                    Ok((_, addr)) if !self.is_gateway(i) && !self.admits(&addr) => continue,
+                    Ok((stream, addr)) if !self.make_room(&stream, &addr) => continue,
+                    accepted => accepted
+                        .and_then(|(s, a)| self.new_connection(s, a))
+                        .map(|(s, c)| (s, self.mark_priority(i, c))),
+                },
+                #[cfg(not(unix))]
+                Ready::Accepted(i, accepted) => match accepted {
+                    Ok((_, addr)) if !self.admits(&addr) => continue,
+                    Ok((stream, addr)) if !self.make_room(&stream, &addr) => continue,
+                    accepted => accepted
+                        .and_then(|(s, a)| self.new_connection(s, a))
+                        .map(|(s, c)| (s, self.mark_priority(i, c))),
+                },
+            };
+            let (stream, mut conn) = match accepted {
+                Ok(accepted) => accepted,
+                Err(e) => {
+                    warn!("accept failed: {e}");
+                    self.hooks.error(&e);
+                    return Some(Err(e));
+                }
+            };
+
+            match self.read_request(stream, &mut conn) {
+                Ok(Some(req)) => {
+                    if self.answered_from_memo(&req) || self.answered_overloaded(&req) {
+                        continue;
+                    }
+                    self.hooks.request(&req);
+                    return Some(Ok(Some(req)));
+                }
+                // A kept-alive connection closed by the peer, or a request answered by the
+                // preview filter.
+                Ok(None) => self.hooks.close(&conn.addr, &conn.stats()),
+                Err(e) => {
+                    conn.in_span(|| log_read_error(&conn.addr, &e));
+                    self.hooks.error(&e);
+                    self.hooks.close(&conn.addr, &conn.stats());
+                    return Some(Err(e));
+                }
             }
-            Err(e) => return Some(Err(e)),
+        }
+    }
+
+    fn admits(&self, addr: &PeerAddr) -> bool {
+        let admitted = self
+            .connection_filter
+            .as_ref()
+            .is_none_or(|filter| filter(addr) == Admission::Accept);
+        if !admitted {
+            debug!("refused connection from {addr}");
+        }
+        admitted
+    }
+
+    /// Whether there is room under `connection_limit` for one more connection,
+    /// closing idle ones or waiting for requests to finish as needed.
+    fn make_room(&self, stream: &Stream, addr: &PeerAddr) -> bool {
+        let Some(limit) = self.connection_limit else {
+            return true;
         };
+        loop {
+            if self.connections.active() + self.idle.len() < limit.max() {
+                return true;
+            }
+            if let Some((_, conn)) = self.idle.take_oldest() {
+                self.hooks.close(&conn.addr, &conn.stats());
+                continue;
+            }
+            match limit {
+                ConnectionLimit::Shed(_) => {
+                    let service_time = self.connections.service_time().unwrap_or(Duration::from_secs(1));
+                    let retry_after = service_time.as_secs_f64().ceil().max(1.0) as u64;
+                    debug!("{addr}: {} connections open, answering 503", limit.max());
+                    self.connections.record_shed();
+                    shed(stream, retry_after);
+                    return false;
+                }
+                // Whoever is next in the backlog waits there.
+                ConnectionLimit::Queue(_) if !self.shutdown.load(Ordering::SeqCst) => {
+                    std::thread::sleep(Duration::from_millis(10));
+                }
+                ConnectionLimit::Queue(_) => return false,
+            }
+        }
+    }
 
-        {
-            // prepare the buffer
-            let buf = &mut self.server.buf;
-            buf.clear();
-            if self.server.req_size_limit > buf.capacity() {
-                // This will not cause reallocation, because the `split_off`ed header_buf and body_buf are dropped at this point.
-                buf.reserve(self.server.req_size_limit - buf.capacity());
+    /// Whether `listener` is the front end's, whose connections were admitted
+    /// when it accepted them.
+    fn is_gateway(&self, listener: usize) -> bool {
+        match &self.listeners[listener] {
+            #[cfg(all(feature = "tls", unix))]
+            Listener::Gateway(_) => true,
+            _ => false,
+        }
+    }
+
+    fn mark_priority(&self, listener: usize, mut conn: ConnState) -> ConnState {
+        conn.priority = self.priority_listeners.contains(&listener);
+        conn
+    }
+
+    fn new_connection(
+        &mut self,
+        stream: Stream,
+        addr: PeerAddr,
+    ) -> io::Result<(Stream, ConnState)> {
+        self.hooks.accept(&addr);
+        let mut conn = ConnState::new(addr);
+        conn.reads.conn = self.read_rate_limit.map(Throttle::new);
+        conn.reads.total = self.total_read_throttle.clone();
+        conn.writes.conn = self.write_rate_limit.map(Throttle::new);
+
+        if let Some(stream) = stream.as_tcp() {
+            let _ = stream.set_nodelay(self.nodelay);
+            let socket = socket2::SockRef::from(stream);
+            if let Some(idle) = self.tcp_keepalive {
+                let keepalive = socket2::TcpKeepalive::new().with_time(idle);
+                #[cfg(any(
+                    target_os = "linux",
+                    target_os = "android",
+                    target_os = "macos",
+                    target_os = "ios",
+                    target_os = "freebsd",
+                    target_os = "netbsd",
+                    windows,
+                ))]
+                let keepalive = keepalive.with_interval(idle);
+                let _ = socket.set_tcp_keepalive(&keepalive);
             }
+            if self.linger.is_some() {
+                let _ = socket.set_linger(self.linger);
+            }
+        }
+        // Reads rely on blocking (with timeouts); a listener in non-blocking mode may
+        // hand out non-blocking streams on some platforms.
+        let prepared = stream
+            .set_nonblocking(false)
+            .and_then(|_| stream.set_read_timeout(self.read_timeout))
+            .and_then(|_| stream.set_write_timeout(self.write_timeout));
+        if let Err(e) = prepared {
+            self.hooks.close(&conn.addr, &conn.stats());
+            return Err(e);
+        }
+        Ok((stream, conn))
+    }
+
+    /// Blocks until the listener or an idle kept-alive connection is readable, or
+    /// `deadline` passes. `None` once the server is shut down.
+    #[cfg(unix)]
+    fn wait_readable(&mut self, deadline: Option<Instant>) -> Option<Ready> {
+        use std::os::fd::AsRawFd;
+
+        let keep_alive = self.keep_alive_enabled();
+        if !keep_alive && deadline.is_none() && self.listeners.len() == 1 {
+            return Some(Ready::Listener(0));
+        }
+
+        let idle_timeout = self.keep_alive.unwrap_or_default();
+        loop {
+            if self.shutdown.load(Ordering::SeqCst) {
+                return None;
+            }
+
+            let mut idle = if keep_alive {
+                self.idle.take_all()
+            } else {
+                Vec::new()
+            };
+            idle.retain(|(_, conn, since)| {
+                let alive = since.elapsed() < idle_timeout;
+                if !alive {
+                    self.hooks.close(&conn.addr, &conn.stats());
+                }
+                alive
+            });
+
+            let mut fds = vec![self.idle.waker_fd()];
+            fds.extend(self.listeners.iter().map(|l| l.as_raw_fd()));
+            fds.extend(idle.iter().map(|(stream, _, _)| stream.as_raw_fd()));
+            let mut timeout = idle
+                .iter()
+                .map(|(_, _, since)| idle_timeout.saturating_sub(since.elapsed()))
+                .min();
+            if let Some(deadline) = deadline {
+                let remaining = deadline.saturating_duration_since(Instant::now());
+                timeout = Some(timeout.map_or(remaining, |t| t.min(remaining)));
+            }
+
+            let ready = match keep_alive::poll_readable(&fds, timeout) {
+                Ok(ready) => ready,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {
+                    self.idle.put_back(idle);
+                    continue;
+                }
+                Err(_) => {
+                    // Without poll there is no way to watch idle connections.
+                    for (_, conn, _) in idle.iter() {
+                        self.hooks.close(&conn.addr, &conn.stats());
+                    }
+                    return Some(Ready::Listener(0));
+                }
+            };
+
+            let (waker, ready) = ready.split_first().unwrap();
+            let (listeners, idle_ready) = ready.split_at(self.listeners.len());
+            if *waker {
+                self.idle.drain_waker();
+            }
+            let is_priority = |i: &usize| self.priority_listeners.contains(i);
+            let priority_listener = (0..listeners.len()).find(|i| listeners[*i] && is_priority(i));
+            let priority_idle = idle_ready
+                .iter()
+                .zip(&idle)
+                .position(|(&r, (_, conn, _))| r && conn.priority);
+            if let (None, Some(i)) = (priority_idle, priority_listener) {
+                self.idle.put_back(idle);
+                return Some(Ready::Listener(i));
+            }
+
+            let fairness = self.keep_alive_fairness;
+            let had_turn = |conn: &ConnState| fairness.is_some_and(|n| conn.streak >= n);
+            let listener = listeners.iter().position(|&r| r);
+            let next_idle = priority_idle.or_else(|| {
+                idle_ready
+                    .iter()
+                    .zip(&idle)
+                    .position(|(&r, (_, conn, _))| r && (listener.is_none() || !had_turn(conn)))
+            });
+            if let Some(i) = next_idle {
+                let (stream, mut conn, _) = idle.swap_remove(i);
+                conn.streak += 1;
+                self.idle.put_back(idle);
+                return Some(Ready::Idle(stream, conn));
+            }
+            if let Some(i) = listener {
+                // Whoever was passed over goes first next time.
+                for ((_, conn, _), _) in idle.iter_mut().zip(idle_ready).filter(|(_, &r)| r) {
+                    conn.streak = 0;
+                }
+                self.idle.put_back(idle);
+                return Some(Ready::Listener(i));
+            }
+            self.idle.put_back(idle);
+            if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                return Some(Ready::TimedOut);
+            }
+        }
+    }
+
+    /// Without poll(2) a deadline, or more than one listener, is handled by retrying
+    /// non-blocking accepts.
+    #[cfg(not(unix))]
+    fn wait_readable(&mut self, deadline: Option<Instant>) -> Option<Ready> {
+        if deadline.is_none() && self.listeners.len() == 1 {
+            return Some(Ready::Listener(0));
+        }
+        let is_priority = |i: &usize| self.priority_listeners.contains(i);
+        let order: Vec<usize> = (0..self.listeners.len())
+            .filter(is_priority)
+            .chain((0..self.listeners.len()).filter(|i| !is_priority(i)))
+            .collect();
+        loop {
+            for &i in &order {
+                let listener = &self.listeners[i];
+                if self.shutdown.load(Ordering::SeqCst) {
+                    return None;
+                }
+                let _ = listener.set_nonblocking(true);
+                let result = listener.accept();
+                let _ = listener.set_nonblocking(false);
+                match result {
+                    Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+                    _ if self.shutdown.load(Ordering::SeqCst) => return None,
+                    Ok((stream, addr)) => {
+                        let result = stream.set_nonblocking(false).map(|_| (stream, addr));
+                        return Some(Ready::Accepted(i, result));
+                    }
+                    Err(e) => return Some(Ready::Accepted(i, Err(e))),
+                }
+            }
+            let remaining = deadline.map_or(Duration::MAX, |deadline| {
+                deadline.saturating_duration_since(Instant::now())
+            });
+            if remaining.is_zero() {
+                return Some(Ready::TimedOut);
+            }
+            std::thread::sleep(remaining.min(Duration::from_millis(10)));
+        }
+    }
+
+    /// Answers `req` with `304` if its connection memo says the client is up to date.
+    fn answered_from_memo(&self, req: &HttpRequest) -> bool {
+        let (Some(ttl), Some(memo)) = (self.validator_memo, &req.conn.memo) else {
+            return false;
+        };
+        if !memo.matches(req.request(), ttl) {
+            return false;
+        }
+        let mut response = Response::new(Vec::new());
+        *response.status_mut() = StatusCode::NOT_MODIFIED;
+        for (name, value) in memo.validators() {
+            response.headers_mut().insert(name, value.clone());
+        }
+        let _ = req.respond(response);
+        if let Some(slot) = &req.memo {
+            *slot.lock().unwrap() = Some(memo.clone());
+        }
+        true
+    }
+
+    /// Answers `req` with `503` if more than `overload_limit` requests are in flight,
+    /// `req` itself included.
+    fn answered_overloaded(&self, req: &HttpRequest) -> bool {
+        let Some(limit) = self.overload_limit else {
+            return false;
+        };
+        let in_flight = self.connections.active();
+        if in_flight <= limit {
+            return false;
+        }
+        // Roughly how long the requests ahead take to clear at the observed pace.
+        let service_time = self.connections.service_time().unwrap_or(Duration::from_secs(1));
+        let ahead = (in_flight - 1) as f64 / limit.max(1) as f64;
+        let retry_after = (service_time.as_secs_f64() * ahead).ceil().max(1.0) as u64;
+
+        debug!("{}: overloaded with {in_flight} requests in flight, answering 503", req.peer_addr);
+        req.connection.untimed();
+        self.connections.record_shed();
+        let mut response = Response::new(b"Service Unavailable".to_vec());
+        *response.status_mut() = StatusCode::SERVICE_UNAVAILABLE;
+        let headers = response.headers_mut();
+        headers.insert(header::RETRY_AFTER, retry_after.into());
+        headers.insert(header::CONNECTION, header::HeaderValue::from_static("close"));
+        let _ = req.respond(response);
+        true
+    }
+
+    /// `None` once the server is shut down.
+    fn accept(&mut self, listener: usize) -> Option<io::Result<(Stream, PeerAddr)>> {
+        let mut storm: Option<AcceptStorm> = None;
+        let result = loop {
+            let result = self.listeners[listener].accept();
+            if self.shutdown.load(Ordering::SeqCst) {
+                break None;
+            }
+            let (e, policy) = match (result, self.accept_backoff) {
+                (Err(e), Some(policy)) if backoff::is_transient(&e) => (e, policy),
+                (result, _) => break Some(result),
+            };
+            let storm = match storm.as_mut() {
+                Some(storm) => {
+                    if let Some(report) = storm.record(&policy, e) {
+                        self.report_accept_error(&report);
+                    }
+                    storm
+                }
+                None => {
+                    let (new, report) = AcceptStorm::new(&policy, e);
+                    self.report_accept_error(&report);
+                    storm.insert(new)
+                }
+            };
+            std::thread::sleep(storm.delay());
+        };
+        if let Some(storm) = storm {
+            self.report_accept_error(&storm.into_error());
         }
+        result
+    }
+
+    fn report_accept_error(&self, e: &io::Error) {
+        warn!("{e}");
+        self.hooks.error(e);
+    }
 
-        let mut header_buf = self.server.buf.split_off(0);
+    /// `Ok(None)` when a kept-alive connection is closed before a new request starts,
+    /// or when the preview filter has answered the request.
+    fn read_request(
+        &mut self,
+        stream: Stream,
+        conn: &mut ConnState,
+    ) -> io::Result<Option<HttpRequest>> {
+        // Each request owns its buffer, so it can outlive the next call to `recv`; the
+        // buffer goes back to the pool once the request is dropped.
+        if let Some(budget) = self.memory_budget {
+            if self.buffers.bytes_in_use() + self.buffers.size() > budget {
+                // Closing with the request unread would reset the connection, and
+                // the client might never see the answer.
+                discard_readable(&stream);
+                reject(&stream, StatusCode::SERVICE_UNAVAILABLE);
+                return Err(io::Error::new(io::ErrorKind::OutOfMemory, "memory budget exhausted"));
+            }
+        }
+        let mut header_buf = self.buffers.take();
+        let deadline = self.header_read_timeout.map(|t| Instant::now() + t);
 
         loop {
-            let mut tmp = header_buf.split_off(header_buf.len());
+            if let Some(deadline) = deadline {
+                let remaining = deadline.saturating_duration_since(Instant::now());
+                if remaining.is_zero() {
+                    reject(&stream, StatusCode::REQUEST_TIMEOUT);
+                    return Err(io::Error::new(
+                        io::ErrorKind::TimedOut,
+                        "request header deadline exceeded",
+                    ));
+                }
+                let timeout = self.read_timeout.map_or(remaining, |t| t.min(remaining));
+                stream.set_read_timeout(Some(timeout))?;
+            }
+
+            let filled = header_buf.len();
+            let mut tmp = header_buf.split_off(filled);
             unsafe { tmp.set_len(tmp.capacity()) };
 
-            match stream.read(&mut tmp) {
+            match conn.reads.reader(&stream).read(&mut tmp) {
                 Ok(0) => {
                     tmp.clear();
                     header_buf.unsplit(tmp);
-                    return Some(Err(io::Error::other("uncomplete request header")));
+                    if header_buf.is_empty() && conn.requests > 0 {
+                        return Ok(None);
+                    }
+                    return Err(io::Error::other("uncomplete request header"));
                 }
                 Ok(n) => {
+                    conn.bytes_read += n;
                     unsafe { tmp.set_len(n) };
                     header_buf.unsplit(tmp);
 
-                    let mut headers = [httparse::EMPTY_HEADER; Server::HEADER_COUNT_LIMIT];
-                    let mut req = httparse::Request::new(&mut headers);
+                    let request_line_len = header_buf.iter().position(|&b| b == b'\n');
+                    let too_long = match request_line_len {
+                        Some(len) => len > self.request_line_limit,
+                        None => {
+                            header_buf.len() > self.request_line_limit
+                                || header_buf.len() == header_buf.capacity()
+                        }
+                    };
+                    if too_long {
+                        reject(&stream, StatusCode::URI_TOO_LONG);
+                        return Err(io::Error::other("request line too long"));
+                    }
+
+                    let mut inline = [httparse::EMPTY_HEADER; Self::INLINE_HEADER_COUNT];
+                    let inline_count = self.header_count_limit.min(Self::INLINE_HEADER_COUNT);
+                    let mut req = httparse::Request::new(&mut inline[..inline_count]);
+                    let mut parsed = req.parse(&header_buf);
 
-                    let offset = match req.parse(&header_buf) {
+                    let mut spilled;
+                    if matches!(parsed, Err(httparse::Error::TooManyHeaders))
+                        && self.header_count_limit > Self::INLINE_HEADER_COUNT
+                    {
+                        spilled = vec![httparse::EMPTY_HEADER; self.header_count_limit];
+                        req = httparse::Request::new(&mut spilled);
+                        parsed = req.parse(&header_buf);
+                    }
+
+                    let offset = match parsed {
                         Ok(httparse::Status::Complete(offset)) => offset,
                         Ok(httparse::Status::Partial) => continue,
-                        Err(e) => {
-                            // eprintln!("error: {e}");
-                            return Some(Err(io::Error::other(e)));
+                        Err(_) if header_buf.starts_with(b"PRI * HTTP/2.0\r\n") => {
+                            #[cfg(all(feature = "h2", unix))]
+                            if !matches!(stream, Stream::Stdio) {
+                                let read = bytes::Bytes::copy_from_slice(&header_buf);
+                                self.front_end()?.serve_h2c(stream, read, None, conn.addr.clone());
+                                return Ok(None);
+                            }
+                            refuse_h2(&stream);
+                            return Err(io::Error::new(
+                                io::ErrorKind::InvalidData,
+                                "HTTP/2 with prior knowledge is not supported",
+                            ));
                         }
+                        Err(e) => return Err(io::Error::new(io::ErrorKind::InvalidData, e)),
                     };
+                    let received_at = Instant::now();
 
                     let version = match req.version {
                         Some(0) => Version::HTTP_10,
@@ -188,76 +1739,478 @@ impl Iterator for Incoming<'_> {
                         None => Version::HTTP_11,
                     };
 
+                    if !valid_host(req.headers) {
+                        reject(&stream, StatusCode::BAD_REQUEST);
+                        return Err(io::Error::new(io::ErrorKind::InvalidData, "invalid host header"));
+                    }
+
+                    if let Some(filter) = &self.preview_filter {
+                        let preview = Preview {
+                            method: req.method.unwrap_or("GET"),
+                            target: req.path.unwrap_or("/"),
+                            version,
+                            headers: req.headers,
+                        };
+                        if let Some(response) = filter(&preview) {
+                            conn.bytes_written += write_closing(&stream, version, &response)?;
+                            return Ok(None);
+                        }
+                    }
+
                     let uri: Uri = match req.path.unwrap_or("/").parse() {
                         Ok(uri) => uri,
                         Err(e) => {
-                            // eprintln!("error: {e}");
-                            return Some(Err(io::Error::other(e)));
+                            reject(&stream, StatusCode::BAD_REQUEST);
+                            return Err(io::Error::new(io::ErrorKind::InvalidData, e));
+                        }
+                    };
+
+                    let method = match Method::from_bytes(req.method.unwrap_or("GET").as_bytes()) {
+                        Ok(method) => method,
+                        Err(e) => {
+                            reject(&stream, StatusCode::BAD_REQUEST);
+                            return Err(io::Error::new(io::ErrorKind::InvalidData, e));
                         }
                     };
+                    let mut builder = (!self.lazy_headers).then(|| {
+                        Request::builder()
+                            .method(method.clone())
+                            .uri(uri.clone())
+                            .version(version)
+                    });
 
-                    let mut builder = Request::builder()
-                        .method(req.method.unwrap_or("GET"))
-                        .uri(uri)
-                        .version(version);
+                    let base = header_buf.as_ptr() as usize;
+                    let span = |s: &[u8]| {
+                        let start = s.as_ptr() as usize - base;
+                        start..start + s.len()
+                    };
+                    let mut header_spans = Vec::with_capacity(req.headers.len());
 
                     let mut content_len = 0;
-                    for header in req.headers {
-                        builder = builder.header(header.name, header.value);
+                    let mut expect_continue = false;
+                    let mut chunked = false;
+                    // HTTP/1.1 connections persist unless closed; HTTP/1.0 ones only
+                    // when the client asks.
+                    let mut persistent = version == Version::HTTP_11;
+                    let mut closed = false;
+                    #[cfg(any(feature = "compression-gzip", feature = "compression-br", feature = "compression-zstd"))]
+                    let mut content_encoding: Option<(Vec<u8>, Vec<usize>)> = None;
+                    for header in req.headers.iter() {
+                        #[cfg(any(feature = "compression-gzip", feature = "compression-br", feature = "compression-zstd"))]
+                        if header.name.eq_ignore_ascii_case(header::CONTENT_ENCODING.as_str())
+                            || header.name.eq_ignore_ascii_case(header::CONTENT_LENGTH.as_str())
+                        {
+                            // Indexes of the headers a decoded body makes wrong.
+                            let (codings, indexes) = content_encoding.get_or_insert_default();
+                            indexes.push(header_spans.len());
+                            if header.name.eq_ignore_ascii_case(header::CONTENT_ENCODING.as_str()) {
+                                if !codings.is_empty() {
+                                    codings.push(b',');
+                                }
+                                codings.extend_from_slice(header.value);
+                            }
+                        }
+                        header_spans.push((span(header.name.as_bytes()), span(header.value)));
+                        builder = builder.map(|b| b.header(header.name, header.value));
+
+                        if header.name.eq_ignore_ascii_case(header::CONNECTION.as_str()) {
+                            for option in header.value.split(|&b| b == b',') {
+                                let option = option.trim_ascii();
+                                closed |= option.eq_ignore_ascii_case(b"close");
+                                persistent |= option.eq_ignore_ascii_case(b"keep-alive");
+                            }
+                        }
+                    }
+                    let mut keep_alive = self.keep_alive_enabled() && persistent && !closed;
+                    let mut content_length_seen = None;
+                    for header in req.headers.iter() {
+                        // Chunked bodies aren't read, so the next request's start is unknown
+                        // unless the body is drained after the response.
+                        if header.name.eq_ignore_ascii_case(header::TRANSFER_ENCODING.as_str()) {
+                            chunked = header.value.trim_ascii_end().to_ascii_lowercase().ends_with(b"chunked");
+                            keep_alive &= chunked
+                                && (self.chunked_body_limit.is_some() || self.unread_body != UnreadBody::Close);
+                        }
 
                         if header.name.eq_ignore_ascii_case(header::CONTENT_LENGTH.as_str()) {
-                            content_len = std::str::from_utf8(header.value).unwrap_or("0").parse::<usize>().unwrap_or(0);
+                            // A length read differently here than by a proxy in front
+                            // smuggles a request into the kept-alive connection.
+                            content_len = match (parse_content_length(header.value), content_length_seen) {
+                                (Some(len), seen) if seen.is_none_or(|seen| seen == len) => len,
+                                _ => {
+                                    reject(&stream, StatusCode::BAD_REQUEST);
+                                    return Err(io::Error::new(io::ErrorKind::InvalidData, "invalid content-length"));
+                                }
+                            };
+                            content_length_seen = Some(content_len);
                             if content_len > header_buf.capacity() - offset {
-                                return Some(Err(io::Error::other("body too large")));
+                                reject(&stream, StatusCode::PAYLOAD_TOO_LARGE);
+                                return Err(io::Error::other("body too large"));
+                            }
+                        }
+
+                        if header.name.eq_ignore_ascii_case(header::EXPECT.as_str()) {
+                            if !header.value.eq_ignore_ascii_case(b"100-continue") {
+                                reject(&stream, StatusCode::EXPECTATION_FAILED);
+                                return Err(io::Error::other("unsupported expectation"));
                             }
+                            expect_continue = version == Version::HTTP_11;
                         }
                     }
 
+                    #[cfg(all(feature = "h2", unix))]
+                    if version == Version::HTTP_11 && content_len == 0 && !chunked && !matches!(stream, Stream::Stdio) {
+                        if let Some(upgraded) = http2::upgrade_request(&method, &uri, req.headers) {
+                            const SWITCHING: &[u8] = b"HTTP/1.1 101 Switching Protocols\r\nconnection: Upgrade\r\nupgrade: h2c\r\n\r\n";
+                            (&stream).write_all(SWITCHING)?;
+                            conn.bytes_written += SWITCHING.len();
+                            let read = bytes::Bytes::copy_from_slice(&header_buf[offset..]);
+                            self.front_end()?.serve_h2c(stream, read, Some(upgraded), conn.addr.clone());
+                            return Ok(None);
+                        }
+                    }
+
+                    if deadline.is_some() {
+                        stream.set_read_timeout(self.read_timeout)?;
+                    }
+
                     let mut body_buf = header_buf.split_off(offset);
                     if body_buf.capacity() < content_len {
-                        return Some(Err(io::Error::other("body too large")));
+                        reject(&stream, StatusCode::PAYLOAD_TOO_LARGE);
+                        return Err(io::Error::other("body too large"));
                     }
 
-                    if body_buf.len() >= content_len {
+                    let mut unread = None;
+                    let mut trailers = HeaderMap::new();
+                    if let (true, Some(limit)) = (chunked, self.chunked_body_limit) {
+                        if expect_continue {
+                            const CONTINUE: &[u8] = b"HTTP/1.1 100 Continue\r\n\r\n";
+                            (&stream).write_all(CONTINUE)?;
+                            conn.bytes_written += CONTINUE.len();
+                        }
+                        let pending = body_buf.split();
+                        let pooled = body_buf.capacity();
+                        let (decoded, read) =
+                            unread::read_chunked(conn.reads.reader(&stream), &pending, limit, &mut body_buf, &mut trailers)?;
+                        conn.bytes_read += read;
+                        if body_buf.capacity() > pooled {
+                            // Outgrew the pooled buffer, so the body lives in one of its own.
+                            header_buf.charge(body_buf.capacity());
+                        }
+                        match decoded {
+                            Decoded::Complete => {}
+                            // Pipelined requests are not supported.
+                            Decoded::Trailing => keep_alive = false,
+                            Decoded::TooLarge => {
+                                reject(&stream, StatusCode::PAYLOAD_TOO_LARGE);
+                                return Err(io::Error::other("body too large"));
+                            }
+                            Decoded::Malformed => {
+                                reject(&stream, StatusCode::BAD_REQUEST);
+                                return Err(io::Error::new(io::ErrorKind::InvalidData, "malformed chunked body"));
+                            }
+                        }
+                    } else if let (true, true, UnreadBody::Drain(limit)) = (chunked, keep_alive, self.unread_body) {
+                        // Whatever arrived with the header is the start of the chunked body.
+                        unread = Some((body_buf.split_off(0), limit));
+                    } else if body_buf.len() >= content_len {
+                        // Pipelined requests are not supported, so a connection with
+                        // more data queued up is closed after this response.
+                        keep_alive &= body_buf.len() == content_len;
                         body_buf.truncate(content_len);
                     } else {
                         let size = content_len - body_buf.len();
+                        if expect_continue {
+                            // The client holds the body back until told to go ahead.
+                            const CONTINUE: &[u8] = b"HTTP/1.1 100 Continue\r\n\r\n";
+                            (&stream).write_all(CONTINUE)?;
+                            conn.bytes_written += CONTINUE.len();
+                        }
     
                         let mut tmp = body_buf.split_off(body_buf.len());
                         unsafe { tmp.set_len(size) };
     
-                        if let Err(e) = stream.read_exact(&mut tmp) {
-                            return Some(Err(e));
-                        }
+                        conn.reads.reader(&stream).read_exact(&mut tmp)?;
+                        conn.bytes_read += size;
                         body_buf.unsplit(tmp);
                     }
 
-                    let request = match builder.body(body_buf) {
-                        Ok(req) => req,
-                        Err(e) => return Some(Err(io::Error::other(e))),
-                    };
+                    #[cfg(any(feature = "compression-gzip", feature = "compression-br", feature = "compression-zstd"))]
+                    if let Some((codings, indexes)) = content_encoding.filter(|(codings, _)| !codings.is_empty()) {
+                        if self.request_decompression && (!chunked || self.chunked_body_limit.is_some()) {
+                            let limit = if chunked {
+                                self.chunked_body_limit.unwrap_or(0)
+                            } else {
+                                self.req_size_limit
+                            };
+                            match decompress::decode(&codings, &body_buf, limit) {
+                                Ok(decoded) => {
+                                    header_buf.charge(decoded.capacity());
+                                    body_buf = decoded;
+                                }
+                                Err(failure) => {
+                                    reject(&stream, failure.status());
+                                    return Err(io::Error::new(io::ErrorKind::InvalidData, "undecodable body"));
+                                }
+                            }
+                            header_spans = header_spans
+                                .into_iter()
+                                .enumerate()
+                                .filter_map(|(i, spans)| (!indexes.contains(&i)).then_some(spans))
+                                .collect();
+                            if let Some(headers) = builder.as_mut().and_then(|b| b.headers_mut()) {
+                                headers.remove(header::CONTENT_ENCODING);
+                                headers.remove(header::CONTENT_LENGTH);
+                            }
+                        }
+                    }
+
+                    if let Some(budget) = self.memory_budget {
+                        if self.buffers.bytes_in_use() > budget {
+                            reject(&stream, StatusCode::SERVICE_UNAVAILABLE);
+                            return Err(io::Error::new(io::ErrorKind::OutOfMemory, "memory budget exhausted"));
+                        }
+                    }
+
+                    let request = OnceLock::new();
+                    let mut body = Some(body_buf);
+                    if let Some(builder) = builder {
+                        match builder.body(body.take().unwrap_or_default()) {
+                            Ok(req) => {
+                                let _ = request.set(req);
+                            }
+                            Err(e) => return Err(io::Error::other(e)),
+                        }
+                    }
 
-                    return Some(Ok(HttpRequest {
-                        peer_addr: addr,
+                    #[cfg(feature = "tracing")]
+                    let span = tracing::info_span!(
+                        parent: &conn.span,
+                        "request",
+                        method = %method,
+                        path = uri.path(),
+                        peer = %conn.addr,
+                        status = tracing::field::Empty,
+                    );
+                    return Ok(Some(HttpRequest {
+                        peer_addr: conn.addr.clone(),
                         header_buf,
+                        head: Head {
+                            method,
+                            uri,
+                            version,
+                            headers: header_spans,
+                        },
+                        body: Mutex::new(body),
                         request,
+                        codecs: self.codecs.clone(),
+                        trusted_proxies: self.trusted_proxies.clone(),
+                        hooks: self.hooks.clone(),
+                        conn: conn.clone(),
+                        bytes_written: AtomicUsize::new(0),
+                        received_at,
+                        status: AtomicU16::new(0),
+                        #[cfg(feature = "tracing")]
+                        span,
+                        idle: keep_alive.then(|| self.idle.clone()),
+                        reusable: AtomicBool::new(false),
+                        memo: (keep_alive && self.validator_memo.is_some())
+                            .then(|| Mutex::new(None)),
+                        connection: self.connections.track(&stream),
+                        unread,
+                        trailers,
                         stream,
                     }));
                 }
                 Err(e) => {
+                    let timed_out = matches!(
+                        e.kind(),
+                        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                    ) && (self.read_timeout.is_some() || deadline.is_some());
+                    if timed_out {
+                        reject(&stream, StatusCode::REQUEST_TIMEOUT);
+                        return Err(io::Error::new(io::ErrorKind::TimedOut, e));
+                    }
                     if e.kind() == io::ErrorKind::Interrupted
                         || e.kind() == io::ErrorKind::WouldBlock
                     {
                         tmp.clear();
                         header_buf.unsplit(tmp);
+                        if e.kind() == io::ErrorKind::WouldBlock {
+                            // Switched to non-blocking behind our back; wait instead of spinning.
+                            stream.wait_readable(None)?;
+                        }
                         continue;
                     }
-                    // eprintln!("error: {e}");
-                    return Some(Err(e));
+                    return Err(e);
                 }
             };
         }
     }
 }
 
+/// At most one `Host`, holding `host[:port]` or nothing (RFC 9112, section 3.2).
+fn valid_host(headers: &[httparse::Header]) -> bool {
+    let mut hosts = headers
+        .iter()
+        .filter(|h| h.name.eq_ignore_ascii_case(header::HOST.as_str()));
+    match (hosts.next(), hosts.next()) {
+        (None, _) => true,
+        (Some(host), None) => {
+            host.value.is_empty()
+                || (!host.value.contains(&b'@')
+                    && uri::Authority::try_from(host.value).is_ok())
+        }
+        (Some(_), Some(_)) => false,
+    }
+}
+
+/// `1*DIGIT`, or a list of the same one repeated, which some senders produce
+/// when joining headers (RFC 9110, section 8.6).
+fn parse_content_length(value: &[u8]) -> Option<usize> {
+    let mut length = None;
+    for item in value.split(|&b| b == b',') {
+        let item = item.trim_ascii();
+        if item.is_empty() || !item.iter().all(u8::is_ascii_digit) {
+            return None;
+        }
+        // All digits, so UTF-8; an overflow is no length either.
+        let item = std::str::from_utf8(item).ok()?.parse::<usize>().ok()?;
+        if length.is_some_and(|length| length != item) {
+            return None;
+        }
+        length = Some(item);
+    }
+    length
+}
+
+/// Statuses whose responses never have a body.
+fn bodiless(status: StatusCode) -> bool {
+    status.is_informational() || status == StatusCode::NO_CONTENT || status == StatusCode::NOT_MODIFIED
+}
+
+thread_local! {
+    /// Where response heads are serialized, kept to save an allocation per response.
+    static HEAD_BUF: std::cell::RefCell<Vec<u8>> = const { std::cell::RefCell::new(Vec::new()) };
+}
 
+/// Sends `head` and `body` with as few syscalls as the OS allows: usually one.
+fn write_all_vectored(mut stream: impl Write, mut head: &[u8], mut body: &[u8]) -> io::Result<()> {
+    while !head.is_empty() {
+        let n = match stream.write_vectored(&[io::IoSlice::new(head), io::IoSlice::new(body)]) {
+            Ok(0) => return Err(io::ErrorKind::WriteZero.into()),
+            Ok(n) => n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        if n < head.len() {
+            head = &head[n..];
+        } else {
+            body = &body[n - head.len()..];
+            head = &[];
+        }
+    }
+    stream.write_all(body)
+}
+
+fn write_date(head: &mut Vec<u8>) {
+    date::now(|now| {
+        head.extend_from_slice(b"date: ");
+        head.extend_from_slice(now.as_bytes());
+        head.extend_from_slice(b"\r\n");
+    });
+}
+
+/// Writes values as the raw bytes they hold; they need not be UTF-8.
+fn write_headers(mut stream: impl Write, headers: &HeaderMap) -> io::Result<()> {
+    for (k, v) in headers {
+        stream.write_all(k.as_str().as_bytes())?;
+        stream.write_all(b": ")?;
+        stream.write_all(v.as_bytes())?;
+        stream.write_all(b"\r\n")?;
+    }
+    Ok(())
+}
+
+/// Writes `response` for a request that is not handed out, then ends the exchange.
+/// Returns the number of bytes written.
+fn write_closing(stream: &Stream, version: Version, response: &Response<Vec<u8>>) -> io::Result<usize> {
+    let mut head = format!(
+        "{:?} {} {}\r\nconnection: close\r\n",
+        version,
+        response.status().as_str(),
+        response.status().canonical_reason().unwrap_or("Unknown"),
+    )
+    .into_bytes();
+    if !response.headers().contains_key(header::CONTENT_LENGTH) {
+        head.extend_from_slice(format!("content-length: {}\r\n", response.body().len()).as_bytes());
+    }
+    if !response.headers().contains_key(header::DATE) {
+        write_date(&mut head);
+    }
+    for (k, v) in response.headers() {
+        if k != header::CONNECTION {
+            head.extend_from_slice(k.as_str().as_bytes());
+            head.extend_from_slice(b": ");
+            head.extend_from_slice(v.as_bytes());
+            head.extend_from_slice(b"\r\n");
+        }
+    }
+    head.extend_from_slice(b"\r\n");
+    let mut stream = stream;
+    write_all_vectored(&mut stream, &head, response.body())?;
+    stream.flush()?;
+    Ok(head.len() + response.body().len())
+}
+
+/// Requests that could not be read are the client's doing, except when the
+/// server ran out of memory for them.
+fn log_read_error(peer: &PeerAddr, e: &io::Error) {
+    match e.kind() {
+        io::ErrorKind::OutOfMemory => warn!("{peer}: request dropped: {e}"),
+        _ => debug!("{peer}: request not read: {e}"),
+    }
+}
+
+/// Reads and drops whatever has already arrived, without waiting for more.
+fn discard_readable(mut stream: &Stream) {
+    if stream.set_nonblocking(true).is_err() {
+        return;
+    }
+    let mut scratch = [0; 1024];
+    // Bounded, so a client that keeps sending can't hold up the accept loop.
+    for _ in 0..64 {
+        if !matches!(stream.read(&mut scratch), Ok(n) if n > 0) {
+            break;
+        }
+    }
+    let _ = stream.set_nonblocking(false);
+}
+
+/// Answers an HTTP/2 connection preface that can't be served, without the `h2`
+/// feature or on stdio, with an empty SETTINGS frame, as the protocol requires,
+/// then a GOAWAY with `HTTP_1_1_REQUIRED`, which tells the client to retry over
+/// HTTP/1.1.
+fn refuse_h2(mut stream: &Stream) {
+    const SETTINGS: [u8; 9] = [0, 0, 0, 0x4, 0, 0, 0, 0, 0];
+    const GOAWAY: [u8; 17] = [0, 0, 8, 0x7, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0xd];
+    let _ = stream.write_all(&[SETTINGS.as_slice(), &GOAWAY].concat());
+}
+
+/// Turns a connection away before reading from it.
+fn shed(mut stream: &Stream, retry_after: u64) {
+    let _ = write!(
+        stream,
+        "HTTP/1.1 503 Service Unavailable\r\nconnection: close\r\nretry-after: {retry_after}\r\ncontent-length: 0\r\n\r\n",
+    );
+}
+
+/// Best-effort error response for requests that never make it to the user.
+fn reject(mut stream: &Stream, status: StatusCode) {
+    let _ = write!(
+        stream,
+        "HTTP/1.1 {} {}\r\nconnection: close\r\ncontent-length: 0\r\n\r\n",
+        status.as_str(),
+        status.canonical_reason().unwrap_or("Unknown"),
+    );
+}
@@ -0,0 +1,446 @@
+//! Request metrics, exported as Prometheus text or JSON.
+//!
+//! `Metrics` is a middleware; add it to a `Router` and serve its exports wherever
+//! convenient. With the `metrics` feature, it also counts requests by method
+//! and status, `instrument` adds connection and traffic counters from the
+//! server itself, and `handler` serves the lot:
+//!
+//! ```no_run
+//! # #[cfg(feature = "metrics")]
+//! # {
+//! use blocking_http_server::metrics::Metrics;
+//! use blocking_http_server::*;
+//!
+//! let mut server = Server::bind("127.0.0.1:8080")?;
+//! let metrics = Metrics::new();
+//! metrics.instrument(&mut server);
+//! let router = Router::new()
+//!     .get("/metrics", metrics.handler())
+//!     .wrap(metrics);
+//! # }
+//! # Ok::<(), std::io::Error>(())
+//! ```
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+#[cfg(feature = "metrics")]
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+use std::time::Instant;
+
+use crate::middleware::Middleware;
+use crate::middleware::Next;
+#[cfg(feature = "metrics")]
+use crate::header;
+use crate::router::Params;
+#[cfg(feature = "metrics")]
+use crate::HeaderValue;
+use crate::HttpRequest;
+#[cfg(feature = "metrics")]
+use crate::Method;
+use crate::Response;
+#[cfg(feature = "metrics")]
+use crate::shutdown::Connections;
+#[cfg(feature = "metrics")]
+use crate::Server;
+use crate::StatusCode;
+
+/// Quantiles included in the JSON export.
+const QUANTILES: [f64; 3] = [0.5, 0.9, 0.99];
+
+/// Upper bounds of the Prometheus histogram buckets, in microseconds: the
+/// client libraries' defaults, 5ms to 10s.
+const BUCKETS: [u64; 11] = [
+    5_000, 10_000, 25_000, 50_000, 100_000, 250_000, 500_000, 1_000_000, 2_500_000, 5_000_000, 10_000_000,
+];
+
+/// Label for requests no route matched.
+const UNMATCHED: &str = "unmatched";
+
+/// Label for routes beyond the route limit.
+const OTHER: &str = "other";
+
+/// Handler latency per matched route pattern and status class (`2xx`, `4xx`, ...).
+///
+/// Routes are labelled by their pattern (`/users/:id`), never by the raw path, and
+/// at most `route_limit` of them get their own series; the rest share `other`.
+#[derive(Debug, Clone)]
+pub struct Metrics {
+    series: Arc<Mutex<BTreeMap<(String, &'static str), Histogram>>>,
+    /// Requests by method and status code.
+    #[cfg(feature = "metrics")]
+    requests: Arc<Mutex<BTreeMap<(&'static str, u16), u64>>>,
+    in_flight: Arc<AtomicUsize>,
+    #[cfg(feature = "metrics")]
+    connections: Arc<Traffic>,
+    route_limit: usize,
+}
+
+/// What `Metrics::instrument` counts.
+#[cfg(feature = "metrics")]
+#[derive(Debug, Default)]
+struct Traffic {
+    open: AtomicUsize,
+    bytes_read: AtomicU64,
+    bytes_written: AtomicU64,
+    /// The instrumented server's, for what its limits see.
+    server: Mutex<Option<Arc<Connections>>>,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self {
+            series: Arc::default(),
+            #[cfg(feature = "metrics")]
+            requests: Arc::default(),
+            in_flight: Arc::default(),
+            #[cfg(feature = "metrics")]
+            connections: Arc::default(),
+            route_limit: 100,
+        }
+    }
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Caps the number of distinct route labels (100 by default).
+    pub fn with_route_limit(mut self, limit: usize) -> Self {
+        self.route_limit = limit;
+        self
+    }
+
+    pub fn record(&self, route: &str, status: StatusCode, latency: Duration) {
+        let class = status_class(status);
+        let mut series = self.series.lock().unwrap();
+        let known = series.keys().any(|(r, _)| r == route);
+        let route = if known || route == UNMATCHED || route_count(&series) < self.route_limit {
+            route
+        } else {
+            OTHER
+        };
+        series
+            .entry((route.to_string(), class))
+            .or_default()
+            .record(latency);
+    }
+
+    /// A copy of the histogram for `route` and `status`'s class, if any were recorded.
+    pub fn histogram(&self, route: &str, status: StatusCode) -> Option<Histogram> {
+        let key = (route.to_string(), status_class(status));
+        self.series.lock().unwrap().get(&key).cloned()
+    }
+
+    /// Counts open connections and the bytes read and written on them, through
+    /// `server`'s `on_accept` and `on_connection_close` hooks, replacing any set
+    /// before. Bytes are counted as each connection closes. Also reports the
+    /// requests `server` has handed out and those it shed with a `503`.
+    #[cfg(feature = "metrics")]
+    pub fn instrument(&self, server: &mut Server) {
+        *self.connections.server.lock().unwrap() = Some(server.connections.clone());
+        let accepted = self.connections.clone();
+        server.on_accept(move |_| {
+            accepted.open.fetch_add(1, Ordering::Relaxed);
+        });
+        let closed = self.connections.clone();
+        server.on_connection_close(move |_, stats| {
+            closed.open.fetch_sub(1, Ordering::Relaxed);
+            closed.bytes_read.fetch_add(stats.bytes_read as u64, Ordering::Relaxed);
+            closed.bytes_written.fetch_add(stats.bytes_written as u64, Ordering::Relaxed);
+        });
+    }
+
+    /// A route handler serving `to_prometheus()`.
+    #[cfg(feature = "metrics")]
+    pub fn handler(&self) -> impl Fn(&HttpRequest) -> Response<Vec<u8>> + Send + Sync + 'static {
+        let metrics = self.clone();
+        move |_| {
+            let mut response = Response::new(metrics.to_prometheus().into_bytes());
+            response.headers_mut().insert(
+                header::CONTENT_TYPE,
+                HeaderValue::from_static("text/plain; version=0.0.4; charset=utf-8"),
+            );
+            response
+        }
+    }
+
+    /// Requests inside the handlers right now: how deep the queue is under load.
+    pub fn in_flight(&self) -> usize {
+        self.in_flight.load(Ordering::Relaxed)
+    }
+
+    /// Prometheus text exposition: a histogram, `http_request_duration_seconds`,
+    /// and a gauge, `http_requests_in_flight`. With the `metrics` feature, also a
+    /// counter, `http_requests_total`, and with `instrument`,
+    /// `http_connections_open`, `http_connections_active`,
+    /// `http_requests_shed_total`, `http_received_bytes_total` and
+    /// `http_sent_bytes_total`.
+    pub fn to_prometheus(&self) -> String {
+        let mut out = String::new();
+        out.push_str("# HELP http_requests_in_flight Requests being handled right now.\n");
+        out.push_str("# TYPE http_requests_in_flight gauge\n");
+        let _ = writeln!(out, "http_requests_in_flight {}", self.in_flight());
+        #[cfg(feature = "metrics")]
+        self.write_counters(&mut out);
+        out.push_str("# HELP http_request_duration_seconds Time spent handling requests.\n");
+        out.push_str("# TYPE http_request_duration_seconds histogram\n");
+        for ((route, class), histogram) in self.series.lock().unwrap().iter() {
+            let labels = format!("route=\"{}\",status=\"{class}\"", escape_label(route));
+            for (le, count) in histogram.buckets() {
+                let _ = writeln!(
+                    out,
+                    "http_request_duration_seconds_bucket{{{labels},le=\"{}\"}} {count}",
+                    le.as_secs_f64()
+                );
+            }
+            let _ = writeln!(
+                out,
+                "http_request_duration_seconds_bucket{{{labels},le=\"+Inf\"}} {}",
+                histogram.count()
+            );
+            let _ = writeln!(
+                out,
+                "http_request_duration_seconds_sum{{{labels}}} {}",
+                histogram.sum().as_secs_f64()
+            );
+            let _ = writeln!(
+                out,
+                "http_request_duration_seconds_count{{{labels}}} {}",
+                histogram.count()
+            );
+        }
+        out
+    }
+
+    #[cfg(feature = "metrics")]
+    fn write_counters(&self, out: &mut String) {
+        out.push_str("# HELP http_requests_total Requests handled.\n");
+        out.push_str("# TYPE http_requests_total counter\n");
+        for ((method, status), count) in self.requests.lock().unwrap().iter() {
+            let _ = writeln!(out, "http_requests_total{{method=\"{method}\",status=\"{status}\"}} {count}");
+        }
+        let traffic = &self.connections;
+        out.push_str("# HELP http_connections_open Connections open right now.\n");
+        out.push_str("# TYPE http_connections_open gauge\n");
+        let _ = writeln!(out, "http_connections_open {}", traffic.open.load(Ordering::Relaxed));
+        if let Some(server) = &*traffic.server.lock().unwrap() {
+            out.push_str("# HELP http_connections_active Requests handed out and not yet dropped, as the overload limit counts them.\n");
+            out.push_str("# TYPE http_connections_active gauge\n");
+            let _ = writeln!(out, "http_connections_active {}", server.active());
+            out.push_str("# HELP http_requests_shed_total Requests answered 503 by the overload or connection limit.\n");
+            out.push_str("# TYPE http_requests_shed_total counter\n");
+            let _ = writeln!(out, "http_requests_shed_total {}", server.shed());
+        }
+        out.push_str("# HELP http_received_bytes_total Bytes read from closed connections.\n");
+        out.push_str("# TYPE http_received_bytes_total counter\n");
+        let _ = writeln!(out, "http_received_bytes_total {}", traffic.bytes_read.load(Ordering::Relaxed));
+        out.push_str("# HELP http_sent_bytes_total Bytes written to closed connections.\n");
+        out.push_str("# TYPE http_sent_bytes_total counter\n");
+        let _ = writeln!(out, "http_sent_bytes_total {}", traffic.bytes_written.load(Ordering::Relaxed));
+    }
+
+    /// `[{"route": .., "status": "2xx", "count": .., "p50": .., ...}]`, latencies in seconds.
+    pub fn to_json(&self) -> String {
+        let mut out = String::from("[");
+        for (i, ((route, class), histogram)) in self.series.lock().unwrap().iter().enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            let _ = write!(
+                out,
+                "{{\"route\":\"{}\",\"status\":\"{class}\",\"count\":{},\"sum\":{}",
+                escape_json(route),
+                histogram.count(),
+                histogram.sum().as_secs_f64()
+            );
+            for q in QUANTILES {
+                let _ = write!(
+                    out,
+                    ",\"p{}\":{}",
+                    (q * 100.0).round(),
+                    histogram.quantile(q).as_secs_f64()
+                );
+            }
+            out.push('}');
+        }
+        out.push(']');
+        out
+    }
+}
+
+impl Middleware for Metrics {
+    fn handle(&self, req: &mut HttpRequest, next: Next<'_>) -> Response<Vec<u8>> {
+        let started = Instant::now();
+        self.in_flight.fetch_add(1, Ordering::Relaxed);
+        let response = next.run(req);
+        self.in_flight.fetch_sub(1, Ordering::Relaxed);
+        #[cfg(feature = "metrics")]
+        {
+            let method = method_label(req.method());
+            *self.requests.lock().unwrap().entry((method, response.status().as_u16())).or_default() += 1;
+        }
+        let route = req
+            .extensions()
+            .get::<Params>()
+            .map_or(UNMATCHED, Params::pattern);
+        self.record(route, response.status(), started.elapsed());
+        response
+    }
+}
+
+/// Distinct route labels in use, not counting the built-in ones.
+fn route_count(series: &BTreeMap<(String, &'static str), Histogram>) -> usize {
+    let mut routes: Vec<&str> = series
+        .keys()
+        .map(|(route, _)| route.as_str())
+        .filter(|route| *route != UNMATCHED && *route != OTHER)
+        .collect();
+    routes.dedup();
+    routes.len()
+}
+
+/// Standard methods by name; anything else would let clients mint new series.
+#[cfg(feature = "metrics")]
+fn method_label(method: &Method) -> &'static str {
+    match *method {
+        Method::GET => "GET",
+        Method::HEAD => "HEAD",
+        Method::POST => "POST",
+        Method::PUT => "PUT",
+        Method::DELETE => "DELETE",
+        Method::PATCH => "PATCH",
+        Method::OPTIONS => "OPTIONS",
+        Method::CONNECT => "CONNECT",
+        Method::TRACE => "TRACE",
+        _ => "other",
+    }
+}
+
+fn status_class(status: StatusCode) -> &'static str {
+    match status.as_u16() / 100 {
+        1 => "1xx",
+        2 => "2xx",
+        3 => "3xx",
+        4 => "4xx",
+        _ => "5xx",
+    }
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+fn escape_json(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            c if c.is_control() => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Bits of precision kept below the leading bit: 16 buckets per power of two,
+/// so a reported quantile is within ~6% of the true value.
+const SUB_BUCKET_BITS: u32 = 4;
+const SUB_BUCKETS: u64 = 1 << SUB_BUCKET_BITS;
+
+/// Log-linear (HDR-style) histogram of durations at microsecond resolution.
+/// Memory grows with the largest value seen, to under 1000 counters. Values up to
+/// each of Prometheus's bucket bounds are counted exactly on the side.
+#[derive(Debug, Clone, Default)]
+pub struct Histogram {
+    counts: Vec<u64>,
+    /// Values up to each of `BUCKETS`, not counting those up to the one before.
+    fixed: [u64; BUCKETS.len()],
+    count: u64,
+    sum: Duration,
+    max: Duration,
+}
+
+impl Histogram {
+    pub fn record(&mut self, value: Duration) {
+        let micros = value.as_micros().min(u64::MAX as u128) as u64;
+        let index = bucket_index(micros);
+        if self.counts.len() <= index {
+            self.counts.resize(index + 1, 0);
+        }
+        self.counts[index] += 1;
+        if let Some(fixed) = BUCKETS.iter().position(|&bound| micros <= bound) {
+            self.fixed[fixed] += 1;
+        }
+        self.count += 1;
+        self.sum += value;
+        self.max = self.max.max(value);
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    pub fn sum(&self) -> Duration {
+        self.sum
+    }
+
+    pub fn max(&self) -> Duration {
+        self.max
+    }
+
+    /// Prometheus's `le` buckets, 5ms to 10s: each bound with the number of values
+    /// up to it. Everything else is below `+Inf`, i.e. `count()`.
+    pub fn buckets(&self) -> impl Iterator<Item = (Duration, u64)> + '_ {
+        BUCKETS.iter().zip(&self.fixed).scan(0, |total, (&bound, &n)| {
+            *total += n;
+            Some((Duration::from_micros(bound), *total))
+        })
+    }
+
+    /// The value below which a fraction `q` of the recorded values fall, reported
+    /// as the upper end of its bucket. Zero when empty.
+    pub fn quantile(&self, q: f64) -> Duration {
+        let rank = ((q.clamp(0.0, 1.0) * self.count as f64).ceil() as u64).max(1);
+        let mut seen = 0;
+        for (index, &n) in self.counts.iter().enumerate() {
+            seen += n;
+            if seen >= rank {
+                let upper = Duration::from_micros(bucket_upper(index));
+                return upper.min(self.max);
+            }
+        }
+        self.max
+    }
+}
+
+fn bucket_index(value: u64) -> usize {
+    if value < SUB_BUCKETS {
+        return value as usize;
+    }
+    let exponent = 63 - value.leading_zeros();
+    let sub = (value >> (exponent - SUB_BUCKET_BITS)) & (SUB_BUCKETS - 1);
+    ((exponent - SUB_BUCKET_BITS + 1) as u64 * SUB_BUCKETS + sub) as usize
+}
+
+/// The largest value that lands in bucket `index`.
+fn bucket_upper(index: usize) -> u64 {
+    let index = index as u64;
+    if index < SUB_BUCKETS {
+        return index;
+    }
+    let shift = index / SUB_BUCKETS - 1;
+    let sub = index % SUB_BUCKETS;
+    ((SUB_BUCKETS + sub) << shift) + ((1 << shift) - 1)
+}
@@ -0,0 +1,67 @@
+//! Wrappers around a `Router`'s handlers, added with `Router::wrap`.
+//!
+//! Middleware runs in the order it was added, outermost first, and sees every
+//! request, including those the router answers itself (`404`, `405`, `OPTIONS`).
+
+pub(crate) mod auth;
+#[cfg(any(feature = "compression-gzip", feature = "compression-br", feature = "compression-zstd"))]
+mod compression;
+mod cors;
+#[cfg(feature = "digest-auth")]
+mod digest;
+mod fault;
+mod rate_limit;
+mod security;
+
+pub use auth::constant_time_eq;
+pub use auth::BasicAuth;
+#[cfg(any(feature = "compression-gzip", feature = "compression-br", feature = "compression-zstd"))]
+pub use compression::Compression;
+pub use cors::Cors;
+#[cfg(feature = "digest-auth")]
+pub use digest::DigestAuth;
+pub use fault::FaultInjection;
+pub use rate_limit::RateLimiter;
+pub use security::ContentSecurityPolicy;
+pub use security::FrameOptions;
+pub use security::SecurityHeaders;
+
+use crate::router::Router;
+use crate::HttpRequest;
+use crate::Response;
+
+pub trait Middleware: Send + Sync {
+    /// Returns a response of its own, or delegates to `next.run(req)` and possibly
+    /// adjusts the result.
+    fn handle(&self, req: &mut HttpRequest, next: Next<'_>) -> Response<Vec<u8>>;
+}
+
+impl<F> Middleware for F
+where
+    F: Fn(&mut HttpRequest, Next<'_>) -> Response<Vec<u8>> + Send + Sync,
+{
+    fn handle(&self, req: &mut HttpRequest, next: Next<'_>) -> Response<Vec<u8>> {
+        self(req, next)
+    }
+}
+
+/// The rest of the chain after the current middleware.
+pub struct Next<'a> {
+    pub(crate) router: &'a Router,
+    pub(crate) rest: &'a [Box<dyn Middleware>],
+}
+
+impl Next<'_> {
+    pub fn run(self, req: &mut HttpRequest) -> Response<Vec<u8>> {
+        match self.rest.split_first() {
+            Some((middleware, rest)) => middleware.handle(
+                req,
+                Next {
+                    router: self.router,
+                    rest,
+                },
+            ),
+            None => self.router.dispatch(req),
+        }
+    }
+}
@@ -0,0 +1,165 @@
+use std::fmt;
+use std::sync::Arc;
+
+use super::Middleware;
+use super::Next;
+use crate::header;
+use crate::HeaderValue;
+use crate::HttpRequest;
+use crate::Response;
+use crate::StatusCode;
+
+type Verifier = dyn Fn(&str, &str) -> bool + Send + Sync;
+
+/// Lets through only requests with valid `Authorization: Basic` credentials,
+/// answering the rest `401 Unauthorized` with a `WWW-Authenticate` challenge.
+///
+/// Basic credentials travel in the clear, so only use it over a TLS-terminating
+/// proxy or a trusted network.
+///
+/// ```no_run
+/// use blocking_http_server::middleware::BasicAuth;
+/// use blocking_http_server::*;
+///
+/// let router = Router::new()
+///     .get("/admin", |_| Response::new(b"hello".to_vec()))
+///     .wrap(BasicAuth::new("admin").with_user("root", "hunter2"));
+/// ```
+#[derive(Clone)]
+pub struct BasicAuth {
+    challenge: HeaderValue,
+    users: Vec<(String, String)>,
+    verifier: Option<Arc<Verifier>>,
+}
+
+impl BasicAuth {
+    /// Nobody is let in until `with_user` or `with_verifier` says who.
+    pub fn new(realm: &str) -> Self {
+        let challenge = format!("Basic realm={}, charset=\"UTF-8\"", quote(realm));
+        Self {
+            challenge: HeaderValue::try_from(challenge)
+                .unwrap_or(HeaderValue::from_static("Basic charset=\"UTF-8\"")),
+            users: Vec::new(),
+            verifier: None,
+        }
+    }
+
+    pub fn with_user(mut self, user: impl Into<String>, password: impl Into<String>) -> Self {
+        self.users.push((user.into(), password.into()));
+        self
+    }
+
+    /// Checks credentials with `verify` instead of the users added with
+    /// `with_user`, e.g. against hashed passwords. Compare secrets with
+    /// `constant_time_eq`.
+    pub fn with_verifier(mut self, verify: impl Fn(&str, &str) -> bool + Send + Sync + 'static) -> Self {
+        self.verifier = Some(Arc::new(verify));
+        self
+    }
+
+    fn verify(&self, user: &str, password: &str) -> bool {
+        if let Some(verify) = &self.verifier {
+            return verify(user, password);
+        }
+        // Every user is compared, so the time taken says nothing about which matched.
+        self.users.iter().fold(false, |found, (u, p)| {
+            let matches = constant_time_eq(u.as_bytes(), user.as_bytes())
+                & constant_time_eq(p.as_bytes(), password.as_bytes());
+            found | matches
+        })
+    }
+}
+
+impl Middleware for BasicAuth {
+    fn handle(&self, req: &mut HttpRequest, next: Next<'_>) -> Response<Vec<u8>> {
+        match req.basic_auth() {
+            Some((user, password)) if self.verify(&user, &password) => next.run(req),
+            _ => {
+                let status = StatusCode::UNAUTHORIZED;
+                let mut response = Response::new(status.to_string().into_bytes());
+                *response.status_mut() = status;
+                response
+                    .headers_mut()
+                    .insert(header::WWW_AUTHENTICATE, self.challenge.clone());
+                response
+            }
+        }
+    }
+}
+
+impl fmt::Debug for BasicAuth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BasicAuth")
+            .field("challenge", &self.challenge)
+            .field("users", &self.users.len())
+            .field("verifier", &self.verifier.is_some())
+            .finish()
+    }
+}
+
+/// Compares two secrets in time that depends only on their lengths, not on
+/// where they first differ.
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+/// The user and password in an `Authorization: Basic` header value.
+pub(crate) fn basic_credentials(value: &[u8]) -> Option<(String, String)> {
+    let encoded = strip_scheme(value, b"basic")?;
+    let decoded = String::from_utf8(decode_base64(encoded)?).ok()?;
+    let (user, password) = decoded.split_once(':')?;
+    Some((user.to_owned(), password.to_owned()))
+}
+
+/// What follows `scheme` (case-insensitive) and a space in an `Authorization`
+/// header value.
+pub(crate) fn strip_scheme<'a>(value: &'a [u8], scheme: &[u8]) -> Option<&'a [u8]> {
+    let value = value.trim_ascii();
+    let (name, rest) = value.split_at_checked(scheme.len())?;
+    if !name.eq_ignore_ascii_case(scheme) || !rest.starts_with(b" ") {
+        return None;
+    }
+    Some(rest.trim_ascii_start())
+}
+
+/// Standard base64, padding optional.
+fn decode_base64(input: &[u8]) -> Option<Vec<u8>> {
+    let input = input.strip_suffix(b"==").or_else(|| input.strip_suffix(b"=")).unwrap_or(input);
+    let mut out = Vec::with_capacity(input.len() * 3 / 4);
+    let mut bits = 0u32;
+    let mut count = 0;
+    for &c in input {
+        let v = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            _ => return None,
+        };
+        bits = bits << 6 | u32::from(v);
+        count += 6;
+        if count >= 8 {
+            count -= 8;
+            out.push((bits >> count) as u8);
+        }
+    }
+    // A lone trailing character cannot complete a byte.
+    (count < 6).then_some(out)
+}
+
+pub(crate) fn quote(s: &str) -> String {
+    let mut quoted = String::with_capacity(s.len() + 2);
+    quoted.push('"');
+    for c in s.chars() {
+        if c == '"' || c == '\\' {
+            quoted.push('\\');
+        }
+        quoted.push(c);
+    }
+    quoted.push('"');
+    quoted
+}
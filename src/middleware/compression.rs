@@ -0,0 +1,266 @@
+#[cfg(any(feature = "compression-gzip", feature = "compression-br"))]
+use std::io::Write;
+
+use super::Middleware;
+use super::Next;
+use crate::accept;
+use crate::header;
+use crate::HeaderValue;
+use crate::HttpRequest;
+use crate::Method;
+use crate::Response;
+use crate::StatusCode;
+
+/// Compresses response bodies for clients that accept it, once they are big
+/// enough to be worth it and of a type that compresses: text, JSON, JavaScript,
+/// XML, SVG and the like. Anything already encoded, partial or marked
+/// `Cache-Control: no-transform` is left alone.
+///
+/// Each encoding comes with a feature: `compression-gzip`, `compression-br` and
+/// `compression-zstd`. Of those built in, the client's `Accept-Encoding` picks
+/// the one with the highest `q`; ties go to `br`, then `zstd`, then `gzip`.
+///
+/// ```no_run
+/// use blocking_http_server::middleware::Compression;
+/// use blocking_http_server::*;
+///
+/// let router = Router::new()
+///     .get("/report", |_| Response::new(vec![b'a'; 64 * 1024]))
+///     .wrap(Compression::new().with_min_size(512));
+/// ```
+#[derive(Debug, Clone)]
+pub struct Compression {
+    min_size: usize,
+    #[cfg(feature = "compression-gzip")]
+    gzip_level: u32,
+    #[cfg(feature = "compression-br")]
+    brotli_quality: u32,
+    #[cfg(feature = "compression-zstd")]
+    zstd_level: i32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Encoding {
+    #[cfg(feature = "compression-br")]
+    Brotli,
+    #[cfg(feature = "compression-zstd")]
+    Zstd,
+    #[cfg(feature = "compression-gzip")]
+    Gzip,
+}
+
+impl Encoding {
+    /// Those built in, most preferred first.
+    const ALL: &'static [Encoding] = &[
+        #[cfg(feature = "compression-br")]
+        Encoding::Brotli,
+        #[cfg(feature = "compression-zstd")]
+        Encoding::Zstd,
+        #[cfg(feature = "compression-gzip")]
+        Encoding::Gzip,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            #[cfg(feature = "compression-br")]
+            Encoding::Brotli => "br",
+            #[cfg(feature = "compression-zstd")]
+            Encoding::Zstd => "zstd",
+            #[cfg(feature = "compression-gzip")]
+            Encoding::Gzip => "gzip",
+        }
+    }
+}
+
+impl Default for Compression {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Compression {
+    /// Bodies of 1 KiB and up, at gzip level 6, brotli quality 5 and zstd level 3:
+    /// a fair trade of size for the CPU spent on every response.
+    pub fn new() -> Self {
+        Self {
+            min_size: 1024,
+            #[cfg(feature = "compression-gzip")]
+            gzip_level: 6,
+            #[cfg(feature = "compression-br")]
+            brotli_quality: 5,
+            #[cfg(feature = "compression-zstd")]
+            zstd_level: 3,
+        }
+    }
+
+    /// Smaller bodies are sent as they are; below a few hundred bytes the
+    /// framing eats most of the gain.
+    pub fn with_min_size(mut self, min_size: usize) -> Self {
+        self.min_size = min_size;
+        self
+    }
+
+    /// From 0, no compression, to 9, the smallest output for the most CPU.
+    #[cfg(feature = "compression-gzip")]
+    pub fn with_gzip_level(mut self, level: u32) -> Self {
+        self.gzip_level = level.min(9);
+        self
+    }
+
+    /// From 0 to 11; the top levels are meant for compressing ahead of time.
+    #[cfg(feature = "compression-br")]
+    pub fn with_brotli_quality(mut self, quality: u32) -> Self {
+        self.brotli_quality = quality.min(11);
+        self
+    }
+
+    /// From 1 to 22, or negative for faster still.
+    #[cfg(feature = "compression-zstd")]
+    pub fn with_zstd_level(mut self, level: i32) -> Self {
+        self.zstd_level = level.min(22);
+        self
+    }
+
+    /// The encoding `req` likes best, if it accepts any.
+    fn negotiate(&self, req: &HttpRequest) -> Option<Encoding> {
+        let mut best: Option<(f32, Encoding)> = None;
+        for &encoding in Encoding::ALL {
+            let q = accept::quality(req.headers(), &header::ACCEPT_ENCODING, encoding.name()).unwrap_or(0.0);
+            if q > 0.0 && best.is_none_or(|(best, _)| q > best) {
+                best = Some((q, encoding));
+            }
+        }
+        best.map(|(_, encoding)| encoding)
+    }
+
+    fn compress(&self, encoding: Encoding, body: &[u8]) -> Option<Vec<u8>> {
+        match encoding {
+            #[cfg(feature = "compression-br")]
+            Encoding::Brotli => {
+                let out = Vec::with_capacity(body.len() / 4);
+                let mut encoder = brotli::CompressorWriter::new(out, 4096, self.brotli_quality, 22);
+                encoder.write_all(body).ok()?;
+                // Finishes the stream.
+                Some(encoder.into_inner())
+            }
+            #[cfg(feature = "compression-zstd")]
+            Encoding::Zstd => zstd::bulk::compress(body, self.zstd_level).ok(),
+            #[cfg(feature = "compression-gzip")]
+            Encoding::Gzip => {
+                let out = Vec::with_capacity(body.len() / 4);
+                let level = flate2::Compression::new(self.gzip_level);
+                let mut encoder = flate2::write::GzEncoder::new(out, level);
+                encoder.write_all(body).ok()?;
+                encoder.finish().ok()
+            }
+        }
+    }
+}
+
+impl Middleware for Compression {
+    fn handle(&self, req: &mut HttpRequest, next: Next<'_>) -> Response<Vec<u8>> {
+        let mut response = next.run(req);
+        if !compressible(&response) {
+            return response;
+        }
+        vary_on_accept_encoding(&mut response);
+
+        // The router has emptied the body of a response to HEAD, leaving the length
+        // of the GET one.
+        let head = req.method() == Method::HEAD;
+        let len = match response.headers().get(header::CONTENT_LENGTH) {
+            Some(len) if head => len.to_str().ok().and_then(|len| len.parse().ok()).unwrap_or(0),
+            _ => response.body().len(),
+        };
+        if len < self.min_size {
+            return response;
+        }
+        let Some(encoding) = self.negotiate(req) else {
+            return response;
+        };
+        if !head {
+            let Some(body) = self.compress(encoding, response.body()) else {
+                return response;
+            };
+            if body.len() >= response.body().len() {
+                return response;
+            }
+            *response.body_mut() = body;
+        }
+        // What the body would compress to is not known for HEAD, so it goes
+        // without a length.
+        let headers = response.headers_mut();
+        headers.remove(header::CONTENT_LENGTH);
+        headers.insert(header::CONTENT_ENCODING, HeaderValue::from_static(encoding.name()));
+        // The bytes differ from the identity representation's, so a strong tag
+        // no longer holds.
+        if let Some(etag) = headers.get(header::ETAG) {
+            if !etag.as_bytes().starts_with(b"W/") {
+                let mut weak = b"W/".to_vec();
+                weak.extend_from_slice(etag.as_bytes());
+                if let Ok(weak) = HeaderValue::from_bytes(&weak) {
+                    headers.insert(header::ETAG, weak);
+                }
+            }
+        }
+        response
+    }
+}
+
+/// Whether `response` is of a kind that could be sent compressed.
+fn compressible(response: &Response<Vec<u8>>) -> bool {
+    let status = response.status();
+    if !status.is_success() || status == StatusCode::NO_CONTENT || status == StatusCode::PARTIAL_CONTENT {
+        return false;
+    }
+    let headers = response.headers();
+    if headers.contains_key(header::CONTENT_ENCODING) || headers.contains_key(header::CONTENT_RANGE) {
+        return false;
+    }
+    let no_transform = headers
+        .get_all(header::CACHE_CONTROL)
+        .into_iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|directive| directive.trim().eq_ignore_ascii_case("no-transform"));
+    if no_transform {
+        return false;
+    }
+    let Some(content_type) = headers.get(header::CONTENT_TYPE).and_then(|v| v.to_str().ok()) else {
+        return false;
+    };
+    let essence = content_type.split(';').next().unwrap_or("").trim().to_ascii_lowercase();
+    // Streams are written as they come; holding them back to compress defeats them.
+    if essence == "text/event-stream" {
+        return false;
+    }
+    essence.starts_with("text/")
+        || essence.ends_with("+json")
+        || essence.ends_with("+xml")
+        || matches!(
+            essence.as_str(),
+            "application/json"
+                | "application/javascript"
+                | "application/xml"
+                | "application/wasm"
+                | "application/x-ndjson"
+                | "application/toml"
+                | "application/yaml"
+        )
+}
+
+fn vary_on_accept_encoding(response: &mut Response<Vec<u8>>) {
+    let headers = response.headers_mut();
+    let varies = headers
+        .get_all(header::VARY)
+        .into_iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|name| {
+            let name = name.trim();
+            name == "*" || name.eq_ignore_ascii_case("accept-encoding")
+        });
+    if !varies {
+        headers.append(header::VARY, HeaderValue::from_static("accept-encoding"));
+    }
+}
@@ -0,0 +1,226 @@
+use std::time::Duration;
+
+use super::Middleware;
+use super::Next;
+use crate::header;
+use crate::header::HeaderName;
+use crate::HeaderValue;
+use crate::HttpRequest;
+use crate::Method;
+use crate::Response;
+use crate::StatusCode;
+
+/// Cross-origin resource sharing: answers preflight `OPTIONS` requests itself and
+/// adds the `Access-Control-*` headers to the responses for allowed origins.
+///
+/// Requests from other origins are still handled, just without the headers that
+/// would let a browser hand the response to the page; disallowed preflights get
+/// `403`.
+///
+/// ```no_run
+/// use blocking_http_server::middleware::Cors;
+/// use blocking_http_server::*;
+/// use std::time::Duration;
+///
+/// let router = Router::new()
+///     .get("/api/items", |_| Response::new(b"[]".to_vec()))
+///     .wrap(
+///         Cors::new()
+///             .with_origin("https://app.example.com")
+///             .with_credentials()
+///             .with_max_age(Duration::from_secs(600)),
+///     );
+/// ```
+#[derive(Debug, Clone)]
+pub struct Cors {
+    /// `None` allows any origin.
+    origins: Option<Vec<HeaderValue>>,
+    methods: Option<Vec<Method>>,
+    headers: Option<Vec<HeaderName>>,
+    expose: Vec<HeaderName>,
+    credentials: bool,
+    max_age: Option<Duration>,
+}
+
+impl Default for Cors {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Cors {
+    /// Allows no origin until told otherwise.
+    pub fn new() -> Self {
+        Self {
+            origins: Some(Vec::new()),
+            methods: None,
+            headers: None,
+            expose: Vec::new(),
+            credentials: false,
+            max_age: None,
+        }
+    }
+
+    /// Panics if `origin` is not a valid header value.
+    #[track_caller]
+    pub fn with_origin(mut self, origin: &str) -> Self {
+        let origin = HeaderValue::from_str(origin).expect("invalid origin");
+        if let Some(origins) = &mut self.origins {
+            origins.push(origin);
+        }
+        self
+    }
+
+    /// Answers every origin with `*`.
+    ///
+    /// Panics if credentials are allowed: letting any site make credentialed
+    /// requests would hand every visitor's session to whichever page asks.
+    ///
+    /// ```should_panic
+    /// use blocking_http_server::middleware::Cors;
+    ///
+    /// let cors = Cors::new().with_credentials().with_any_origin();
+    /// ```
+    #[track_caller]
+    pub fn with_any_origin(mut self) -> Self {
+        assert!(!self.credentials, "CORS credentials cannot be allowed for any origin");
+        self.origins = None;
+        self
+    }
+
+    /// Methods allowed across origins; by default, whatever the router answers
+    /// for the path.
+    pub fn with_methods(mut self, methods: impl IntoIterator<Item = Method>) -> Self {
+        self.methods = Some(methods.into_iter().collect());
+        self
+    }
+
+    /// Request headers allowed across origins; by default, any the preflight asks
+    /// for.
+    pub fn with_headers(mut self, headers: impl IntoIterator<Item = HeaderName>) -> Self {
+        self.headers = Some(headers.into_iter().collect());
+        self
+    }
+
+    /// Response headers, beyond the CORS-safelisted ones, that pages may read.
+    pub fn with_expose_headers(mut self, headers: impl IntoIterator<Item = HeaderName>) -> Self {
+        self.expose = headers.into_iter().collect();
+        self
+    }
+
+    /// Lets pages send cookies and `Authorization` along.
+    ///
+    /// Panics after `with_any_origin`; list the trusted origins instead.
+    #[track_caller]
+    pub fn with_credentials(mut self) -> Self {
+        assert!(self.origins.is_some(), "CORS credentials cannot be allowed for any origin");
+        self.credentials = true;
+        self
+    }
+
+    /// How long browsers may cache a preflight's outcome.
+    pub fn with_max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    fn allows(&self, origin: &HeaderValue) -> bool {
+        self.origins.as_ref().is_none_or(|origins| origins.contains(origin))
+    }
+
+    /// The headers every response to an allowed origin carries.
+    fn decorate(&self, response: &mut Response<Vec<u8>>, origin: &HeaderValue) {
+        let headers = response.headers_mut();
+        let allow_origin = match self.origins {
+            None => HeaderValue::from_static("*"),
+            Some(_) => origin.clone(),
+        };
+        headers.insert(header::ACCESS_CONTROL_ALLOW_ORIGIN, allow_origin);
+        if self.credentials {
+            headers.insert(header::ACCESS_CONTROL_ALLOW_CREDENTIALS, HeaderValue::from_static("true"));
+        }
+        if !self.expose.is_empty() {
+            headers.insert(header::ACCESS_CONTROL_EXPOSE_HEADERS, join(&self.expose));
+        }
+        headers.append(header::VARY, HeaderValue::from_static("origin"));
+    }
+
+    fn preflight(&self, req: &HttpRequest, origin: &HeaderValue, next: &Next<'_>) -> Response<Vec<u8>> {
+        let method = req
+            .headers()
+            .get(header::ACCESS_CONTROL_REQUEST_METHOD)
+            .and_then(|method| Method::from_bytes(method.as_bytes()).ok());
+        let methods = match &self.methods {
+            Some(methods) => methods.clone(),
+            None => next.router.allowed_methods(req.uri().path()),
+        };
+        let requested: Vec<HeaderName> = req
+            .headers()
+            .get_all(header::ACCESS_CONTROL_REQUEST_HEADERS)
+            .iter()
+            .flat_map(|v| v.as_bytes().split(|&b| b == b','))
+            .filter_map(|name| HeaderName::from_bytes(name.trim_ascii()).ok())
+            .collect();
+        let headers_allowed = match &self.headers {
+            Some(allowed) => requested.iter().all(|name| allowed.contains(name)),
+            None => true,
+        };
+
+        let allowed = self.allows(origin)
+            && method.is_some_and(|method| methods.contains(&method))
+            && headers_allowed;
+        if !allowed {
+            let status = StatusCode::FORBIDDEN;
+            let mut response = Response::new(status.to_string().into_bytes());
+            *response.status_mut() = status;
+            response.headers_mut().append(header::VARY, HeaderValue::from_static("origin"));
+            return response;
+        }
+
+        let mut response = Response::new(Vec::new());
+        *response.status_mut() = StatusCode::NO_CONTENT;
+        self.decorate(&mut response, origin);
+        let headers = response.headers_mut();
+        let methods: Vec<&str> = methods.iter().map(Method::as_str).collect();
+        if let Ok(methods) = HeaderValue::from_str(&methods.join(", ")) {
+            headers.insert(header::ACCESS_CONTROL_ALLOW_METHODS, methods);
+        }
+        if !requested.is_empty() {
+            headers.insert(header::ACCESS_CONTROL_ALLOW_HEADERS, join(&requested));
+        }
+        if let Some(max_age) = self.max_age {
+            headers.insert(header::ACCESS_CONTROL_MAX_AGE, max_age.as_secs().into());
+        }
+        headers.append(
+            header::VARY,
+            HeaderValue::from_static("access-control-request-method, access-control-request-headers"),
+        );
+        response
+    }
+}
+
+impl Middleware for Cors {
+    fn handle(&self, req: &mut HttpRequest, next: Next<'_>) -> Response<Vec<u8>> {
+        let Some(origin) = req.headers().get(header::ORIGIN).cloned() else {
+            return next.run(req);
+        };
+        let is_preflight = req.method() == Method::OPTIONS
+            && req.headers().contains_key(header::ACCESS_CONTROL_REQUEST_METHOD);
+        if is_preflight {
+            return self.preflight(req, &origin, &next);
+        }
+
+        let mut response = next.run(req);
+        if self.allows(&origin) {
+            self.decorate(&mut response, &origin);
+        } else {
+            response.headers_mut().append(header::VARY, HeaderValue::from_static("origin"));
+        }
+        response
+    }
+}
+
+fn join(names: &[HeaderName]) -> HeaderValue {
+    let names: Vec<&str> = names.iter().map(HeaderName::as_str).collect();
+    HeaderValue::from_str(&names.join(", ")).unwrap_or(HeaderValue::from_static(""))
+}
@@ -0,0 +1,126 @@
+use std::collections::hash_map::RandomState;
+use std::hash::BuildHasher;
+use std::net::Shutdown;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+use super::Middleware;
+use super::Next;
+use crate::header;
+use crate::HeaderValue;
+use crate::HttpRequest;
+use crate::Response;
+use crate::StatusCode;
+
+/// Misbehaves on purpose, for testing how clients cope with a flaky server.
+///
+/// Each fault fires independently with its own probability in `[0, 1]`:
+///
+/// ```no_run
+/// use blocking_http_server::middleware::FaultInjection;
+/// use blocking_http_server::*;
+/// use std::time::Duration;
+///
+/// let router = Router::new()
+///     .get("/", |_| Response::new(b"hello".to_vec()))
+///     .wrap(
+///         FaultInjection::new()
+///             .latency(0.2, Duration::from_millis(500))
+///             .error(0.05, StatusCode::SERVICE_UNAVAILABLE)
+///             .truncate(0.01)
+///             .drop_connection(0.01),
+///     );
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct FaultInjection {
+    latency: Option<(f64, Duration)>,
+    error: Option<(f64, StatusCode)>,
+    truncate: f64,
+    drop_connection: f64,
+    rolls: AtomicCounter,
+}
+
+impl FaultInjection {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sleeps for `delay` before handling the request.
+    pub fn latency(mut self, probability: f64, delay: Duration) -> Self {
+        self.latency = Some((probability, delay));
+        self
+    }
+
+    /// Answers with `status` instead of calling the handler.
+    pub fn error(mut self, probability: f64, status: StatusCode) -> Self {
+        self.error = Some((probability, status));
+        self
+    }
+
+    /// Sends only half of the body while announcing its full `content-length`, then
+    /// closes the connection.
+    pub fn truncate(mut self, probability: f64) -> Self {
+        self.truncate = probability;
+        self
+    }
+
+    /// Shuts the connection down without answering.
+    pub fn drop_connection(mut self, probability: f64) -> Self {
+        self.drop_connection = probability;
+        self
+    }
+
+    fn fires(&self, probability: f64) -> bool {
+        let n = self.rolls.0.fetch_add(1, Ordering::Relaxed);
+        let roll = (RandomState::new().hash_one(n) % 1_000_000) as f64 / 1_000_000.0;
+        roll < probability
+    }
+}
+
+impl Middleware for FaultInjection {
+    fn handle(&self, req: &mut HttpRequest, next: Next<'_>) -> Response<Vec<u8>> {
+        if let Some((probability, delay)) = self.latency {
+            if self.fires(probability) {
+                std::thread::sleep(delay);
+            }
+        }
+
+        if self.fires(self.drop_connection) {
+            let _ = req.stream.shutdown(Shutdown::Both);
+            // Writing this will fail; there is no one left to read it.
+            let mut response = Response::new(Vec::new());
+            response.headers_mut().insert(header::CONNECTION, HeaderValue::from_static("close"));
+            return response;
+        }
+
+        if let Some((probability, status)) = self.error {
+            if self.fires(probability) {
+                let mut response = Response::new(status.to_string().into_bytes());
+                *response.status_mut() = status;
+                return response;
+            }
+        }
+
+        let mut response = next.run(req);
+        if self.fires(self.truncate) {
+            let len = response.body().len();
+            let headers = response.headers_mut();
+            headers.insert(header::CONTENT_LENGTH, len.into());
+            // The client would take the next response for the rest of the body.
+            headers.insert(header::CONNECTION, HeaderValue::from_static("close"));
+            response.body_mut().truncate(len / 2);
+        }
+        response
+    }
+}
+
+/// `AtomicU64` that clones by value, so `FaultInjection` can derive `Clone`.
+#[derive(Debug, Default)]
+struct AtomicCounter(AtomicU64);
+
+impl Clone for AtomicCounter {
+    fn clone(&self) -> Self {
+        Self(AtomicU64::new(self.0.load(Ordering::Relaxed)))
+    }
+}
@@ -0,0 +1,185 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+use std::time::Instant;
+
+use super::Middleware;
+use super::Next;
+use crate::clock::Clock;
+use crate::clock::SystemClock;
+use crate::header;
+use crate::HttpRequest;
+use crate::Response;
+use crate::StatusCode;
+
+type KeyFn = dyn Fn(&HttpRequest) -> Option<String> + Send + Sync;
+
+/// Buckets kept before the full ones are swept out.
+const MIN_SWEEP: usize = 1024;
+
+/// Limits each client to `per_second` requests on average, with bursts of up to
+/// `burst`, answering the excess `429 Too Many Requests` with a `Retry-After`.
+///
+/// Clients are told apart by `HttpRequest::client_addr` unless `with_key` says
+/// otherwise. Clones share their buckets, so one limiter can guard several
+/// routers served from different threads.
+///
+/// ```no_run
+/// use blocking_http_server::middleware::RateLimiter;
+/// use blocking_http_server::*;
+///
+/// let router = Router::new()
+///     .get("/", |_| Response::new(b"hello".to_vec()))
+///     .wrap(RateLimiter::new(10.0, 20));
+/// ```
+#[derive(Clone)]
+pub struct RateLimiter {
+    per_second: f64,
+    burst: f64,
+    key: Arc<KeyFn>,
+    clock: Arc<dyn Clock>,
+    state: Arc<Mutex<Buckets>>,
+}
+
+#[derive(Debug, Default)]
+struct Buckets {
+    by_key: HashMap<String, Bucket>,
+    /// Size at which the next sweep happens.
+    sweep_at: usize,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl RateLimiter {
+    /// Panics unless `per_second` is positive and `burst` at least 1.
+    pub fn new(per_second: f64, burst: u32) -> Self {
+        assert!(per_second > 0.0, "rate must be positive");
+        assert!(burst >= 1, "burst must allow at least one request");
+        Self {
+            per_second,
+            burst: f64::from(burst),
+            key: Arc::new(|req: &HttpRequest| req.client_addr().map(|ip| ip.to_string())),
+            clock: Arc::new(SystemClock),
+            state: Arc::new(Mutex::new(Buckets {
+                by_key: HashMap::new(),
+                sweep_at: MIN_SWEEP,
+            })),
+        }
+    }
+
+    /// Buckets requests by `key` instead, e.g. an API key header. Requests it
+    /// returns `None` for are not limited.
+    pub fn with_key(mut self, key: impl Fn(&HttpRequest) -> Option<String> + Send + Sync + 'static) -> Self {
+        self.key = Arc::new(key);
+        self
+    }
+
+    pub fn with_clock(mut self, clock: impl Clock + 'static) -> Self {
+        self.clock = Arc::new(clock);
+        self
+    }
+
+    /// Takes a token from `key`'s bucket, or says how long until there is one.
+    pub fn check(&self, key: &str) -> Result<(), Duration> {
+        let now = self.clock.now();
+        let mut state = self.state.lock().unwrap();
+        if state.by_key.len() >= state.sweep_at {
+            // A full bucket is no different from a missing one.
+            let (per_second, burst) = (self.per_second, self.burst);
+            state
+                .by_key
+                .retain(|_, bucket| bucket.refilled(now, per_second, burst) < burst);
+            state.sweep_at = (state.by_key.len() * 2).max(MIN_SWEEP);
+        }
+
+        let bucket = state.by_key.entry(key.to_owned()).or_insert(Bucket {
+            tokens: self.burst,
+            updated: now,
+        });
+        bucket.tokens = bucket.refilled(now, self.per_second, self.burst);
+        bucket.updated = now;
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - bucket.tokens) / self.per_second))
+        }
+    }
+
+    /// The tokens in `key`'s bucket right now, without taking one. `None` if it
+    /// has none yet, or was swept out full.
+    ///
+    /// ```
+    /// use blocking_http_server::clock::ManualClock;
+    /// use blocking_http_server::middleware::RateLimiter;
+    /// use std::time::Duration;
+    ///
+    /// let clock = ManualClock::new();
+    /// let limiter = RateLimiter::new(1.0, 2).with_clock(clock.clone());
+    /// assert_eq!(limiter.tokens("alice"), None);
+    /// limiter.check("alice").unwrap();
+    /// limiter.check("alice").unwrap();
+    /// assert_eq!(limiter.tokens("alice"), Some(0.0));
+    /// assert_eq!(limiter.retry_after("alice"), Some(Duration::from_secs(1)));
+    ///
+    /// clock.advance(Duration::from_millis(1500));
+    /// assert_eq!(limiter.tokens("alice"), Some(1.5));
+    /// assert_eq!(limiter.retry_after("alice"), None);
+    /// ```
+    pub fn tokens(&self, key: &str) -> Option<f64> {
+        let now = self.clock.now();
+        let state = self.state.lock().unwrap();
+        let bucket = state.by_key.get(key)?;
+        Some(bucket.refilled(now, self.per_second, self.burst))
+    }
+
+    /// How long until `key`'s next request would be let through, without taking a
+    /// token; `None` if it would be now.
+    pub fn retry_after(&self, key: &str) -> Option<Duration> {
+        let tokens = self.tokens(key)?;
+        (tokens < 1.0).then(|| Duration::from_secs_f64((1.0 - tokens) / self.per_second))
+    }
+}
+
+impl Bucket {
+    fn refilled(&self, now: Instant, per_second: f64, burst: f64) -> f64 {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        (self.tokens + elapsed * per_second).min(burst)
+    }
+}
+
+impl Middleware for RateLimiter {
+    fn handle(&self, req: &mut HttpRequest, next: Next<'_>) -> Response<Vec<u8>> {
+        let Some(key) = (self.key)(req) else {
+            return next.run(req);
+        };
+        match self.check(&key) {
+            Ok(()) => next.run(req),
+            Err(wait) => {
+                let status = StatusCode::TOO_MANY_REQUESTS;
+                let mut response = Response::new(status.to_string().into_bytes());
+                *response.status_mut() = status;
+                // Whole seconds, rounded up so that a retry on time succeeds.
+                let secs = wait.as_secs() + u64::from(wait.subsec_nanos() > 0);
+                response.headers_mut().insert(header::RETRY_AFTER, secs.into());
+                response
+            }
+        }
+    }
+}
+
+impl fmt::Debug for RateLimiter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RateLimiter")
+            .field("per_second", &self.per_second)
+            .field("burst", &self.burst)
+            .field("clock", &self.clock)
+            .finish_non_exhaustive()
+    }
+}
@@ -0,0 +1,195 @@
+use std::fmt;
+use std::time::Duration;
+
+use super::Middleware;
+use super::Next;
+use crate::header;
+use crate::HeaderMap;
+use crate::HeaderValue;
+use crate::HttpRequest;
+use crate::Response;
+
+/// Adds the usual hardening headers to every response that does not set them
+/// itself: `X-Content-Type-Options: nosniff`, `X-Frame-Options: DENY` and
+/// `Referrer-Policy: strict-origin-when-cross-origin` by default, plus HSTS and a
+/// Content-Security-Policy when configured.
+///
+/// ```no_run
+/// use blocking_http_server::middleware::{ContentSecurityPolicy, SecurityHeaders};
+/// use blocking_http_server::*;
+/// use std::time::Duration;
+///
+/// let csp = ContentSecurityPolicy::new()
+///     .with_directive("default-src", ["'self'"])
+///     .with_directive("img-src", ["'self'", "data:"]);
+/// let router = Router::new()
+///     .get("/", |_| Response::new(b"hello".to_vec()))
+///     .wrap(
+///         SecurityHeaders::new()
+///             .with_hsts(Duration::from_secs(365 * 24 * 3600), true)
+///             .with_csp(&csp),
+///     );
+/// ```
+#[derive(Debug, Clone)]
+pub struct SecurityHeaders {
+    headers: HeaderMap,
+}
+
+/// Whether pages may be framed, for `SecurityHeaders::with_frame_options`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameOptions {
+    Deny,
+    SameOrigin,
+}
+
+impl Default for SecurityHeaders {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SecurityHeaders {
+    pub fn new() -> Self {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::X_CONTENT_TYPE_OPTIONS, HeaderValue::from_static("nosniff"));
+        headers.insert(header::X_FRAME_OPTIONS, HeaderValue::from_static("DENY"));
+        headers.insert(
+            header::REFERRER_POLICY,
+            HeaderValue::from_static("strict-origin-when-cross-origin"),
+        );
+        Self { headers }
+    }
+
+    /// Tells browsers to use HTTPS only for `max_age`. Only send this from a site
+    /// served over HTTPS, e.g. behind a TLS-terminating proxy: browsers remember it.
+    pub fn with_hsts(mut self, max_age: Duration, include_subdomains: bool) -> Self {
+        let mut value = format!("max-age={}", max_age.as_secs());
+        if include_subdomains {
+            value.push_str("; includeSubDomains");
+        }
+        if let Ok(value) = HeaderValue::try_from(value) {
+            self.headers.insert(header::STRICT_TRANSPORT_SECURITY, value);
+        }
+        self
+    }
+
+    /// `None` leaves `X-Frame-Options` out, e.g. when the CSP's `frame-ancestors`
+    /// covers it.
+    pub fn with_frame_options(mut self, options: Option<FrameOptions>) -> Self {
+        match options {
+            Some(FrameOptions::Deny) => {
+                self.headers.insert(header::X_FRAME_OPTIONS, HeaderValue::from_static("DENY"));
+            }
+            Some(FrameOptions::SameOrigin) => {
+                self.headers
+                    .insert(header::X_FRAME_OPTIONS, HeaderValue::from_static("SAMEORIGIN"));
+            }
+            None => {
+                self.headers.remove(header::X_FRAME_OPTIONS);
+            }
+        }
+        self
+    }
+
+    /// Panics if `policy` is not a valid header value.
+    #[track_caller]
+    pub fn with_referrer_policy(mut self, policy: &str) -> Self {
+        let policy = HeaderValue::from_str(policy).expect("invalid referrer policy");
+        self.headers.insert(header::REFERRER_POLICY, policy);
+        self
+    }
+
+    pub fn with_csp(mut self, csp: &ContentSecurityPolicy) -> Self {
+        let (name, value) = csp.header();
+        self.headers.insert(name, value);
+        self
+    }
+}
+
+impl Middleware for SecurityHeaders {
+    fn handle(&self, req: &mut HttpRequest, next: Next<'_>) -> Response<Vec<u8>> {
+        let mut response = next.run(req);
+        let headers = response.headers_mut();
+        for (name, value) in &self.headers {
+            if !headers.contains_key(name) {
+                headers.insert(name, value.clone());
+            }
+        }
+        response
+    }
+}
+
+/// A `Content-Security-Policy`, built up one directive at a time.
+///
+/// ```
+/// use blocking_http_server::middleware::ContentSecurityPolicy;
+///
+/// let csp = ContentSecurityPolicy::new()
+///     .with_directive("default-src", ["'self'"])
+///     .with_directive("script-src", ["'self'"])
+///     .with_directive("script-src", ["https://cdn.example.com"]);
+/// assert_eq!(
+///     csp.to_string(),
+///     "default-src 'self'; script-src 'self' https://cdn.example.com",
+/// );
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ContentSecurityPolicy {
+    directives: Vec<(String, Vec<String>)>,
+    report_only: bool,
+}
+
+impl ContentSecurityPolicy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds `sources` to directive `name`, creating it if needed. Directives
+    /// without sources, like `upgrade-insecure-requests`, take an empty list.
+    pub fn with_directive<S: Into<String>>(mut self, name: &str, sources: impl IntoIterator<Item = S>) -> Self {
+        let sources = sources.into_iter().map(Into::into);
+        match self.directives.iter_mut().find(|(n, _)| n.eq_ignore_ascii_case(name)) {
+            Some((_, existing)) => {
+                for source in sources {
+                    if !existing.contains(&source) {
+                        existing.push(source);
+                    }
+                }
+            }
+            None => self.directives.push((name.to_ascii_lowercase(), sources.collect())),
+        }
+        self
+    }
+
+    /// Sends it as `Content-Security-Policy-Report-Only`, so that violations are
+    /// reported but not blocked.
+    pub fn with_report_only(mut self) -> Self {
+        self.report_only = true;
+        self
+    }
+
+    fn header(&self) -> (header::HeaderName, HeaderValue) {
+        let name = if self.report_only {
+            header::CONTENT_SECURITY_POLICY_REPORT_ONLY
+        } else {
+            header::CONTENT_SECURITY_POLICY
+        };
+        let value = HeaderValue::try_from(self.to_string()).unwrap_or(HeaderValue::from_static("default-src 'none'"));
+        (name, value)
+    }
+}
+
+impl fmt::Display for ContentSecurityPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, (name, sources)) in self.directives.iter().enumerate() {
+            if i > 0 {
+                f.write_str("; ")?;
+            }
+            f.write_str(name)?;
+            for source in sources {
+                write!(f, " {source}")?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,88 @@
+//! Guessing a `Content-Type` from a file extension.
+//!
+//! ```
+//! use blocking_http_server::mime;
+//!
+//! assert_eq!(mime::guess("app.wasm"), "application/wasm");
+//! assert_eq!(mime::from_extension("CSS"), Some("text/css; charset=utf-8"));
+//! assert_eq!(mime::guess("README"), "application/octet-stream");
+//! ```
+
+use std::path::Path;
+
+/// What to send when nothing better is known.
+pub const OCTET_STREAM: &str = "application/octet-stream";
+
+/// Types by extension, kept sorted for the binary search. Text types carry a
+/// charset so browsers need not sniff one.
+const TYPES: &[(&str, &str)] = &[
+    ("7z", "application/x-7z-compressed"),
+    ("aac", "audio/aac"),
+    ("apng", "image/apng"),
+    ("avif", "image/avif"),
+    ("bin", "application/octet-stream"),
+    ("bmp", "image/bmp"),
+    ("css", "text/css; charset=utf-8"),
+    ("csv", "text/csv; charset=utf-8"),
+    ("gif", "image/gif"),
+    ("gz", "application/gzip"),
+    ("htm", "text/html; charset=utf-8"),
+    ("html", "text/html; charset=utf-8"),
+    ("ico", "image/x-icon"),
+    ("ics", "text/calendar; charset=utf-8"),
+    ("jpeg", "image/jpeg"),
+    ("jpg", "image/jpeg"),
+    ("js", "text/javascript; charset=utf-8"),
+    ("json", "application/json"),
+    ("jsonld", "application/ld+json"),
+    ("map", "application/json"),
+    ("md", "text/markdown; charset=utf-8"),
+    ("mjs", "text/javascript; charset=utf-8"),
+    ("mp3", "audio/mpeg"),
+    ("mp4", "video/mp4"),
+    ("oga", "audio/ogg"),
+    ("ogg", "audio/ogg"),
+    ("ogv", "video/ogg"),
+    ("opus", "audio/opus"),
+    ("otf", "font/otf"),
+    ("pdf", "application/pdf"),
+    ("png", "image/png"),
+    ("rss", "application/rss+xml"),
+    ("svg", "image/svg+xml"),
+    ("tar", "application/x-tar"),
+    ("tif", "image/tiff"),
+    ("tiff", "image/tiff"),
+    ("toml", "application/toml"),
+    ("ttf", "font/ttf"),
+    ("txt", "text/plain; charset=utf-8"),
+    ("wasm", "application/wasm"),
+    ("wav", "audio/wav"),
+    ("webm", "video/webm"),
+    ("webmanifest", "application/manifest+json"),
+    ("webp", "image/webp"),
+    ("woff", "font/woff"),
+    ("woff2", "font/woff2"),
+    ("xml", "application/xml"),
+    ("yaml", "application/yaml"),
+    ("yml", "application/yaml"),
+    ("zip", "application/zip"),
+];
+
+/// The type for `extension`, without the dot, in any case.
+pub fn from_extension(extension: &str) -> Option<&'static str> {
+    let extension = extension.to_ascii_lowercase();
+    TYPES
+        .binary_search_by(|(ext, _)| (*ext).cmp(&extension))
+        .ok()
+        .map(|i| TYPES[i].1)
+}
+
+/// The type for the file at `path`, `application/octet-stream` if its
+/// extension is unknown.
+pub fn guess(path: impl AsRef<Path>) -> &'static str {
+    path.as_ref()
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .and_then(from_extension)
+        .unwrap_or(OCTET_STREAM)
+}
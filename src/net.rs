@@ -0,0 +1,367 @@
+use std::fmt;
+use std::io;
+use std::io::Read;
+use std::io::Write;
+use std::net::Shutdown;
+use std::net::SocketAddr;
+use std::net::TcpListener;
+use std::net::TcpStream;
+use std::net::ToSocketAddrs;
+use std::time::Duration;
+
+use socket2::Domain;
+use socket2::Socket;
+use socket2::Type;
+
+#[cfg(unix)]
+use std::os::unix::net::UnixListener;
+#[cfg(unix)]
+use std::os::unix::net::UnixStream;
+
+/// The other end of a connection.
+#[derive(Debug, Clone)]
+pub enum PeerAddr {
+    Tcp(SocketAddr),
+    /// Usually unnamed: clients rarely bind their end of a Unix socket.
+    #[cfg(unix)]
+    Unix(std::os::unix::net::SocketAddr),
+    /// A connection handed in without an address, e.g. stdin/stdout.
+    Unknown,
+}
+
+impl PeerAddr {
+    pub fn as_tcp(&self) -> Option<SocketAddr> {
+        match self {
+            Self::Tcp(addr) => Some(*addr),
+            _ => None,
+        }
+    }
+}
+
+impl From<SocketAddr> for PeerAddr {
+    fn from(addr: SocketAddr) -> Self {
+        Self::Tcp(addr)
+    }
+}
+
+impl fmt::Display for PeerAddr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Tcp(addr) => addr.fmt(f),
+            #[cfg(unix)]
+            Self::Unix(addr) => match addr.as_pathname() {
+                Some(path) => write!(f, "unix:{}", path.display()),
+                None => f.write_str("unix:(unnamed)"),
+            },
+            Self::Unknown => f.write_str("unknown"),
+        }
+    }
+}
+
+/// A connection accepted by a `Server`.
+#[derive(Debug)]
+pub enum Stream {
+    Tcp(TcpStream),
+    #[cfg(unix)]
+    Unix(UnixStream),
+    /// Requests on stdin, responses on stdout. Timeouts are not supported.
+    Stdio,
+}
+
+impl Stream {
+    pub fn as_tcp(&self) -> Option<&TcpStream> {
+        match self {
+            Self::Tcp(stream) => Some(stream),
+            _ => None,
+        }
+    }
+
+    pub fn try_clone(&self) -> io::Result<Self> {
+        match self {
+            Self::Tcp(stream) => stream.try_clone().map(Self::Tcp),
+            #[cfg(unix)]
+            Self::Unix(stream) => stream.try_clone().map(Self::Unix),
+            Self::Stdio => Ok(Self::Stdio),
+        }
+    }
+
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        match self {
+            Self::Tcp(stream) => stream.set_read_timeout(timeout),
+            #[cfg(unix)]
+            Self::Unix(stream) => stream.set_read_timeout(timeout),
+            Self::Stdio => Ok(()),
+        }
+    }
+
+    pub fn set_write_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        match self {
+            Self::Tcp(stream) => stream.set_write_timeout(timeout),
+            #[cfg(unix)]
+            Self::Unix(stream) => stream.set_write_timeout(timeout),
+            Self::Stdio => Ok(()),
+        }
+    }
+
+    pub fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()> {
+        match self {
+            Self::Tcp(stream) => stream.set_nonblocking(nonblocking),
+            #[cfg(unix)]
+            Self::Unix(stream) => stream.set_nonblocking(nonblocking),
+            Self::Stdio if nonblocking => Err(io::ErrorKind::Unsupported.into()),
+            Self::Stdio => Ok(()),
+        }
+    }
+
+    /// Blocks until there is something to read, for when a read hit `WouldBlock`
+    /// because someone switched the stream to non-blocking mode.
+    pub(crate) fn wait_readable(&self, timeout: Option<Duration>) -> io::Result<()> {
+        #[cfg(unix)]
+        {
+            use std::os::fd::AsRawFd;
+            crate::keep_alive::poll_readable(&[self.as_raw_fd()], timeout).map(|_| ())
+        }
+        #[cfg(not(unix))]
+        {
+            let pause = Duration::from_millis(1);
+            std::thread::sleep(timeout.map_or(pause, |t| t.min(pause)));
+            Ok(())
+        }
+    }
+
+    pub fn shutdown(&self, how: Shutdown) -> io::Result<()> {
+        match self {
+            Self::Tcp(stream) => stream.shutdown(how),
+            #[cfg(unix)]
+            Self::Unix(stream) => stream.shutdown(how),
+            Self::Stdio => Ok(()),
+        }
+    }
+}
+
+impl Read for &Stream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Stream::Tcp(stream) => (&*stream).read(buf),
+            #[cfg(unix)]
+            Stream::Unix(stream) => (&*stream).read(buf),
+            Stream::Stdio => io::stdin().read(buf),
+        }
+    }
+}
+
+impl Write for &Stream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Stream::Tcp(stream) => (&*stream).write(buf),
+            #[cfg(unix)]
+            Stream::Unix(stream) => (&*stream).write(buf),
+            Stream::Stdio => io::stdout().write(buf),
+        }
+    }
+
+    fn write_vectored(&mut self, bufs: &[io::IoSlice<'_>]) -> io::Result<usize> {
+        match self {
+            Stream::Tcp(stream) => (&*stream).write_vectored(bufs),
+            #[cfg(unix)]
+            Stream::Unix(stream) => (&*stream).write_vectored(bufs),
+            Stream::Stdio => io::stdout().write_vectored(bufs),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Stream::Tcp(stream) => (&*stream).flush(),
+            #[cfg(unix)]
+            Stream::Unix(stream) => (&*stream).flush(),
+            Stream::Stdio => io::stdout().flush(),
+        }
+    }
+}
+
+impl Read for Stream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        (&*self).read(buf)
+    }
+}
+
+impl Write for Stream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        (&*self).write(buf)
+    }
+
+    fn write_vectored(&mut self, bufs: &[io::IoSlice<'_>]) -> io::Result<usize> {
+        (&*self).write_vectored(bufs)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        (&*self).flush()
+    }
+}
+
+#[cfg(unix)]
+impl std::os::fd::AsRawFd for Stream {
+    fn as_raw_fd(&self) -> std::os::fd::RawFd {
+        match self {
+            Self::Tcp(stream) => stream.as_raw_fd(),
+            Self::Unix(stream) => stream.as_raw_fd(),
+            Self::Stdio => io::stdin().as_raw_fd(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub(crate) enum Listener {
+    Tcp(TcpListener),
+    #[cfg(unix)]
+    Unix(UnixListener),
+    /// Plaintext connections from the TLS and HTTP/2 front end.
+    #[cfg(all(unix, feature = "tls"))]
+    Gateway(std::sync::Arc<crate::front_end::Gateway>),
+}
+
+impl Listener {
+    pub(crate) fn accept(&self) -> io::Result<(Stream, PeerAddr)> {
+        match self {
+            Self::Tcp(listener) => listener.accept().map(|(stream, addr)| {
+                // Dual-stack sockets report IPv4 peers as `::ffff:a.b.c.d`.
+                let addr = SocketAddr::new(addr.ip().to_canonical(), addr.port());
+                (Stream::Tcp(stream), PeerAddr::Tcp(addr))
+            }),
+            #[cfg(unix)]
+            Self::Unix(listener) => listener
+                .accept()
+                .map(|(stream, addr)| (Stream::Unix(stream), PeerAddr::Unix(addr))),
+            #[cfg(all(unix, feature = "tls"))]
+            Self::Gateway(gateway) => gateway.accept(),
+        }
+    }
+
+    #[cfg(not(unix))]
+    pub(crate) fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()> {
+        match self {
+            Self::Tcp(listener) => listener.set_nonblocking(nonblocking),
+        }
+    }
+
+    /// Where to connect to wake up a thread blocked in `accept()`.
+    pub(crate) fn wake_addr(&self) -> io::Result<WakeAddr> {
+        match self {
+            Self::Tcp(listener) => listener.local_addr().map(WakeAddr::Tcp),
+            #[cfg(unix)]
+            Self::Unix(listener) => {
+                let addr = listener.local_addr()?;
+                let path = addr.as_pathname().ok_or_else(|| {
+                    io::Error::new(io::ErrorKind::Unsupported, "unnamed unix listener")
+                })?;
+                Ok(WakeAddr::Unix(path.to_owned()))
+            }
+            #[cfg(all(unix, feature = "tls"))]
+            Self::Gateway(_) => Err(io::Error::new(io::ErrorKind::Unsupported, "not a socket listener")),
+        }
+    }
+}
+
+#[cfg(unix)]
+impl Listener {
+    /// Takes over a listening socket inherited from another process, whether TCP
+    /// or Unix, and marks it close-on-exec.
+    ///
+    /// # Safety
+    ///
+    /// `fd` must be an open listening socket that nothing else owns.
+    pub(crate) unsafe fn inherit(fd: std::os::fd::RawFd) -> io::Result<Self> {
+        use std::os::fd::FromRawFd;
+        use std::os::fd::IntoRawFd;
+
+        if libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC) < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let listener = TcpListener::from_raw_fd(fd);
+        // Only inet sockets have an address a `TcpListener` understands.
+        if listener.local_addr().is_ok() {
+            listener.set_nonblocking(false)?;
+            return Ok(Self::Tcp(listener));
+        }
+        let listener = UnixListener::from_raw_fd(listener.into_raw_fd());
+        listener.local_addr()?;
+        listener.set_nonblocking(false)?;
+        Ok(Self::Unix(listener))
+    }
+}
+
+#[cfg(unix)]
+impl std::os::fd::AsRawFd for Listener {
+    fn as_raw_fd(&self) -> std::os::fd::RawFd {
+        match self {
+            Self::Tcp(listener) => listener.as_raw_fd(),
+            Self::Unix(listener) => listener.as_raw_fd(),
+            #[cfg(feature = "tls")]
+            Self::Gateway(gateway) => gateway.as_raw_fd(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(crate) enum WakeAddr {
+    Tcp(SocketAddr),
+    #[cfg(unix)]
+    Unix(std::path::PathBuf),
+}
+
+/// Socket options that have to be in place before `bind(2)`.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct BindOptions {
+    pub(crate) reuse_port: bool,
+    pub(crate) backlog: u32,
+    /// `IPV6_V6ONLY` for IPv6 addresses; the OS default when `None`.
+    pub(crate) only_v6: Option<bool>,
+}
+
+impl Default for BindOptions {
+    fn default() -> Self {
+        Self {
+            reuse_port: false,
+            backlog: 128,
+            only_v6: None,
+        }
+    }
+}
+
+impl BindOptions {
+    /// Like `TcpListener::bind`: tries each resolved address until one works.
+    pub(crate) fn bind(&self, addr: impl ToSocketAddrs) -> io::Result<TcpListener> {
+        let mut last_err = None;
+        for addr in addr.to_socket_addrs()? {
+            match self.bind_one(addr) {
+                Ok(listener) => return Ok(listener),
+                Err(e) => last_err = Some(e),
+            }
+        }
+        Err(last_err.unwrap_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "could not resolve to any addresses")
+        }))
+    }
+
+    fn bind_one(&self, addr: SocketAddr) -> io::Result<TcpListener> {
+        let socket = Socket::new(Domain::for_address(addr), Type::STREAM, None)?;
+        // What std does on Unix, so restarts don't trip over TIME_WAIT.
+        #[cfg(unix)]
+        socket.set_reuse_address(true)?;
+        #[cfg(unix)]
+        socket.set_reuse_port(self.reuse_port)?;
+        #[cfg(not(unix))]
+        if self.reuse_port {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "SO_REUSEPORT is only available on Unix",
+            ));
+        }
+        if let (true, Some(only_v6)) = (addr.is_ipv6(), self.only_v6) {
+            socket.set_only_v6(only_v6)?;
+        }
+        socket.bind(&addr.into())?;
+        socket.listen(self.backlog.min(i32::MAX as u32) as i32)?;
+        Ok(socket.into())
+    }
+}
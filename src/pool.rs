@@ -0,0 +1,110 @@
+use std::ops::Deref;
+use std::ops::DerefMut;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+use bytes::BytesMut;
+
+/// Request buffers kept around for reuse, all of the configured request size limit.
+#[derive(Debug)]
+pub(crate) struct BufferPool {
+    size: usize,
+    free: Mutex<Vec<BytesMut>>,
+    /// Bytes held by the buffers checked out and what they were charged for, shared
+    /// with the pools this one was resized from.
+    in_use: Arc<AtomicUsize>,
+}
+
+impl BufferPool {
+    /// Buffers beyond this many are freed rather than kept.
+    const MAX_IDLE: usize = 64;
+
+    pub(crate) fn new(size: usize) -> Arc<Self> {
+        Arc::new(Self {
+            size,
+            free: Mutex::default(),
+            in_use: Arc::default(),
+        })
+    }
+
+    /// A pool of `size` buffers that goes on counting those checked out of this one.
+    pub(crate) fn resized(&self, size: usize) -> Arc<Self> {
+        Arc::new(Self {
+            size,
+            free: Mutex::default(),
+            in_use: self.in_use.clone(),
+        })
+    }
+
+    pub(crate) fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Bytes held by the buffers currently checked out.
+    pub(crate) fn bytes_in_use(&self) -> usize {
+        self.in_use.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn take(self: &Arc<Self>) -> PooledBuf {
+        self.in_use.fetch_add(self.size, Ordering::Relaxed);
+        let buf = self.free.lock().unwrap().pop();
+        PooledBuf {
+            buf: buf.unwrap_or_else(|| BytesMut::with_capacity(self.size)),
+            pool: self.clone(),
+            charged: 0,
+        }
+    }
+
+    fn give_back(&self, mut buf: BytesMut, charged: usize) {
+        self.in_use.fetch_sub(self.size + charged, Ordering::Relaxed);
+        buf.clear();
+        // Fails while a part split off from `buf` (the body, say) is still alive.
+        if !buf.try_reclaim(self.size) {
+            return;
+        }
+        let mut free = self.free.lock().unwrap();
+        if free.len() < Self::MAX_IDLE {
+            free.push(buf);
+        }
+    }
+}
+
+/// A buffer checked out of a `BufferPool`, returned to it on drop.
+#[derive(Debug)]
+pub(crate) struct PooledBuf {
+    buf: BytesMut,
+    pool: Arc<BufferPool>,
+    /// Memory allocated apart from `buf` for the same request, like a decoded body.
+    charged: usize,
+}
+
+impl PooledBuf {
+    /// Counts `bytes` held elsewhere for this request against the pool until the
+    /// buffer is dropped, in place of what was charged before.
+    pub(crate) fn charge(&mut self, bytes: usize) {
+        self.pool.in_use.fetch_add(bytes, Ordering::Relaxed);
+        self.pool.in_use.fetch_sub(self.charged, Ordering::Relaxed);
+        self.charged = bytes;
+    }
+}
+
+impl Deref for PooledBuf {
+    type Target = BytesMut;
+    fn deref(&self) -> &BytesMut {
+        &self.buf
+    }
+}
+
+impl DerefMut for PooledBuf {
+    fn deref_mut(&mut self) -> &mut BytesMut {
+        &mut self.buf
+    }
+}
+
+impl Drop for PooledBuf {
+    fn drop(&mut self) {
+        self.pool.give_back(std::mem::take(&mut self.buf), self.charged);
+    }
+}
@@ -0,0 +1,43 @@
+use crate::Version;
+
+/// The request line and headers straight from the parser, before an
+/// `http::Request` is built. See `Server::set_preview_filter`.
+#[derive(Debug)]
+pub struct Preview<'a> {
+    pub(crate) method: &'a str,
+    pub(crate) target: &'a str,
+    pub(crate) version: Version,
+    pub(crate) headers: &'a [httparse::Header<'a>],
+}
+
+impl<'a> Preview<'a> {
+    pub fn method(&self) -> &'a str {
+        self.method
+    }
+
+    /// The request target as sent, query included.
+    pub fn target(&self) -> &'a str {
+        self.target
+    }
+
+    /// The target without its query string.
+    pub fn path(&self) -> &'a str {
+        self.target.split_once('?').map_or(self.target, |(path, _)| path)
+    }
+
+    pub fn version(&self) -> Version {
+        self.version
+    }
+
+    /// The first header named `name`, compared case-insensitively.
+    pub fn header(&self, name: &str) -> Option<&'a [u8]> {
+        self.headers
+            .iter()
+            .find(|h| h.name.eq_ignore_ascii_case(name))
+            .map(|h| h.value)
+    }
+
+    pub fn headers(&self) -> impl Iterator<Item = (&'a str, &'a [u8])> + '_ {
+        self.headers.iter().map(|h| (h.name, h.value))
+    }
+}
@@ -0,0 +1,440 @@
+//! Forwarding requests to an upstream HTTP/1.1 server.
+//!
+//! ```no_run
+//! use blocking_http_server::*;
+//!
+//! let mut server = Server::bind("127.0.0.1:8080")?;
+//! for req in server.incoming().flatten() {
+//!     if let Err(e) = proxy::forward(&req, "127.0.0.1:3000") {
+//!         eprintln!("upstream: {e}");
+//!         let _ = req.respond(Response::new(Vec::new()).with_status(StatusCode::BAD_GATEWAY));
+//!     }
+//! }
+//! # Ok::<(), std::io::Error>(())
+//! ```
+//!
+//! `forward` connects anew for every request. Use an `UpstreamPool` to keep
+//! upstream connections alive between requests instead. `tunnel` serves
+//! `CONNECT` requests, for forward proxies.
+
+use std::collections::HashMap;
+use std::io;
+use std::io::BufRead;
+use std::io::BufReader;
+use std::io::Read;
+use std::io::Write;
+use std::net::Shutdown;
+use std::net::TcpStream;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+use std::time::Instant;
+
+use crate::header;
+use crate::header::HeaderName;
+use crate::unread;
+use crate::HeaderMap;
+use crate::HeaderValue;
+use crate::HttpRequest;
+use crate::Method;
+use crate::Response;
+use crate::ResponseExt;
+use crate::StatusCode;
+use crate::Stream;
+use crate::Version;
+
+/// Connection-level headers, which are not forwarded in either direction.
+const HOP_BY_HOP: [HeaderName; 8] = [
+    header::CONNECTION,
+    HeaderName::from_static("keep-alive"),
+    HeaderName::from_static("proxy-connection"),
+    header::PROXY_AUTHENTICATE,
+    header::PROXY_AUTHORIZATION,
+    header::TE,
+    header::TRANSFER_ENCODING,
+    header::UPGRADE,
+];
+
+/// Longest upstream response head accepted.
+const MAX_HEAD: usize = 64 * 1024;
+
+/// Sends `req` to `upstream` (`host:port`) and streams its response back.
+///
+/// Hop-by-hop headers, and those named in `Connection`, are dropped both ways.
+/// The client's address is appended to `X-Forwarded-For`, and
+/// `X-Forwarded-Proto` is set to `http` unless a proxy trusted with
+/// `Server::set_trusted_proxies` sent one. `Host` is passed through as the
+/// client sent it.
+///
+/// Chunked responses are relayed chunked, trailers included. Chunked request
+/// bodies are only forwarded once `Server::set_chunked_body_limit` has had them
+/// read.
+///
+/// If this fails before the upstream's response head is in, nothing has been
+/// sent to the client yet and `req` can still be answered, e.g. with `502`.
+pub fn forward(req: &HttpRequest, upstream: &str) -> io::Result<()> {
+    let conn = TcpStream::connect(upstream)?;
+    exchange(req, upstream, &conn, false)?;
+    Ok(())
+}
+
+/// Serves a `CONNECT` request: connects to the `host:port` it names, accepts the
+/// tunnel and relays bytes both ways until both sides are done. Whether to allow
+/// the target is up to the caller, who can `respond` with `403` instead.
+///
+/// Answers `400` itself if the request is not a `CONNECT` to `host:port`, and
+/// `502` if the target can't be reached.
+///
+/// ```no_run
+/// use blocking_http_server::*;
+///
+/// let mut server = Server::bind("127.0.0.1:3128")?;
+/// for req in server.incoming().flatten() {
+///     let allowed = req.uri().port_u16() == Some(443);
+///     if req.method() == Method::CONNECT && allowed {
+///         std::thread::spawn(move || proxy::tunnel(req));
+///     } else {
+///         let _ = req.respond(Response::new(Vec::new()).with_status(StatusCode::FORBIDDEN));
+///     }
+/// }
+/// # Ok::<(), std::io::Error>(())
+/// ```
+pub fn tunnel(req: HttpRequest) -> io::Result<()> {
+    let target = req.uri().authority().filter(|a| a.port().is_some()).map(|a| a.to_string());
+    let Some(target) = target.filter(|_| req.method() == Method::CONNECT) else {
+        req.respond(Response::new(Vec::new()).with_status(StatusCode::BAD_REQUEST))?;
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "not a CONNECT to host:port"));
+    };
+    let upstream = match TcpStream::connect(&target) {
+        Ok(upstream) => upstream,
+        Err(e) => {
+            let _ = req.respond(Response::new(Vec::new()).with_status(StatusCode::BAD_GATEWAY));
+            return Err(e);
+        }
+    };
+    let client = req.into_tunnel()?;
+    // Tunnels idle for as long as the two ends like.
+    client.set_read_timeout(None)?;
+    splice(client, upstream)
+}
+
+/// Copies each side's bytes to the other, passing on half-closes.
+fn splice(client: Stream, upstream: TcpStream) -> io::Result<()> {
+    let (client_reader, upstream_writer) = (client.try_clone()?, upstream.try_clone()?);
+    let outbound = std::thread::spawn(move || {
+        let copied = io::copy(&mut &client_reader, &mut &upstream_writer);
+        let _ = upstream_writer.shutdown(Shutdown::Write);
+        copied
+    });
+    let inbound = io::copy(&mut &upstream, &mut &client);
+    let _ = client.shutdown(Shutdown::Write);
+    let outbound = outbound.join().unwrap_or_else(|_| Err(io::Error::other("tunnel thread panicked")));
+    inbound.and(outbound).map(drop)
+}
+
+/// Idle connections by upstream, each with when it went idle, most recent last.
+type IdleConns = HashMap<String, Vec<(TcpStream, Instant)>>;
+
+/// Kept-alive connections to upstream servers, reused across requests. Clones
+/// share the same connections.
+///
+/// ```no_run
+/// use std::time::Duration;
+/// use blocking_http_server::proxy::UpstreamPool;
+/// use blocking_http_server::*;
+///
+/// let pool = UpstreamPool::new().with_max_idle(32).with_idle_timeout(Duration::from_secs(10));
+/// let mut server = Server::bind("127.0.0.1:8080")?;
+/// for req in server.incoming().flatten() {
+///     let pool = pool.clone();
+///     std::thread::spawn(move || {
+///         if pool.forward(&req, "127.0.0.1:3000").is_err() {
+///             let _ = req.respond(Response::new(Vec::new()).with_status(StatusCode::BAD_GATEWAY));
+///         }
+///     });
+/// }
+/// # Ok::<(), std::io::Error>(())
+/// ```
+#[derive(Debug, Clone)]
+pub struct UpstreamPool {
+    idle: Arc<Mutex<IdleConns>>,
+    max_idle: usize,
+    idle_timeout: Duration,
+}
+
+impl Default for UpstreamPool {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl UpstreamPool {
+    /// Keeps up to 8 idle connections per upstream, for up to 30 seconds each.
+    pub fn new() -> Self {
+        Self {
+            idle: Arc::default(),
+            max_idle: 8,
+            idle_timeout: Duration::from_secs(30),
+        }
+    }
+
+    /// Idle connections kept per upstream; more are closed after their response.
+    pub fn with_max_idle(mut self, per_upstream: usize) -> Self {
+        self.max_idle = per_upstream;
+        self
+    }
+
+    /// How long an idle connection is kept. Keep it below the upstream's own
+    /// keep-alive timeout, or connections it has closed will be tried first.
+    pub fn with_idle_timeout(mut self, timeout: Duration) -> Self {
+        self.idle_timeout = timeout;
+        self
+    }
+
+    /// Like `proxy::forward`, over a pooled connection when one is idle.
+    ///
+    /// An idempotent request that finds its pooled connection closed by the
+    /// upstream is retried once on a new connection.
+    pub fn forward(&self, req: &HttpRequest, upstream: &str) -> io::Result<()> {
+        if let Some(conn) = self.take(upstream) {
+            match exchange(req, upstream, &conn, true) {
+                Ok(reusable) => {
+                    if reusable {
+                        self.put(upstream, conn);
+                    }
+                    return Ok(());
+                }
+                Err(e) if e.kind() == io::ErrorKind::ConnectionAborted && req.method().is_idempotent() => {}
+                Err(e) => return Err(e),
+            }
+        }
+        let conn = TcpStream::connect(upstream)?;
+        if exchange(req, upstream, &conn, true)? {
+            self.put(upstream, conn);
+        }
+        Ok(())
+    }
+
+    fn take(&self, upstream: &str) -> Option<TcpStream> {
+        loop {
+            let (conn, since) = {
+                let mut idle = self.idle.lock().unwrap();
+                let conns = idle.get_mut(upstream)?;
+                conns.retain(|(_, since)| since.elapsed() < self.idle_timeout);
+                conns.pop()?
+            };
+            if since.elapsed() < self.idle_timeout && is_open(&conn) {
+                return Some(conn);
+            }
+        }
+    }
+
+    fn put(&self, upstream: &str, conn: TcpStream) {
+        let mut idle = self.idle.lock().unwrap();
+        let conns = idle.entry(upstream.to_string()).or_default();
+        if conns.len() < self.max_idle {
+            conns.push((conn, Instant::now()));
+        }
+    }
+}
+
+/// Whether an idle connection is still usable: not closed by the upstream, and
+/// with nothing unexpected sent on it.
+fn is_open(conn: &TcpStream) -> bool {
+    if conn.set_nonblocking(true).is_err() {
+        return false;
+    }
+    let open = matches!(conn.peek(&mut [0]), Err(e) if e.kind() == io::ErrorKind::WouldBlock);
+    conn.set_nonblocking(false).is_ok() && open
+}
+
+/// Sends `req` over `conn` and relays the response. True if `conn` can carry
+/// another request. With `keep_alive`, a connection that turns out to be closed
+/// before any response arrives fails with `ConnectionAborted`.
+fn exchange(req: &HttpRequest, upstream: &str, conn: &TcpStream, keep_alive: bool) -> io::Result<bool> {
+    let sent = send_request(conn, req, upstream, keep_alive);
+    let mut reader = BufReader::new(conn);
+    let head = sent.and_then(|_| read_head(&mut reader));
+    let mut head = match head {
+        Ok(head) => head,
+        Err(e) if keep_alive && is_disconnect(&e) => {
+            return Err(io::Error::new(io::ErrorKind::ConnectionAborted, e));
+        }
+        Err(e) => return Err(e),
+    };
+    // `100 Continue` and the like were meant for this end: the body is already sent.
+    while head.status().is_informational() && head.status() != StatusCode::SWITCHING_PROTOCOLS {
+        head = read_head(&mut reader)?;
+    }
+    let reusable = relay(req, head, &mut reader)?;
+    Ok(keep_alive && reusable && reader.buffer().is_empty())
+}
+
+fn is_disconnect(e: &io::Error) -> bool {
+    matches!(
+        e.kind(),
+        io::ErrorKind::UnexpectedEof
+            | io::ErrorKind::ConnectionReset
+            | io::ErrorKind::ConnectionAborted
+            | io::ErrorKind::BrokenPipe
+    )
+}
+
+fn send_request(mut conn: &TcpStream, req: &HttpRequest, upstream: &str, keep_alive: bool) -> io::Result<()> {
+    let target = req.uri().path_and_query().map_or("/", |p| p.as_str());
+    let mut head = format!("{} {target} HTTP/1.1\r\n", req.method()).into_bytes();
+
+    let headers = req.headers();
+    let mut forwarded = without_hop_by_hop(headers);
+    forwarded.remove(header::EXPECT);
+    forwarded.remove(header::CONTENT_LENGTH);
+    if !forwarded.contains_key(header::HOST) {
+        forwarded.insert(header::HOST, HeaderValue::from_str(upstream).map_err(io::Error::other)?);
+    }
+    let peer = req.peer_addr.as_tcp().map(|peer| peer.ip());
+    if let Some(peer) = peer {
+        let mut chain: Vec<&str> = headers
+            .get_all("x-forwarded-for")
+            .into_iter()
+            .filter_map(|v| v.to_str().ok())
+            .collect();
+        let peer = peer.to_string();
+        chain.push(&peer);
+        forwarded.insert("x-forwarded-for", HeaderValue::from_str(&chain.join(", ")).map_err(io::Error::other)?);
+    }
+    // Only a trusted proxy in front knows how the client connected.
+    let trusted = peer.is_some_and(|ip| req.trusted_proxies.iter().any(|cidr| cidr.contains(ip)));
+    if !trusted || !forwarded.contains_key("x-forwarded-proto") {
+        forwarded.insert("x-forwarded-proto", HeaderValue::from_static("http"));
+    }
+    crate::write_headers(&mut head, &forwarded)?;
+    if !keep_alive {
+        head.extend_from_slice(b"connection: close\r\n");
+    }
+
+    let body = req.body();
+    if headers.contains_key(header::TRANSFER_ENCODING) {
+        head.extend_from_slice(b"transfer-encoding: chunked\r\n\r\n");
+        if !body.is_empty() {
+            write!(head, "{:x}\r\n", body.len())?;
+            head.extend_from_slice(body);
+            head.extend_from_slice(b"\r\n");
+        }
+        head.extend_from_slice(b"0\r\n");
+        crate::write_headers(&mut head, req.trailers())?;
+        head.extend_from_slice(b"\r\n");
+        conn.write_all(&head)?;
+    } else {
+        if !body.is_empty() || headers.contains_key(header::CONTENT_LENGTH) {
+            write!(head, "content-length: {}\r\n", body.len())?;
+        }
+        head.extend_from_slice(b"\r\n");
+        crate::write_all_vectored(&mut conn, &head, body)?;
+    }
+    conn.flush()
+}
+
+/// Reads a response head, leaving `reader` at the start of the body.
+pub(crate) fn read_head(reader: &mut impl BufRead) -> io::Result<Response<()>> {
+    let mut raw = Vec::new();
+    loop {
+        let n = (&mut *reader).take((MAX_HEAD - raw.len()) as u64).read_until(b'\n', &mut raw)?;
+        if n == 0 {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "upstream closed before responding"));
+        }
+        if raw.ends_with(b"\r\n\r\n") || raw.ends_with(b"\n\n") {
+            break;
+        }
+        if raw.len() >= MAX_HEAD {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "upstream response head too large"));
+        }
+    }
+
+    let mut headers = [httparse::EMPTY_HEADER; 128];
+    let mut parsed = httparse::Response::new(&mut headers);
+    if !parsed.parse(&raw).map_err(io::Error::other)?.is_complete() {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "truncated upstream response head"));
+    }
+    let status = StatusCode::from_u16(parsed.code.unwrap_or(0)).map_err(io::Error::other)?;
+    let mut response = Response::new(());
+    *response.status_mut() = status;
+    if parsed.version == Some(0) {
+        *response.version_mut() = Version::HTTP_10;
+    }
+    for h in parsed.headers.iter() {
+        let name = HeaderName::from_bytes(h.name.as_bytes()).map_err(io::Error::other)?;
+        let value = HeaderValue::from_bytes(h.value).map_err(io::Error::other)?;
+        response.headers_mut().append(name, value);
+    }
+    Ok(response)
+}
+
+/// True if the upstream connection is left at the start of its next response.
+fn relay(req: &HttpRequest, upstream: Response<()>, mut reader: impl BufRead) -> io::Result<bool> {
+    let chunked = upstream
+        .headers()
+        .get(header::TRANSFER_ENCODING)
+        .is_some_and(|v| v.as_bytes().trim_ascii_end().to_ascii_lowercase().ends_with(b"chunked"));
+    let bodiless = req.method() == Method::HEAD
+        || upstream.status().is_informational()
+        || upstream.status() == StatusCode::NO_CONTENT
+        || upstream.status() == StatusCode::NOT_MODIFIED;
+
+    let mut head = Response::new(());
+    *head.status_mut() = upstream.status();
+    *head.headers_mut() = without_hop_by_hop(upstream.headers());
+    if chunked {
+        // Re-chunked on the way out, whatever length the upstream claimed.
+        head.headers_mut().remove(header::CONTENT_LENGTH);
+    }
+    let length = head
+        .headers()
+        .get(header::CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok()?.parse::<u64>().ok());
+    let persistent = upstream.version() == Version::HTTP_11
+        && !upstream
+            .headers()
+            .get_all(header::CONNECTION)
+            .iter()
+            .any(|v| v.as_bytes().split(|&b| b == b',').any(|o| o.trim_ascii().eq_ignore_ascii_case(b"close")));
+    let framed = bodiless || chunked || length.is_some();
+
+    let mut body = req.respond_chunked(head)?;
+    let mut trailers = HeaderMap::new();
+    if bodiless {
+        // Nothing follows the head.
+    } else if chunked {
+        if !unread::decode_chunks(&mut reader, &mut body, Some(&mut trailers))? {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "malformed chunked upstream body"));
+        }
+        trailers = trailers
+            .iter()
+            .filter(|(name, _)| body.is_declared(name))
+            .map(|(name, value)| (name.clone(), value.clone()))
+            .collect();
+    } else if let Some(length) = length {
+        let copied = io::copy(&mut reader.take(length), &mut body)?;
+        if copied != length {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "upstream body cut short"));
+        }
+    } else {
+        io::copy(&mut reader, &mut body)?;
+    }
+    body.finish(&trailers)?;
+    Ok(persistent && framed)
+}
+
+/// `headers` without hop-by-hop fields, including those listed in `Connection`.
+pub(crate) fn without_hop_by_hop(headers: &HeaderMap) -> HeaderMap {
+    let listed: Vec<HeaderName> = headers
+        .get_all(header::CONNECTION)
+        .iter()
+        .flat_map(|v| v.as_bytes().split(|&b| b == b','))
+        .filter_map(|name| HeaderName::from_bytes(name.trim_ascii()).ok())
+        .collect();
+    let mut kept = headers.clone();
+    for name in HOP_BY_HOP.iter().chain(&listed) {
+        kept.remove(name);
+    }
+    kept
+}
@@ -0,0 +1,206 @@
+use std::borrow::Cow;
+use std::fmt;
+
+#[cfg(feature = "serde")]
+pub use typed::InvalidQuery;
+
+/// How `Query::get` treats a key that appears more than once.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DuplicatePolicy {
+    #[default]
+    FirstWins,
+    LastWins,
+    /// Strict mode: a repeated key is an error rather than silently picking one value.
+    Reject,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DuplicateKey(pub String);
+
+impl fmt::Display for DuplicateKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "duplicate query parameter {:?}", self.0)
+    }
+}
+
+impl std::error::Error for DuplicateKey {}
+
+/// Decoded `application/x-www-form-urlencoded` pairs, in their original order.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Query {
+    pairs: Vec<(String, String)>,
+    policy: DuplicatePolicy,
+}
+
+impl Query {
+    pub fn parse(query: &str) -> Self {
+        let pairs = pairs(query).map(|(k, v)| (k.into_owned(), v.into_owned())).collect();
+        Self {
+            pairs,
+            policy: DuplicatePolicy::default(),
+        }
+    }
+
+    pub fn with_policy(mut self, policy: DuplicatePolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    pub fn policy(&self) -> DuplicatePolicy {
+        self.policy
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.pairs.iter().map(|(k, v)| (k.as_str(), v.as_str()))
+    }
+
+    pub fn get_all<'a>(&'a self, key: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.iter().filter(move |(k, _)| *k == key).map(|(_, v)| v)
+    }
+
+    /// Single-value lookup following the configured `DuplicatePolicy`.
+    pub fn get(&self, key: &str) -> Result<Option<&str>, DuplicateKey> {
+        let mut values = self
+            .pairs
+            .iter()
+            .filter(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str());
+        match self.policy {
+            DuplicatePolicy::FirstWins => Ok(values.next()),
+            DuplicatePolicy::LastWins => Ok(values.next_back()),
+            DuplicatePolicy::Reject => {
+                let first = values.next();
+                if values.next().is_some() {
+                    return Err(DuplicateKey(key.to_string()));
+                }
+                Ok(first)
+            }
+        }
+    }
+
+    pub fn contains_key(&self, key: &str) -> bool {
+        self.get_all(key).next().is_some()
+    }
+
+    pub fn len(&self) -> usize {
+        self.pairs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pairs.is_empty()
+    }
+}
+
+/// The decoded pairs of `query`, without copying what needs no decoding.
+pub(crate) fn pairs(query: &str) -> impl Iterator<Item = (Cow<'_, str>, Cow<'_, str>)> {
+    query.split('&').filter(|s| !s.is_empty()).map(|pair| {
+        let (k, v) = pair.split_once('=').unwrap_or((pair, ""));
+        (decode(k), decode(v))
+    })
+}
+
+/// Percent-decodes `s`, treating `+` as a space. Invalid UTF-8 is replaced lossily.
+pub fn decode(s: &str) -> Cow<'_, str> {
+    if !s.bytes().any(|b| b == b'%' || b == b'+') {
+        return Cow::Borrowed(s);
+    }
+
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => out.push(b' '),
+            b'%' if i + 2 < bytes.len() => {
+                match (hex(bytes[i + 1]), hex(bytes[i + 2])) {
+                    (Some(hi), Some(lo)) => {
+                        out.push(hi << 4 | lo);
+                        i += 2;
+                    }
+                    _ => out.push(b'%'),
+                }
+            }
+            b => out.push(b),
+        }
+        i += 1;
+    }
+    match String::from_utf8(out) {
+        Ok(s) => Cow::Owned(s),
+        Err(e) => Cow::Owned(String::from_utf8_lossy(e.as_bytes()).into_owned()),
+    }
+}
+
+fn hex(b: u8) -> Option<u8> {
+    match b {
+        b'0'..=b'9' => Some(b - b'0'),
+        b'a'..=b'f' => Some(b - b'a' + 10),
+        b'A'..=b'F' => Some(b - b'A' + 10),
+        _ => None,
+    }
+}
+
+#[cfg(feature = "serde")]
+mod typed {
+    use std::fmt;
+    use std::io;
+
+    use crate::HttpRequest;
+    use crate::Response;
+    use crate::StatusCode;
+
+    /// A query string that `HttpRequest::query_as` could not turn into the type asked
+    /// for. Converts into a `400 Bad Request` naming the problem.
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub struct InvalidQuery(pub String);
+
+    impl fmt::Display for InvalidQuery {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "invalid query string: {}", self.0)
+        }
+    }
+
+    impl std::error::Error for InvalidQuery {}
+
+    impl From<InvalidQuery> for Response<Vec<u8>> {
+        fn from(e: InvalidQuery) -> Self {
+            let mut response = Response::new(e.to_string().into_bytes());
+            *response.status_mut() = StatusCode::BAD_REQUEST;
+            response
+        }
+    }
+
+    impl From<InvalidQuery> for io::Error {
+        fn from(e: InvalidQuery) -> Self {
+            io::Error::new(io::ErrorKind::InvalidData, e)
+        }
+    }
+
+    impl HttpRequest {
+        /// Deserializes the query string into `T`, e.g. a struct whose optional
+        /// parameters are `Option`s or have `#[serde(default)]`.
+        ///
+        /// ```no_run
+        /// use blocking_http_server::*;
+        /// use serde::Deserialize;
+        ///
+        /// #[derive(Deserialize)]
+        /// struct Search {
+        ///     q: String,
+        ///     #[serde(default)]
+        ///     page: u32,
+        ///     sort: Option<String>,
+        /// }
+        ///
+        /// let router = Router::new().get("/search", |req| {
+        ///     let search: Search = match req.query_as() {
+        ///         Ok(search) => search,
+        ///         Err(e) => return e.into(),
+        ///     };
+        ///     Response::new(format!("{} page {} by {:?}", search.q, search.page, search.sort).into_bytes())
+        /// });
+        /// ```
+        pub fn query_as<T: serde::de::DeserializeOwned>(&self) -> Result<T, InvalidQuery> {
+            serde_urlencoded::from_str(self.uri().query().unwrap_or("")).map_err(|e| InvalidQuery(e.to_string()))
+        }
+    }
+}
@@ -0,0 +1,161 @@
+//! Byte range requests: answering `Range: bytes=...` with `206 Partial Content`,
+//! as for video seeking and resumed downloads.
+//!
+//! `files::ServeDir` handles them by itself; `apply` does the same for any
+//! response held in memory:
+//!
+//! ```no_run
+//! use blocking_http_server::*;
+//!
+//! let router = Router::new().get("/report.pdf", |req| {
+//!     range::apply(req, Response::new(std::fs::read("report.pdf").unwrap_or_default()))
+//! });
+//! ```
+
+use std::ops::Range;
+
+use crate::header;
+use crate::HeaderMap;
+use crate::HeaderValue;
+use crate::HttpRequest;
+use crate::Method;
+use crate::Response;
+use crate::StatusCode;
+
+/// What a request asks of a representation, given its length and validators.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Ranged {
+    /// No usable `Range`, or an `If-Range` that no longer matches: send it all.
+    Full,
+    Partial(Range<u64>),
+    /// The range starts past the end: `416 Range Not Satisfiable`.
+    Unsatisfiable,
+}
+
+/// Evaluates the `Range` and `If-Range` headers of `req` against a representation
+/// of `len` bytes whose `ETag` and `Last-Modified` are among `headers`.
+///
+/// Only `GET` requests with a single range are served partially; several ranges
+/// would need a `multipart/byteranges` body, so they get the whole thing, as
+/// RFC 9110 allows.
+pub fn evaluate(req: &HttpRequest, len: u64, headers: &HeaderMap) -> Ranged {
+    if req.method() != Method::GET {
+        return Ranged::Full;
+    }
+    let Some(range) = req.headers().get(header::RANGE) else {
+        return Ranged::Full;
+    };
+    if let Some(if_range) = req.headers().get(header::IF_RANGE) {
+        if !if_range_matches(if_range, headers) {
+            return Ranged::Full;
+        }
+    }
+    parse(range.as_bytes(), len)
+}
+
+/// Cuts `response` down to the range `req` asks for. Only `200`s are touched; they
+/// also get `Accept-Ranges: bytes`, to tell clients they can ask.
+pub fn apply(req: &HttpRequest, mut response: Response<Vec<u8>>) -> Response<Vec<u8>> {
+    if response.status() != StatusCode::OK {
+        return response;
+    }
+    let len = response.body().len() as u64;
+    match evaluate(req, len, response.headers()) {
+        Ranged::Full => {
+            response
+                .headers_mut()
+                .insert(header::ACCEPT_RANGES, HeaderValue::from_static("bytes"));
+        }
+        Ranged::Partial(range) => {
+            let body = response.body_mut();
+            body.truncate(range.end as usize);
+            body.drain(..range.start as usize);
+            set_partial(&mut response, &range, len);
+        }
+        Ranged::Unsatisfiable => {
+            response.body_mut().clear();
+            set_unsatisfiable(&mut response, len);
+        }
+    }
+    response
+}
+
+/// Turns a `200` head into the `206` for `range` of a `len` byte representation.
+pub(crate) fn set_partial<T>(response: &mut Response<T>, range: &Range<u64>, len: u64) {
+    *response.status_mut() = StatusCode::PARTIAL_CONTENT;
+    let headers = response.headers_mut();
+    headers.remove(header::CONTENT_LENGTH);
+    let content_range = format!("bytes {}-{}/{len}", range.start, range.end - 1);
+    if let Ok(content_range) = HeaderValue::try_from(content_range) {
+        headers.insert(header::CONTENT_RANGE, content_range);
+    }
+    headers.insert(header::ACCEPT_RANGES, HeaderValue::from_static("bytes"));
+}
+
+pub(crate) fn set_unsatisfiable<T>(response: &mut Response<T>, len: u64) {
+    *response.status_mut() = StatusCode::RANGE_NOT_SATISFIABLE;
+    let headers = response.headers_mut();
+    headers.remove(header::CONTENT_LENGTH);
+    headers.remove(header::CONTENT_TYPE);
+    if let Ok(content_range) = HeaderValue::try_from(format!("bytes */{len}")) {
+        headers.insert(header::CONTENT_RANGE, content_range);
+    }
+}
+
+/// A `Range` header value that is not a single valid byte range is ignored.
+fn parse(value: &[u8], len: u64) -> Ranged {
+    let value = value.trim_ascii();
+    let spec = value
+        .get(6..)
+        .filter(|_| value[..6].eq_ignore_ascii_case(b"bytes="))
+        .and_then(|spec| std::str::from_utf8(spec).ok());
+    let Some(spec) = spec else {
+        return Ranged::Full;
+    };
+    if spec.contains(',') {
+        return Ranged::Full;
+    }
+    let Some((first, last)) = spec.trim().split_once('-') else {
+        return Ranged::Full;
+    };
+    let (first, last) = (first.trim(), last.trim());
+    let range = if first.is_empty() {
+        // A suffix: the last `n` bytes.
+        let Ok(n) = last.parse::<u64>() else {
+            return Ranged::Full;
+        };
+        if n == 0 {
+            return Ranged::Unsatisfiable;
+        }
+        len.saturating_sub(n)..len
+    } else {
+        let Ok(first) = first.parse::<u64>() else {
+            return Ranged::Full;
+        };
+        let last = match last {
+            "" => u64::MAX,
+            last => match last.parse::<u64>() {
+                Ok(last) if last >= first => last,
+                _ => return Ranged::Full,
+            },
+        };
+        first..last.saturating_add(1).min(len)
+    };
+    if range.start >= len {
+        return Ranged::Unsatisfiable;
+    }
+    Ranged::Partial(range)
+}
+
+/// An `If-Range` holds an entity tag, which must match the current one strongly,
+/// or a date, which must be the exact `Last-Modified`.
+fn if_range_matches(if_range: &HeaderValue, headers: &HeaderMap) -> bool {
+    let if_range = if_range.as_bytes().trim_ascii();
+    if if_range.starts_with(b"\"") || if_range.starts_with(b"W/") {
+        let etag = headers.get(header::ETAG).map(|etag| etag.as_bytes().trim_ascii());
+        return !if_range.starts_with(b"W/") && etag == Some(if_range);
+    }
+    headers
+        .get(header::LAST_MODIFIED)
+        .is_some_and(|modified| modified.as_bytes().trim_ascii() == if_range)
+}
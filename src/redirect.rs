@@ -0,0 +1,104 @@
+use std::io;
+use std::net::SocketAddr;
+use std::net::ToSocketAddrs;
+use std::time::Duration;
+
+use crate::header;
+use crate::HeaderValue;
+use crate::HttpRequest;
+use crate::Method;
+use crate::Response;
+use crate::Server;
+use crate::ShutdownHandle;
+use crate::StatusCode;
+
+impl Server {
+    /// Listens for plain HTTP on `addr` in a thread of its own, sending every
+    /// request to the same host and path over HTTPS on `https_port`. `GET` and
+    /// `HEAD` get a `301`; other methods a `308`, so browsers do not turn them
+    /// into a `GET`.
+    ///
+    /// This is the port 80 half of a site served over HTTPS, whether by `bind_tls`
+    /// or by a proxy in front.
+    ///
+    /// ```no_run
+    /// # fn main() -> std::io::Result<()> {
+    /// let redirect = blocking_http_server::Server::spawn_https_redirect("0.0.0.0:80", 443)?;
+    /// // ...
+    /// redirect.shutdown();
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn spawn_https_redirect(addr: impl ToSocketAddrs, https_port: u16) -> io::Result<ShutdownHandle> {
+        let mut server = Server::bind(addr)?;
+        // One thread serves them all; a slow client must not hold up the rest.
+        server.set_read_timeout(Some(Duration::from_secs(10)));
+        server.set_write_timeout(Some(Duration::from_secs(10)));
+        let handle = server.shutdown_handle()?;
+        std::thread::spawn(move || {
+            for req in server.incoming().flatten() {
+                let _ = req.respond(https_redirect(&req, https_port));
+            }
+        });
+        Ok(handle)
+    }
+
+    /// Like `spawn_https_redirect`, listening on `port` of this server's address
+    /// and redirecting to this server's own port, for when that is where clients
+    /// reach HTTPS.
+    ///
+    /// ```no_run
+    /// # fn main() -> std::io::Result<()> {
+    /// let server = blocking_http_server::Server::bind("0.0.0.0:443")?;
+    /// let redirect = server.with_http_redirect(80)?;
+    /// // ...
+    /// redirect.shutdown();
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn with_http_redirect(&self, port: u16) -> io::Result<ShutdownHandle> {
+        let https = self.local_addr()?;
+        Self::spawn_https_redirect(SocketAddr::new(https.ip(), port), https.port())
+    }
+}
+
+fn https_redirect(req: &HttpRequest, https_port: u16) -> Response<Vec<u8>> {
+    let host = req
+        .uri()
+        .authority()
+        .map(|authority| authority.host().to_owned())
+        .or_else(|| {
+            let host = req.headers().get(header::HOST)?.to_str().ok()?;
+            Some(strip_port(host).to_owned())
+        })
+        .filter(|host| !host.is_empty());
+    let Some(host) = host else {
+        let mut response = Response::new(b"Bad Request: no host to redirect to".to_vec());
+        *response.status_mut() = StatusCode::BAD_REQUEST;
+        return response;
+    };
+
+    let path = req.uri().path_and_query().map_or("/", |path| path.as_str());
+    let location = match https_port {
+        443 => format!("https://{host}{path}"),
+        port => format!("https://{host}:{port}{path}"),
+    };
+    let status = match *req.method() {
+        Method::GET | Method::HEAD => StatusCode::MOVED_PERMANENTLY,
+        _ => StatusCode::PERMANENT_REDIRECT,
+    };
+    let mut response = Response::new(Vec::new());
+    *response.status_mut() = status;
+    if let Ok(location) = HeaderValue::try_from(location) {
+        response.headers_mut().insert(header::LOCATION, location);
+    }
+    response
+}
+
+/// `host` from `host:port`, brackets kept on IPv6 literals.
+fn strip_port(host: &str) -> &str {
+    match host.rfind(':') {
+        Some(i) if !host[i..].contains(']') => &host[..i],
+        _ => host,
+    }
+}
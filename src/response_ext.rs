@@ -0,0 +1,51 @@
+use crate::header;
+use crate::header::HeaderValue;
+use crate::header::IntoHeaderName;
+use crate::Response;
+use crate::StatusCode;
+
+/// Chainable setters for `Response`, without the `Result` that
+/// `Response::builder()` makes every caller unwrap.
+///
+/// ```
+/// use blocking_http_server::*;
+///
+/// let response = Response::new(b"{}".to_vec())
+///     .with_status(StatusCode::CREATED)
+///     .with_content_type("application/json")
+///     .with_header(header::LOCATION, HeaderValue::from_static("/items/1"));
+/// assert_eq!(response.status(), StatusCode::CREATED);
+/// ```
+pub trait ResponseExt<T>: Sized {
+    fn with_status(self, status: StatusCode) -> Self;
+
+    /// Replaces any values already set for `name`.
+    fn with_header(self, name: impl IntoHeaderName, value: HeaderValue) -> Self;
+
+    /// Panics if `content_type` is not a valid header value.
+    fn with_content_type(self, content_type: &str) -> Self;
+
+    fn map_body<U>(self, f: impl FnOnce(T) -> U) -> Response<U>;
+}
+
+impl<T> ResponseExt<T> for Response<T> {
+    fn with_status(mut self, status: StatusCode) -> Self {
+        *self.status_mut() = status;
+        self
+    }
+
+    fn with_header(mut self, name: impl IntoHeaderName, value: HeaderValue) -> Self {
+        self.headers_mut().insert(name, value);
+        self
+    }
+
+    #[track_caller]
+    fn with_content_type(self, content_type: &str) -> Self {
+        let value = HeaderValue::from_str(content_type).expect("invalid content type");
+        self.with_header(header::CONTENT_TYPE, value)
+    }
+
+    fn map_body<U>(self, f: impl FnOnce(T) -> U) -> Response<U> {
+        self.map(f)
+    }
+}
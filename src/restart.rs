@@ -0,0 +1,108 @@
+//! Zero-downtime restarts: the running process passes its listening sockets to
+//! a successor, which keeps accepting on them while the old one drains.
+//!
+//! ```no_run
+//! use blocking_http_server::*;
+//! use std::process::Command;
+//! use std::sync::atomic::{AtomicBool, Ordering};
+//! use std::sync::Arc;
+//! use std::time::Duration;
+//!
+//! # fn main() -> std::io::Result<()> {
+//! let mut server = match Server::from_inherited() {
+//!     Ok(server) => server,
+//!     Err(_) => Server::bind("0.0.0.0:8080")?,
+//! };
+//! let restart = Arc::new(AtomicBool::new(false));
+//! // e.g. signal_hook::flag::register(SIGHUP, restart.clone())
+//! while !restart.load(Ordering::SeqCst) {
+//!     if let Ok(req) = server.recv_timeout(Duration::from_secs(1)) {
+//!         let _ = req.respond(Response::new("hello"));
+//!     }
+//! }
+//! server.hand_off(&mut Command::new(std::env::current_exe()?))?;
+//! server.drain(Duration::from_secs(30));
+//! # Ok(())
+//! # }
+//! ```
+
+use std::io;
+use std::os::fd::AsRawFd;
+use std::os::fd::RawFd;
+use std::os::unix::process::CommandExt;
+use std::process::Child;
+use std::process::Command;
+
+use crate::net::Listener;
+use crate::Server;
+
+/// Comma-separated listening descriptors passed to the successor.
+const INHERITED_FDS: &str = "BLOCKING_HTTP_SERVER_FDS";
+
+impl Server {
+    /// Starts `command` with this server's listening sockets, then closes them here
+    /// so that `incoming()` ends and every new connection goes to the successor.
+    /// Connections waiting in the backlog are not lost: the queue belongs to the
+    /// sockets, which the successor now holds. Requests already handed out are
+    /// unaffected; `drain` waits for them.
+    ///
+    /// Call it from the thread running `incoming()`, between requests, and have the
+    /// successor pick the sockets up with `Server::from_inherited`. Listeners added
+    /// with `bind_tls` are not passed on, but closed all the same.
+    pub fn hand_off(&mut self, command: &mut Command) -> io::Result<Child> {
+        let fds: Vec<RawFd> = (0..self.listeners.len())
+            .filter(|&i| self.is_inheritable(i))
+            .map(|i| self.listeners[i].as_raw_fd())
+            .collect();
+        let list = fds
+            .iter()
+            .map(|fd| fd.to_string())
+            .collect::<Vec<_>>()
+            .join(",");
+        command.env(INHERITED_FDS, list);
+        // SAFETY: only async-signal-safe fcntl(2) calls run between fork and exec.
+        unsafe {
+            command.pre_exec(move || {
+                for &fd in &fds {
+                    let flags = libc::fcntl(fd, libc::F_GETFD);
+                    if flags < 0 || libc::fcntl(fd, libc::F_SETFD, flags & !libc::FD_CLOEXEC) < 0 {
+                        return Err(io::Error::last_os_error());
+                    }
+                }
+                Ok(())
+            });
+        }
+        let child = command.spawn()?;
+        self.listeners.clear();
+        Ok(child)
+    }
+
+    /// The successor could not tell a TLS listener from a plain one, and the
+    /// front end's gateway is no socket at all.
+    fn is_inheritable(&self, listener: usize) -> bool {
+        #[cfg(feature = "tls")]
+        let tls = self.terminates_tls(listener);
+        #[cfg(not(feature = "tls"))]
+        let tls = false;
+        !tls && !self.is_gateway(listener)
+    }
+
+    /// Picks up the sockets passed by a predecessor's `hand_off`. Fails with
+    /// `NotFound` when there are none, i.e. on a regular start.
+    pub fn from_inherited() -> io::Result<Self> {
+        let list = std::env::var(INHERITED_FDS)
+            .map_err(|_| io::Error::new(io::ErrorKind::NotFound, "no inherited listeners"))?;
+        std::env::remove_var(INHERITED_FDS);
+        let listeners = list
+            .split(',')
+            .map(|fd| {
+                let fd: RawFd = fd.parse().map_err(|_| {
+                    io::Error::new(io::ErrorKind::InvalidInput, "invalid inherited descriptor")
+                })?;
+                // SAFETY: the predecessor passed these descriptors for this process to own.
+                unsafe { Listener::inherit(fd) }
+            })
+            .collect::<io::Result<Vec<_>>>()?;
+        Self::from_listeners(listeners)
+    }
+}
@@ -0,0 +1,359 @@
+use std::collections::HashMap;
+use std::io;
+use std::io::Write;
+use std::net::TcpStream;
+
+use crate::header;
+use crate::middleware::Middleware;
+use crate::middleware::Next;
+use crate::HttpRequest;
+use crate::Method;
+use crate::Response;
+use crate::Server;
+use crate::StatusCode;
+
+pub type Handler = Box<dyn Fn(&HttpRequest) -> Response<Vec<u8>> + Send + Sync>;
+
+/// Dispatches requests by method and path pattern.
+///
+/// Patterns are `/`-separated; a `:name` segment matches any single segment and a
+/// trailing `*` matches the rest of the path. On top of the registered routes the
+/// router answers `HEAD` from the `GET` handler, `OPTIONS` with `204` plus `Allow`,
+/// and unregistered methods with `405` plus `Allow`.
+#[derive(Default)]
+pub struct Router {
+    routes: Vec<Route>,
+    middleware: Vec<Box<dyn Middleware>>,
+}
+
+struct Route {
+    method: Method,
+    pattern: String,
+    handler: Handler,
+}
+
+/// Parameters captured by the matched route, stored in the request extensions.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Params {
+    pattern: String,
+    values: HashMap<String, String>,
+}
+
+impl Params {
+    /// The route pattern that matched, e.g. `/users/:id`.
+    pub fn pattern(&self) -> &str {
+        &self.pattern
+    }
+
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.values.get(name).map(String::as_str)
+    }
+}
+
+impl Router {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn route<F>(mut self, method: Method, pattern: &str, handler: F) -> Self
+    where
+        F: Fn(&HttpRequest) -> Response<Vec<u8>> + Send + Sync + 'static,
+    {
+        self.routes.push(Route {
+            method,
+            pattern: pattern.to_string(),
+            handler: Box::new(handler),
+        });
+        self
+    }
+
+    pub fn get<F>(self, pattern: &str, handler: F) -> Self
+    where
+        F: Fn(&HttpRequest) -> Response<Vec<u8>> + Send + Sync + 'static,
+    {
+        self.route(Method::GET, pattern, handler)
+    }
+
+    pub fn post<F>(self, pattern: &str, handler: F) -> Self
+    where
+        F: Fn(&HttpRequest) -> Response<Vec<u8>> + Send + Sync + 'static,
+    {
+        self.route(Method::POST, pattern, handler)
+    }
+
+    pub fn put<F>(self, pattern: &str, handler: F) -> Self
+    where
+        F: Fn(&HttpRequest) -> Response<Vec<u8>> + Send + Sync + 'static,
+    {
+        self.route(Method::PUT, pattern, handler)
+    }
+
+    pub fn delete<F>(self, pattern: &str, handler: F) -> Self
+    where
+        F: Fn(&HttpRequest) -> Response<Vec<u8>> + Send + Sync + 'static,
+    {
+        self.route(Method::DELETE, pattern, handler)
+    }
+
+    /// Runs `middleware` around every request; see the `middleware` module.
+    pub fn wrap(mut self, middleware: impl Middleware + 'static) -> Self {
+        self.middleware.push(Box::new(middleware));
+        self
+    }
+
+    /// Registered `(method, pattern)` pairs in registration order.
+    pub fn routes(&self) -> impl Iterator<Item = (&Method, &str)> {
+        self.routes.iter().map(|r| (&r.method, r.pattern.as_str()))
+    }
+
+    /// Methods the router answers for `path`, including the automatic `HEAD` and `OPTIONS`.
+    pub fn allowed_methods(&self, path: &str) -> Vec<Method> {
+        let mut methods: Vec<Method> = Vec::new();
+        for route in self.routes.iter() {
+            if match_pattern(&route.pattern, path).is_some() && !methods.contains(&route.method) {
+                methods.push(route.method.clone());
+            }
+        }
+        with_automatic_methods(methods)
+    }
+
+    /// Every method some route answers, for `OPTIONS *`.
+    fn all_methods(&self) -> Vec<Method> {
+        let mut methods: Vec<Method> = Vec::new();
+        for route in self.routes.iter() {
+            if !methods.contains(&route.method) {
+                methods.push(route.method.clone());
+            }
+        }
+        with_automatic_methods(methods)
+    }
+
+    pub fn handle(&self, req: &mut HttpRequest) -> Response<Vec<u8>> {
+        Next {
+            router: self,
+            rest: &self.middleware,
+        }
+        .run(req)
+    }
+
+    /// Routing proper, once the middleware is through.
+    pub(crate) fn dispatch(&self, req: &mut HttpRequest) -> Response<Vec<u8>> {
+        let path = req.uri().path().to_string();
+        let method = req.method().clone();
+
+        if let Some((route, params)) = self.find(&method, &path) {
+            req.extensions_mut().insert(params);
+            return (route.handler)(req);
+        }
+
+        if method == Method::HEAD {
+            if let Some((route, params)) = self.find(&Method::GET, &path) {
+                req.extensions_mut().insert(params);
+                let mut response = (route.handler)(req);
+                strip_body(&mut response);
+                return response;
+            }
+        }
+
+        // `OPTIONS *` asks about the server as a whole (RFC 9110, section 9.3.7).
+        let allowed = if method == Method::OPTIONS && path == "*" {
+            self.all_methods()
+        } else {
+            self.allowed_methods(&path)
+        };
+        if allowed.is_empty() {
+            return status_response(StatusCode::NOT_FOUND, "404 Not Found");
+        }
+
+        let status = if method == Method::OPTIONS {
+            StatusCode::NO_CONTENT
+        } else {
+            StatusCode::METHOD_NOT_ALLOWED
+        };
+        let mut response = status_response(status, "");
+        response
+            .headers_mut()
+            .insert(header::ALLOW, allow_header(&allowed));
+        response
+    }
+
+    /// Dispatches `req` and writes the response.
+    pub fn serve(&self, mut req: HttpRequest) -> io::Result<()> {
+        let response = self.handle(&mut req);
+        req.respond(response)
+    }
+
+    fn find(&self, method: &Method, path: &str) -> Option<(&Route, Params)> {
+        self.routes
+            .iter()
+            .filter(|r| r.method == method)
+            .find_map(|r| match_pattern(&r.pattern, path).map(|values| {
+                let params = Params {
+                    pattern: r.pattern.clone(),
+                    values,
+                };
+                (r, params)
+            }))
+    }
+}
+
+fn match_pattern(pattern: &str, path: &str) -> Option<HashMap<String, String>> {
+    let mut values = HashMap::new();
+    let mut segments = path.trim_start_matches('/').split('/');
+    for part in pattern.trim_start_matches('/').split('/') {
+        if part == "*" {
+            let rest: Vec<&str> = segments.by_ref().collect();
+            values.insert("*".to_string(), rest.join("/"));
+            return Some(values);
+        }
+        let segment = segments.next()?;
+        match part.strip_prefix(':') {
+            Some(name) if !segment.is_empty() => {
+                values.insert(name.to_string(), segment.to_string());
+            }
+            Some(_) => return None,
+            None if part == segment => {}
+            None => return None,
+        }
+    }
+    if segments.next().is_some() {
+        return None;
+    }
+    Some(values)
+}
+
+/// Adds the methods the router answers by itself to the registered `methods`.
+fn with_automatic_methods(mut methods: Vec<Method>) -> Vec<Method> {
+    if methods.is_empty() {
+        return methods;
+    }
+    if methods.contains(&Method::GET) && !methods.contains(&Method::HEAD) {
+        methods.push(Method::HEAD);
+    }
+    if !methods.contains(&Method::OPTIONS) {
+        methods.push(Method::OPTIONS);
+    }
+    methods
+}
+
+fn strip_body(response: &mut Response<Vec<u8>>) {
+    if !response.headers().contains_key(header::CONTENT_LENGTH) {
+        let len = response.body().len();
+        response
+            .headers_mut()
+            .insert(header::CONTENT_LENGTH, len.into());
+    }
+    response.body_mut().clear();
+}
+
+fn status_response(status: StatusCode, body: &str) -> Response<Vec<u8>> {
+    let mut response = Response::new(body.as_bytes().to_vec());
+    *response.status_mut() = status;
+    response
+}
+
+fn allow_header(methods: &[Method]) -> header::HeaderValue {
+    let allow = methods
+        .iter()
+        .map(Method::as_str)
+        .collect::<Vec<_>>()
+        .join(", ");
+    header::HeaderValue::from_str(&allow).expect("method names are valid header values")
+}
+
+/// Test helper: sends `OPTIONS`, `HEAD` and an unregistered method to every route of
+/// `router` and panics unless the automatic responses follow RFC 9110.
+///
+/// Route handlers for `GET` are invoked, so they should be safe to call in tests.
+pub fn assert_route_semantics(router: &Router) {
+    let mut server = Server::bind("127.0.0.1:0").expect("bind a loopback listener");
+    let addr = server.local_addr().expect("listener address");
+
+    let mut exchange = |method: &Method, path: &str| -> Response<Vec<u8>> {
+        let raw = format!("{method} {path} HTTP/1.1\r\nhost: {addr}\r\ncontent-length: 0\r\n\r\n");
+        let mut client = TcpStream::connect(addr).expect("connect to the loopback listener");
+        client.write_all(raw.as_bytes()).expect("send request");
+        let mut req = server.recv().expect("receive request");
+        router.handle(&mut req)
+    };
+
+    let mut patterns: Vec<&str> = router.routes().map(|(_, p)| p).collect();
+    patterns.sort_unstable();
+    patterns.dedup();
+
+    for pattern in patterns {
+        let path = sample_path(pattern);
+        let allowed = router.allowed_methods(&path);
+        let context = format!("route {pattern} (requested as {path})");
+
+        let options = exchange(&Method::OPTIONS, &path);
+        assert!(
+            options.status().is_success(),
+            "{context}: OPTIONS answered {}",
+            options.status()
+        );
+        assert_allow(&options, &allowed, &context);
+
+        if allowed.contains(&Method::GET) {
+            let get = exchange(&Method::GET, &path);
+            let head = exchange(&Method::HEAD, &path);
+            assert_eq!(
+                head.status(),
+                get.status(),
+                "{context}: HEAD status differs from GET"
+            );
+            assert!(head.body().is_empty(), "{context}: HEAD response has a body");
+            let get_len = get
+                .headers()
+                .get(header::CONTENT_LENGTH)
+                .cloned()
+                .unwrap_or_else(|| get.body().len().into());
+            assert_eq!(
+                head.headers().get(header::CONTENT_LENGTH),
+                Some(&get_len),
+                "{context}: HEAD content-length differs from GET"
+            );
+        }
+
+        let unsupported = [
+            Method::GET,
+            Method::POST,
+            Method::PUT,
+            Method::PATCH,
+            Method::DELETE,
+        ]
+        .into_iter()
+        .find(|m| !allowed.contains(m));
+        if let Some(method) = unsupported {
+            let response = exchange(&method, &path);
+            assert_eq!(
+                response.status(),
+                StatusCode::METHOD_NOT_ALLOWED,
+                "{context}: unregistered {method} not answered with 405"
+            );
+            assert_allow(&response, &allowed, &context);
+        }
+    }
+}
+
+fn assert_allow(response: &Response<Vec<u8>>, allowed: &[Method], context: &str) {
+    let allow = response
+        .headers()
+        .get(header::ALLOW)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_else(|| panic!("{context}: {} response without Allow", response.status()));
+    for method in allowed {
+        assert!(
+            allow.split(',').any(|m| m.trim() == method.as_str()),
+            "{context}: Allow {allow:?} is missing {method}"
+        );
+    }
+}
+
+fn sample_path(pattern: &str) -> String {
+    pattern
+        .split('/')
+        .map(|part| if part.starts_with(':') || part == "*" { "x" } else { part })
+        .collect::<Vec<_>>()
+        .join("/")
+}
@@ -0,0 +1,282 @@
+use std::collections::HashMap;
+use std::io::Write;
+use std::net::IpAddr;
+use std::net::Ipv4Addr;
+use std::net::Ipv6Addr;
+use std::net::Shutdown;
+use std::net::SocketAddr;
+use std::net::TcpStream;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::sync::Condvar;
+use std::sync::Mutex;
+use std::time::Duration;
+use std::time::Instant;
+
+use crate::net::Stream;
+use crate::net::WakeAddr;
+
+/// Stops a `Server` from another thread. Obtained from `Server::shutdown_handle`.
+#[derive(Debug, Clone)]
+pub struct ShutdownHandle {
+    flag: Arc<AtomicBool>,
+    addr: WakeAddr,
+}
+
+impl ShutdownHandle {
+    pub(crate) fn new(flag: Arc<AtomicBool>, addr: WakeAddr) -> Self {
+        Self { flag, addr }
+    }
+
+    /// Makes `incoming()` stop yielding. Requests already handed out are unaffected,
+    /// so the loop ends once the current one has been answered.
+    pub fn shutdown(&self) {
+        if self.flag.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        // Wake up a thread blocked in `accept()`.
+        match &self.addr {
+            WakeAddr::Tcp(addr) => drop(TcpStream::connect(wake_addr(*addr))),
+            #[cfg(unix)]
+            WakeAddr::Unix(path) => drop(std::os::unix::net::UnixStream::connect(path)),
+        }
+    }
+
+    pub fn is_shutdown(&self) -> bool {
+        self.flag.load(Ordering::SeqCst)
+    }
+}
+
+fn wake_addr(mut addr: SocketAddr) -> SocketAddr {
+    match addr.ip() {
+        IpAddr::V4(ip) if ip.is_unspecified() => addr.set_ip(Ipv4Addr::LOCALHOST.into()),
+        IpAddr::V6(ip) if ip.is_unspecified() => addr.set_ip(Ipv6Addr::LOCALHOST.into()),
+        _ => {}
+    }
+    addr
+}
+
+/// Connections handed out as `HttpRequest`s that have not been dropped yet.
+#[derive(Debug, Default)]
+pub(crate) struct Connections {
+    state: Mutex<ConnectionsState>,
+    idle: Condvar,
+    /// Requests answered `503` by the overload or connection limit.
+    shed: AtomicU64,
+}
+
+#[derive(Debug, Default)]
+struct ConnectionsState {
+    next_id: u64,
+    streams: HashMap<u64, Tracked>,
+    /// Moving average of how long requests stay handed out.
+    service_time: Option<Duration>,
+}
+
+#[derive(Debug)]
+struct Tracked {
+    stream: Option<Stream>,
+    tags: Vec<String>,
+    /// Already shut down on purpose, so not worth waiting for.
+    closed: bool,
+    since: Instant,
+    /// Counts towards `service_time` when dropped.
+    timed: bool,
+}
+
+impl Tracked {
+    fn close(&mut self, farewell: &[u8]) {
+        self.closed = true;
+        if let Some(mut stream) = self.stream.as_ref() {
+            if !farewell.is_empty() {
+                let _ = stream.write_all(farewell);
+            }
+            let _ = stream.shutdown(Shutdown::Both);
+        }
+    }
+}
+
+/// What `Server::drain` does with connections carrying a given tag.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum DrainPolicy {
+    /// Wait for the request to be dropped, up to the drain timeout.
+    #[default]
+    Wait,
+    /// Close right away, after sending `farewell` (e.g. a WebSocket close frame;
+    /// may be empty).
+    Close { farewell: Vec<u8> },
+}
+
+/// A cap on the connections a server holds open, for `Server::set_connection_limit`.
+/// Both kept-alive connections waiting for their next request and those whose
+/// request is still alive count; the longest idle ones are closed first to make
+/// room.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionLimit {
+    /// Leaves further connections in the listen backlog until one closes.
+    Queue(usize),
+    /// Answers further connections `503 Service Unavailable`, with a `Retry-After`
+    /// estimated from how long requests have been taking, and closes them.
+    Shed(usize),
+}
+
+impl ConnectionLimit {
+    pub(crate) fn max(self) -> usize {
+        match self {
+            Self::Queue(max) | Self::Shed(max) => max,
+        }
+    }
+}
+
+impl Connections {
+    pub(crate) fn track(self: &Arc<Self>, stream: &Stream) -> ConnectionGuard {
+        let mut state = self.state.lock().unwrap();
+        let id = state.next_id;
+        state.next_id += 1;
+        // Without a clone the connection can still be waited for, just not force-closed.
+        let tracked = Tracked {
+            stream: stream.try_clone().ok(),
+            tags: Vec::new(),
+            closed: false,
+            since: Instant::now(),
+            timed: true,
+        };
+        state.streams.insert(id, tracked);
+        ConnectionGuard {
+            connections: self.clone(),
+            id,
+        }
+    }
+
+    pub(crate) fn active(&self) -> usize {
+        self.state.lock().unwrap().streams.len()
+    }
+
+    pub(crate) fn record_shed(&self) {
+        self.shed.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn shed(&self) -> u64 {
+        self.shed.load(Ordering::Relaxed)
+    }
+
+    /// Typical time from handing a request out to dropping it, once one has been.
+    pub(crate) fn service_time(&self) -> Option<Duration> {
+        self.state.lock().unwrap().service_time
+    }
+
+    /// Closes connections whose tags ask for it, then waits until every other
+    /// tracked connection is dropped or `deadline` passes and shuts down the
+    /// remaining ones. Returns how many had to be closed forcibly.
+    pub(crate) fn drain(&self, deadline: Instant, policies: &HashMap<String, DrainPolicy>) -> usize {
+        let mut state = self.state.lock().unwrap();
+        for tracked in state.streams.values_mut() {
+            let farewell = tracked.tags.iter().find_map(|tag| match policies.get(tag) {
+                Some(DrainPolicy::Close { farewell }) => Some(farewell.clone()),
+                _ => None,
+            });
+            if let Some(farewell) = farewell {
+                tracked.close(&farewell);
+            }
+        }
+        while state.streams.values().any(|tracked| !tracked.closed) {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                break;
+            }
+            state = self.idle.wait_timeout(state, remaining).unwrap().0;
+        }
+        let mut forced = 0;
+        for tracked in state.streams.values_mut().filter(|tracked| !tracked.closed) {
+            tracked.close(&[]);
+            forced += 1;
+        }
+        forced
+    }
+
+    /// Shuts down the live connections tagged `tag`; returns how many there were.
+    pub(crate) fn close_tagged(&self, tag: &str, farewell: &[u8]) -> usize {
+        let mut state = self.state.lock().unwrap();
+        let tagged = state
+            .streams
+            .values_mut()
+            .filter(|tracked| tracked.tags.iter().any(|t| t == tag));
+        let mut closed = 0;
+        for tracked in tagged {
+            tracked.close(farewell);
+            closed += 1;
+        }
+        closed
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct ConnectionGuard {
+    connections: Arc<Connections>,
+    id: u64,
+}
+
+impl ConnectionGuard {
+    pub(crate) fn tag(&self, tag: String) {
+        let mut state = self.connections.state.lock().unwrap();
+        if let Some(tracked) = state.streams.get_mut(&self.id) {
+            if !tracked.tags.contains(&tag) {
+                tracked.tags.push(tag);
+            }
+        }
+    }
+}
+
+impl ConnectionGuard {
+    /// Leaves this request out of the service time estimate, e.g. when it was
+    /// turned away rather than served.
+    pub(crate) fn untimed(&self) {
+        let mut state = self.connections.state.lock().unwrap();
+        if let Some(tracked) = state.streams.get_mut(&self.id) {
+            tracked.timed = false;
+        }
+    }
+}
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        let mut state = self.connections.state.lock().unwrap();
+        if let Some(tracked) = state.streams.remove(&self.id).filter(|t| t.timed) {
+            let elapsed = tracked.since.elapsed();
+            // Exponentially weighted, so the estimate follows changes in load.
+            state.service_time = Some(match state.service_time {
+                Some(average) => average.mul_f64(0.9) + elapsed.mul_f64(0.1),
+                None => elapsed,
+            });
+        }
+        self.connections.idle.notify_all();
+    }
+}
+
+#[cfg(all(feature = "signals", unix))]
+impl ShutdownHandle {
+    /// Shuts down on the first SIGINT or SIGTERM, from a background thread. A second
+    /// signal gets the default behaviour, so a stuck handler can still be killed with
+    /// another Ctrl-C.
+    pub fn shutdown_on_signals(&self) -> std::io::Result<()> {
+        use signal_hook::consts::SIGINT;
+        use signal_hook::consts::SIGTERM;
+        use signal_hook::iterator::Signals;
+
+        let mut signals = Signals::new([SIGINT, SIGTERM])?;
+        let handle = self.clone();
+        std::thread::Builder::new()
+            .name("shutdown-signals".into())
+            .spawn(move || {
+                for signal in signals.forever() {
+                    if handle.is_shutdown() {
+                        let _ = signal_hook::low_level::emulate_default_handler(signal);
+                    }
+                    handle.shutdown();
+                }
+            })?;
+        Ok(())
+    }
+}
@@ -0,0 +1,285 @@
+//! A mock HTTP service built from canned responses, for testing clients.
+//!
+//! Every request is recorded, matched or not, so tests can assert on what the
+//! client sent. `TestServer` packages this up for integration tests:
+//!
+//! ```no_run
+//! use blocking_http_server::stub::Stub;
+//! use blocking_http_server::*;
+//!
+//! # fn main() -> std::io::Result<()> {
+//! let stub = Stub::new()
+//!     .on(Method::GET, "/users/:id", Response::new(br#"{"name":"ann"}"#.to_vec()))
+//!     .on_file(Method::GET, "/config", "fixtures/config.json")?;
+//! let captured = stub.captured();
+//!
+//! let mut server = Server::bind("127.0.0.1:8000")?;
+//! let shutdown = server.shutdown_handle()?;
+//! std::thread::spawn(move || stub.run(&mut server));
+//! // ... exercise the client ...
+//! shutdown.shutdown();
+//! assert_eq!(captured.requests()[0].uri.path(), "/users/1");
+//! # Ok(())
+//! # }
+//! ```
+
+use std::io;
+use std::net::Ipv4Addr;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::thread::JoinHandle;
+
+use crate::header::HeaderMap;
+use crate::middleware::Next;
+use crate::HttpRequest;
+use crate::Method;
+use crate::Response;
+use crate::Router;
+use crate::Server;
+use crate::ShutdownHandle;
+use crate::StatusCode;
+use crate::Uri;
+
+#[derive(Debug, Default)]
+pub struct Stub {
+    entries: Vec<Entry>,
+    captured: Captured,
+}
+
+#[derive(Debug)]
+struct Entry {
+    method: Method,
+    pattern: String,
+    responses: Vec<Canned>,
+}
+
+#[derive(Debug)]
+struct Canned {
+    status: StatusCode,
+    headers: HeaderMap,
+    body: Vec<u8>,
+}
+
+impl From<Response<Vec<u8>>> for Canned {
+    fn from(response: Response<Vec<u8>>) -> Self {
+        let (parts, body) = response.into_parts();
+        Self {
+            status: parts.status,
+            headers: parts.headers,
+            body,
+        }
+    }
+}
+
+impl Canned {
+    fn to_response(&self) -> Response<Vec<u8>> {
+        let mut response = Response::new(self.body.clone());
+        *response.status_mut() = self.status;
+        *response.headers_mut() = self.headers.clone();
+        response
+    }
+}
+
+impl Stub {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Answers `method` requests matching `pattern` (same syntax as `Router`)
+    /// with a copy of `response`.
+    pub fn on(self, method: Method, pattern: &str, response: Response<Vec<u8>>) -> Self {
+        self.on_sequence(method, pattern, [response])
+    }
+
+    /// Answers successive matching requests with `responses` in order, repeating
+    /// the last one once they run out. Handy for scripting retries.
+    pub fn on_sequence(
+        mut self,
+        method: Method,
+        pattern: &str,
+        responses: impl IntoIterator<Item = Response<Vec<u8>>>,
+    ) -> Self {
+        self.entries.push(Entry {
+            method,
+            pattern: pattern.to_string(),
+            responses: responses.into_iter().map(Canned::from).collect(),
+        });
+        self
+    }
+
+    /// Like `on`, with a `200` whose body is the current contents of `file`.
+    pub fn on_file(self, method: Method, pattern: &str, file: impl AsRef<Path>) -> io::Result<Self> {
+        let body = std::fs::read(file)?;
+        Ok(self.on(method, pattern, Response::new(body)))
+    }
+
+    /// The log of received requests; keep a clone before the stub is consumed.
+    pub fn captured(&self) -> Captured {
+        self.captured.clone()
+    }
+
+    pub fn into_router(self) -> Router {
+        let captured = self.captured;
+        let router = self.entries.into_iter().fold(Router::new(), |router, entry| {
+            let served = AtomicUsize::new(0);
+            router.route(entry.method.clone(), &entry.pattern, move |_| {
+                let n = served.fetch_add(1, Ordering::Relaxed);
+                match entry.responses.get(n).or(entry.responses.last()) {
+                    Some(canned) => canned.to_response(),
+                    None => Response::new(Vec::new()),
+                }
+            })
+        });
+        router.wrap(move |req: &mut HttpRequest, next: Next<'_>| {
+            captured.record(req);
+            next.run(req)
+        })
+    }
+
+    /// Serves `server` until it is shut down.
+    pub fn run(self, server: &mut Server) {
+        let router = self.into_router();
+        for req in server.incoming().flatten() {
+            let _ = router.serve(req);
+        }
+    }
+}
+
+/// A `Stub` served on an ephemeral loopback port from a background thread,
+/// stopped when dropped.
+///
+/// ```no_run
+/// use blocking_http_server::stub::{Stub, TestServer};
+/// use blocking_http_server::*;
+///
+/// let server = TestServer::spawn(
+///     Stub::new().on(Method::POST, "/events", Response::new(Vec::new())),
+/// )
+/// .unwrap();
+/// // point the client under test at server.url("/events") ...
+/// let request = server.assert_received(&Method::POST, "/events");
+/// assert_eq!(request.body, b"{}");
+/// ```
+#[derive(Debug)]
+pub struct TestServer {
+    addr: SocketAddr,
+    captured: Captured,
+    shutdown: ShutdownHandle,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl TestServer {
+    pub fn spawn(stub: Stub) -> io::Result<Self> {
+        let mut server = Server::bind((Ipv4Addr::LOCALHOST, 0))?;
+        let addr = server.local_addr()?;
+        let shutdown = server.shutdown_handle()?;
+        let captured = stub.captured();
+        let thread = std::thread::Builder::new()
+            .name("test-server".into())
+            .spawn(move || stub.run(&mut server))?;
+        Ok(Self {
+            addr,
+            captured,
+            shutdown,
+            thread: Some(thread),
+        })
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// `http://127.0.0.1:<port>` followed by `path`.
+    pub fn url(&self, path: &str) -> String {
+        format!("http://{}{}", self.addr, path)
+    }
+
+    pub fn received_requests(&self) -> Vec<CapturedRequest> {
+        self.captured.requests()
+    }
+
+    /// Returns the first request for `method` and `path` (query excluded), or panics
+    /// listing what was received instead.
+    #[track_caller]
+    pub fn assert_received(&self, method: &Method, path: &str) -> CapturedRequest {
+        let requests = self.received_requests();
+        match requests.iter().find(|r| r.method == method && r.uri.path() == path) {
+            Some(request) => request.clone(),
+            None => panic!(
+                "no {method} {path} request received; got: {:?}",
+                summary(&requests)
+            ),
+        }
+    }
+
+    /// Panics unless exactly `times` requests for `method` and `path` were received.
+    #[track_caller]
+    pub fn assert_received_times(&self, method: &Method, path: &str, times: usize) {
+        let requests = self.received_requests();
+        let count = requests
+            .iter()
+            .filter(|r| r.method == method && r.uri.path() == path)
+            .count();
+        assert_eq!(
+            count,
+            times,
+            "expected {times} {method} {path} requests; got: {:?}",
+            summary(&requests)
+        );
+    }
+}
+
+impl Drop for TestServer {
+    fn drop(&mut self) {
+        self.shutdown.shutdown();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn summary(requests: &[CapturedRequest]) -> Vec<String> {
+    requests
+        .iter()
+        .map(|r| format!("{} {}", r.method, r.uri))
+        .collect()
+}
+
+/// A request as the stub received it.
+#[derive(Debug, Clone)]
+pub struct CapturedRequest {
+    pub method: Method,
+    pub uri: Uri,
+    pub headers: HeaderMap,
+    pub body: Vec<u8>,
+}
+
+/// Shared, thread-safe log of the requests a `Stub` has received.
+#[derive(Debug, Clone, Default)]
+pub struct Captured {
+    requests: Arc<Mutex<Vec<CapturedRequest>>>,
+}
+
+impl Captured {
+    /// A snapshot of everything received so far, oldest first.
+    pub fn requests(&self) -> Vec<CapturedRequest> {
+        self.requests.lock().unwrap().clone()
+    }
+
+    pub fn clear(&self) {
+        self.requests.lock().unwrap().clear();
+    }
+
+    fn record(&self, req: &HttpRequest) {
+        self.requests.lock().unwrap().push(CapturedRequest {
+            method: req.method().clone(),
+            uri: req.uri().clone(),
+            headers: req.headers().clone(),
+            body: req.body().to_vec(),
+        });
+    }
+}
@@ -0,0 +1,126 @@
+use std::io;
+use std::io::Read;
+use std::io::Write;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+use std::time::Instant;
+
+/// A budget of bytes per second, shared by clones. Up to a second's worth can
+/// go through at once; going over is paid for by sleeping.
+#[derive(Debug, Clone)]
+pub(crate) struct Throttle {
+    rate: u64,
+    /// Bytes available, negative while in debt, as of the instant.
+    state: Arc<Mutex<(f64, Instant)>>,
+}
+
+impl Throttle {
+    pub(crate) fn new(rate: u64) -> Self {
+        let rate = rate.max(1);
+        Self {
+            rate,
+            state: Arc::new(Mutex::new((rate as f64, Instant::now()))),
+        }
+    }
+
+    /// Most bytes worth moving in one call.
+    fn chunk(&self) -> usize {
+        usize::try_from(self.rate).unwrap_or(usize::MAX)
+    }
+
+    /// Takes `n` bytes from the budget, sleeping until it is no longer overdrawn.
+    fn charge(&self, n: usize) {
+        let wait = {
+            let mut state = self.state.lock().unwrap();
+            let (tokens, updated) = &mut *state;
+            let now = Instant::now();
+            let rate = self.rate as f64;
+            *tokens = (*tokens + now.duration_since(*updated).as_secs_f64() * rate).min(rate);
+            *updated = now;
+            *tokens -= n as f64;
+            Duration::from_secs_f64((-*tokens / rate).max(0.0))
+        };
+        if !wait.is_zero() {
+            std::thread::sleep(wait);
+        }
+    }
+}
+
+/// The limits one direction of a connection is held to: its own and the one it
+/// shares with every other connection.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Throttles {
+    pub(crate) conn: Option<Throttle>,
+    pub(crate) total: Option<Throttle>,
+}
+
+impl Throttles {
+    fn iter(&self) -> impl Iterator<Item = &Throttle> {
+        self.conn.iter().chain(&self.total)
+    }
+
+    fn limit(&self, len: usize) -> usize {
+        self.iter().map(Throttle::chunk).fold(len, usize::min)
+    }
+
+    fn charge(&self, n: usize) {
+        for throttle in self.iter() {
+            throttle.charge(n);
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.conn.is_none() && self.total.is_none()
+    }
+
+    pub(crate) fn reader<R: Read>(&self, inner: R) -> Throttled<'_, R> {
+        Throttled {
+            inner,
+            throttles: self,
+        }
+    }
+
+    pub(crate) fn writer<W: Write>(&self, inner: W) -> Throttled<'_, W> {
+        Throttled {
+            inner,
+            throttles: self,
+        }
+    }
+}
+
+/// Reads or writes no faster than `throttles` allow.
+pub(crate) struct Throttled<'a, T> {
+    inner: T,
+    throttles: &'a Throttles,
+}
+
+impl<R: Read> Read for Throttled<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = self.throttles.limit(buf.len());
+        let n = self.inner.read(&mut buf[..len])?;
+        self.throttles.charge(n);
+        Ok(n)
+    }
+}
+
+impl<W: Write> Write for Throttled<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let len = self.throttles.limit(buf.len());
+        let n = self.inner.write(&buf[..len])?;
+        self.throttles.charge(n);
+        Ok(n)
+    }
+
+    fn write_vectored(&mut self, bufs: &[io::IoSlice<'_>]) -> io::Result<usize> {
+        if self.throttles.is_empty() {
+            return self.inner.write_vectored(bufs);
+        }
+        let buf = bufs.iter().find(|buf| !buf.is_empty()).map_or(&[][..], |buf| &**buf);
+        self.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
@@ -0,0 +1,139 @@
+use std::io;
+use std::io::BufRead;
+use std::io::BufReader;
+use std::io::Read;
+use std::io::Write;
+
+use bytes::BufMut;
+use bytes::BytesMut;
+
+use crate::header::HeaderName;
+use crate::header::HeaderValue;
+use crate::HeaderMap;
+
+/// What happens to a kept-alive connection whose request body was never read.
+///
+/// `Content-Length` bodies are always read before a request is handed out, so this
+/// only concerns `Transfer-Encoding: chunked` ones, which are left on the socket
+/// unless `Server::set_chunked_body_limit` has them read too.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum UnreadBody {
+    /// Close the connection after the response.
+    #[default]
+    Close,
+    /// Read and discard up to this many bytes of chunked body after the response,
+    /// keeping the connection if it ends within them. Leaving a body unread on a
+    /// connection that is then reused would have it parsed as the next request.
+    Drain(usize),
+}
+
+/// Discards the rest of a chunked body, `pending` being what was already read
+/// past the header. True if it ended cleanly within `limit` bytes, with nothing
+/// after it.
+pub(crate) fn drain_chunked(stream: impl Read, pending: &[u8], limit: usize) -> bool {
+    let mut reader = BufReader::new(pending.chain(stream).take(limit as u64));
+    let ended = decode_chunks(&mut reader, &mut io::sink(), None).unwrap_or(false);
+    // Anything buffered past the body would be a pipelined request, which is not
+    // supported.
+    ended && reader.buffer().is_empty()
+}
+
+/// How reading a whole chunked body went.
+pub(crate) enum Decoded {
+    /// The body ended, with nothing queued after it.
+    Complete,
+    /// The body ended, but more was sent after it.
+    Trailing,
+    TooLarge,
+    Malformed,
+}
+
+/// Reads a chunked body into `body` and its trailer fields into `trailers`,
+/// `pending` being what was already read past the header. At most `limit` bytes
+/// are read off the wire, framing included. Also returns how many bytes came from
+/// `stream`.
+pub(crate) fn read_chunked(
+    stream: impl Read,
+    pending: &[u8],
+    limit: usize,
+    body: &mut BytesMut,
+    trailers: &mut HeaderMap,
+) -> io::Result<(Decoded, usize)> {
+    let mut reader = BufReader::new(pending.chain(stream).take(limit as u64));
+    let ended = decode_chunks(&mut reader, &mut body.writer(), Some(trailers))?;
+    let trailing = !reader.buffer().is_empty();
+    let left = reader.into_inner().limit() as usize;
+    let read = (limit - left).saturating_sub(pending.len());
+    let decoded = match (ended, trailing) {
+        (true, false) => Decoded::Complete,
+        (true, true) => Decoded::Trailing,
+        (false, _) if left == 0 => Decoded::TooLarge,
+        (false, _) => Decoded::Malformed,
+    };
+    Ok((decoded, read))
+}
+
+/// Copies the chunks' data to `body`, then reads the trailer fields, keeping them
+/// if `trailers` is given. False if the body is malformed or cut short.
+pub(crate) fn decode_chunks(
+    reader: &mut impl BufRead,
+    body: &mut impl Write,
+    mut trailers: Option<&mut HeaderMap>,
+) -> io::Result<bool> {
+    let mut line = Vec::new();
+    loop {
+        let Some(size) = read_line(reader, &mut line)?.and_then(chunk_size) else {
+            return Ok(false);
+        };
+        if size == 0 {
+            break;
+        }
+        let copied = io::copy(&mut reader.take(size), body)?;
+        if copied != size || read_line(reader, &mut line)?.is_none_or(|line| !line.is_empty()) {
+            return Ok(false);
+        }
+    }
+    // Trailer fields, up to the empty line that ends the message.
+    loop {
+        let Some(field) = read_line(reader, &mut line)? else {
+            return Ok(false);
+        };
+        if field.is_empty() {
+            return Ok(true);
+        }
+        if let Some(trailers) = trailers.as_deref_mut() {
+            let Some(colon) = field.iter().position(|&b| b == b':') else {
+                return Ok(false);
+            };
+            let name = HeaderName::from_bytes(&field[..colon]);
+            let value = HeaderValue::from_bytes(field[colon + 1..].trim_ascii());
+            let (Ok(name), Ok(value)) = (name, value) else {
+                return Ok(false);
+            };
+            trailers.append(name, value);
+        }
+    }
+}
+
+/// The next line into `buf`, without its CRLF; `None` at the end of input or if
+/// the line ends in a bare LF.
+fn read_line<'a>(reader: &mut impl BufRead, buf: &'a mut Vec<u8>) -> io::Result<Option<&'a [u8]>> {
+    buf.clear();
+    reader.read_until(b'\n', buf)?;
+    Ok(buf.strip_suffix(b"\r\n"))
+}
+
+/// `1*HEXDIG`, optionally followed by extensions, which are ignored
+/// (RFC 9112, section 7.1).
+fn chunk_size(line: &[u8]) -> Option<u64> {
+    let size = match line.iter().position(|&b| b == b';') {
+        // Whitespace may only come before the extensions.
+        Some(semicolon) => line[..semicolon].trim_ascii_end(),
+        None => line,
+    };
+    if size.is_empty() || !size.iter().all(u8::is_ascii_hexdigit) {
+        return None;
+    }
+    // Hex digits are ASCII; too many of them overflow.
+    u64::from_str_radix(std::str::from_utf8(size).ok()?, 16).ok()
+}
@@ -0,0 +1,88 @@
+use std::io::Read;
+use std::io::Write;
+use std::net::SocketAddr;
+use std::net::TcpStream;
+use std::thread;
+
+use blocking_http_server::*;
+
+/// A server answering `/type` with `negotiate` and `/language` with
+/// `preferred_language`, over the comma-separated `x-available` list; `-` for
+/// `None`.
+fn serve() -> SocketAddr {
+    let mut server = Server::bind("127.0.0.1:0").unwrap();
+    let addr = server.local_addr().unwrap();
+    thread::spawn(move || {
+        for req in server.incoming().flatten() {
+            let available = req.headers().get("x-available").map_or("", |v| v.to_str().unwrap()).to_owned();
+            let available: Vec<&str> = available.split(',').map(str::trim).collect();
+            let chosen = match req.uri().path() {
+                "/type" => req.negotiate(&available),
+                _ => req.preferred_language(&available),
+            };
+            let _ = req.respond(Response::new(chosen.unwrap_or("-").as_bytes().to_vec()));
+        }
+    });
+    addr
+}
+
+fn chosen(addr: SocketAddr, path: &str, header: &str, value: &str, available: &str) -> String {
+    let mut stream = TcpStream::connect(addr).unwrap();
+    write!(
+        stream,
+        "GET {path} HTTP/1.1\r\nhost: x\r\n{header}: {value}\r\nx-available: {available}\r\nconnection: close\r\n\r\n"
+    )
+    .unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    response.split_once("\r\n\r\n").unwrap().1.to_owned()
+}
+
+#[test]
+fn negotiate() {
+    let addr = serve();
+    let cases = [
+        // The most specific range wins, even at a lower q.
+        ("text/*;q=1, text/html;q=0.5", "text/html, text/plain", "text/plain"),
+        ("*/*;q=0.9, application/json;q=0.1", "application/json, text/html", "text/html"),
+        ("text/html;level=1;q=0.2, text/html", "text/html;level=1", "text/html;level=1"),
+        // q=0 excludes.
+        ("application/json;q=0, */*", "application/json, text/html", "text/html"),
+        ("text/*;q=0", "text/html, text/plain", "-"),
+        // Bad q values count as 0.
+        ("application/json;q=2, text/html;q=0.1", "application/json, text/html", "text/html"),
+        ("application/json;q=x, text/html;q=0.1", "application/json, text/html", "text/html"),
+        ("application/json;q=-1", "application/json", "-"),
+        // What follows q is an extension, not a parameter to match.
+        ("text/html;q=0.5;level=1", "text/html", "text/html"),
+        ("text/html;charset=utf-8;q=0.5", "text/html;charset=utf-8", "text/html;charset=utf-8"),
+        // Ties go to the server's order.
+        ("application/json, text/html", "text/html, application/json", "text/html"),
+    ];
+    for (accept, available, expected) in cases {
+        assert_eq!(chosen(addr, "/type", "accept", accept, available), expected, "{accept}");
+    }
+}
+
+#[test]
+fn preferred_language() {
+    let addr = serve();
+    let cases = [
+        // en-gb falls back to en when nothing closer is supported.
+        ("en-gb", "de, en", "en"),
+        ("en-gb, de;q=0.5", "de, en", "en"),
+        ("en-gb", "en-us, de", "-"),
+        ("en", "de, en-us", "en-us"),
+        // q=0 excludes, wildcard included.
+        ("*, de;q=0", "de, en", "en"),
+        ("en;q=0, *", "en-us, fr", "fr"),
+        // Bad q values count as 0.
+        ("de;q=1.5, en;q=0.1", "de, en", "en"),
+        ("de;q=abc", "de", "-"),
+        // Parameters after q are ignored.
+        ("de;q=0.2;foo=bar, en;q=0.1", "en, de", "de"),
+    ];
+    for (accept, available, expected) in cases {
+        assert_eq!(chosen(addr, "/language", "accept-language", accept, available), expected, "{accept}");
+    }
+}
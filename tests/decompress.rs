@@ -0,0 +1,59 @@
+#![cfg(feature = "compression-gzip")]
+
+use std::io::Read;
+use std::io::Write;
+use std::net::SocketAddr;
+use std::net::TcpStream;
+use std::thread;
+
+use blocking_http_server::*;
+
+/// A server answering with the body it got.
+fn serve(limit: usize) -> SocketAddr {
+    let mut server = Server::bind("127.0.0.1:0").unwrap();
+    server.set_request_size_limit(limit);
+    server.set_request_decompression(true);
+    let addr = server.local_addr().unwrap();
+    thread::spawn(move || {
+        for req in server.incoming().flatten() {
+            let body = req.body().to_vec();
+            let _ = req.respond(Response::new(body));
+        }
+    });
+    addr
+}
+
+fn gzip(data: &[u8]) -> Vec<u8> {
+    let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+    encoder.write_all(data).unwrap();
+    encoder.finish().unwrap()
+}
+
+/// Sends `body` gzipped and returns the status line and body of the response.
+fn post(addr: SocketAddr, body: &[u8]) -> (String, Vec<u8>) {
+    let body = gzip(body);
+    let mut stream = TcpStream::connect(addr).unwrap();
+    let head = format!(
+        "POST / HTTP/1.1\r\nhost: x\r\ncontent-encoding: gzip\r\ncontent-length: {}\r\nconnection: close\r\n\r\n",
+        body.len()
+    );
+    stream.write_all(&[head.as_bytes(), &body].concat()).unwrap();
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).unwrap();
+    let end = response.windows(4).position(|w| w == b"\r\n\r\n").unwrap();
+    let status = String::from_utf8_lossy(&response[..12]).into_owned();
+    (status, response[end + 4..].to_vec())
+}
+
+#[test]
+fn gzip_bodies_are_decoded() {
+    let addr = serve(1024);
+    assert_eq!(post(addr, b"hello"), ("HTTP/1.1 200".to_owned(), b"hello".to_vec()));
+}
+
+#[test]
+fn decoded_bodies_over_the_limit_get_413() {
+    let addr = serve(1024);
+    let (status, _) = post(addr, &[b'a'; 4096]);
+    assert_eq!(status, "HTTP/1.1 413");
+}
@@ -0,0 +1,181 @@
+//! Runs the examples and checks that they do what they say.
+
+use std::io::BufRead;
+use std::io::BufReader;
+use std::io::Read;
+use std::io::Write;
+use std::net::SocketAddr;
+use std::net::TcpStream;
+use std::path::PathBuf;
+use std::process::Child;
+use std::process::Command;
+use std::process::Stdio;
+use std::thread;
+use std::time::Duration;
+
+use blocking_http_server::*;
+
+/// A running example, killed on drop.
+struct Example {
+    child: Child,
+    addr: SocketAddr,
+}
+
+impl Drop for Example {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// Starts `examples/<name>.rs` on a free port, with `args` after the address,
+/// and waits for it to print the URL it serves. `cargo test` builds the
+/// examples; `features` are what to build it with otherwise.
+fn run(name: &str, features: &str, args: &[&str]) -> Example {
+    let target = std::env::current_exe().unwrap().parent().unwrap().parent().unwrap().to_owned();
+    let binary: PathBuf = target.join("examples").join(name);
+    if !binary.exists() {
+        let built = Command::new(env!("CARGO"))
+            .args(["build", "--example", name, "--features", features])
+            .status()
+            .unwrap();
+        assert!(built.success(), "building {name}");
+    }
+    let mut child = Command::new(binary)
+        .arg("127.0.0.1:0")
+        .args(args)
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut line = String::new();
+    BufReader::new(child.stdout.take().unwrap()).read_line(&mut line).unwrap();
+    let url = line.split_whitespace().find(|word| word.contains("://")).unwrap_or_else(|| panic!("{line}"));
+    let addr = url.split_once("://").unwrap().1.trim_end_matches('/').parse().unwrap();
+    Example { child, addr }
+}
+
+/// The head and body of the response to `request`, sent with `connection: close`.
+fn get(addr: SocketAddr, request: &str) -> (String, String) {
+    let mut stream = TcpStream::connect(addr).unwrap();
+    stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    stream.write_all(request.as_bytes()).unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    let (head, body) = response.split_once("\r\n\r\n").unwrap();
+    (head.to_owned(), body.to_owned())
+}
+
+fn temp_root(name: &str) -> PathBuf {
+    let root = std::env::temp_dir().join(format!("blocking-http-server-{name}-{}", std::process::id()));
+    std::fs::create_dir_all(&root).unwrap();
+    std::fs::write(root.join("hello.txt"), "hello from disk\n").unwrap();
+    root
+}
+
+#[cfg(all(feature = "metrics", feature = "serde"))]
+#[test]
+fn json_api_stores_todos() {
+    let example = run("json_api", "metrics,serde", &[]);
+    let (head, body) = get(
+        example.addr,
+        "POST /todos HTTP/1.1\r\nhost: x\r\ncontent-type: application/json\r\ncontent-length: 20\r\nconnection: close\r\n\r\n{\"title\":\"buy milk\"}",
+    );
+    assert!(head.starts_with("HTTP/1.1 201"), "{head}");
+    assert_eq!(body, r#"{"id":0}"#);
+    let (_, body) = get(example.addr, "GET /todos/0 HTTP/1.1\r\nhost: x\r\nconnection: close\r\n\r\n");
+    assert_eq!(body, r#""buy milk""#);
+    let (_, metrics) = get(example.addr, "GET /metrics HTTP/1.1\r\nhost: x\r\nconnection: close\r\n\r\n");
+    assert!(metrics.contains("http_requests_total"), "{metrics}");
+}
+
+#[test]
+fn static_files_serves_the_directory() {
+    let root = temp_root("static-files");
+    let example = run("static_files", "", &[root.to_str().unwrap()]);
+    let (head, body) = get(example.addr, "GET /hello.txt HTTP/1.1\r\nhost: x\r\nconnection: close\r\n\r\n");
+    assert!(head.starts_with("HTTP/1.1 200"), "{head}");
+    assert_eq!(body, "hello from disk\n");
+    let (_, listing) = get(example.addr, "GET / HTTP/1.1\r\nhost: x\r\nconnection: close\r\n\r\n");
+    assert!(listing.contains("hello.txt"), "{listing}");
+    let _ = std::fs::remove_dir_all(root);
+}
+
+#[test]
+fn sse_streams_events() {
+    let example = run("sse", "", &[]);
+    let (head, page) = get(example.addr, "GET / HTTP/1.1\r\nhost: x\r\nconnection: close\r\n\r\n");
+    assert!(head.starts_with("HTTP/1.1 200"), "{head}");
+    assert!(page.contains("EventSource"), "{page}");
+
+    let mut stream = TcpStream::connect(example.addr).unwrap();
+    stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    stream.write_all(b"GET /events HTTP/1.1\r\nhost: x\r\n\r\n").unwrap();
+    let mut received = String::new();
+    let mut reader = BufReader::new(stream);
+    while !received.contains("data: uptime") {
+        assert!(reader.read_line(&mut received).unwrap() > 0, "{received}");
+    }
+    assert!(received.to_ascii_lowercase().contains("content-type: text/event-stream"), "{received}");
+}
+
+#[test]
+fn reverse_proxy_forwards_to_the_upstream() {
+    let mut upstream = Server::bind("127.0.0.1:0").unwrap();
+    let upstream_addr = upstream.local_addr().unwrap();
+    thread::spawn(move || {
+        for req in upstream.incoming().flatten() {
+            let body = format!("upstream saw {}", req.uri());
+            let _ = req.respond(Response::new(body.into_bytes()));
+        }
+    });
+    let example = run("reverse_proxy", "", &[&upstream_addr.to_string()]);
+    let (head, body) = get(example.addr, "GET /a?b=c HTTP/1.1\r\nhost: x\r\nconnection: close\r\n\r\n");
+    assert!(head.starts_with("HTTP/1.1 200"), "{head}");
+    assert_eq!(body, "upstream saw /a?b=c");
+}
+
+#[test]
+fn websocket_echo_echoes() {
+    let example = run("websocket_echo", "", &[]);
+    let url = format!("ws://{}/", example.addr);
+    let (mut socket, response) = tungstenite::connect(url).unwrap();
+    assert_eq!(response.status(), StatusCode::SWITCHING_PROTOCOLS);
+    socket.send(tungstenite::Message::text("hello")).unwrap();
+    assert_eq!(socket.read().unwrap(), tungstenite::Message::text("hello"));
+    socket.close(None).unwrap();
+
+    let (head, _) = get(example.addr, "GET / HTTP/1.1\r\nhost: x\r\nconnection: close\r\n\r\n");
+    assert!(head.starts_with("HTTP/1.1 426"), "{head}");
+}
+
+#[cfg(all(unix, feature = "tls"))]
+#[test]
+fn tls_static_files_serves_over_https() {
+    use std::sync::Arc;
+
+    use blocking_http_server::rustls::pki_types::ServerName;
+
+    let root = temp_root("tls-static-files");
+    let generated = rcgen::generate_simple_self_signed(vec!["localhost".into()]).unwrap();
+    std::fs::write(root.join("cert.pem"), generated.cert.pem()).unwrap();
+    std::fs::write(root.join("key.pem"), generated.key_pair.serialize_pem()).unwrap();
+    let path = |name: &str| root.join(name).to_str().unwrap().to_owned();
+    let example = run("tls_static_files", "tls", &[&path(""), &path("cert.pem"), &path("key.pem")]);
+
+    let mut roots = rustls::RootCertStore::empty();
+    roots.add(generated.cert.der().clone()).unwrap();
+    let config = rustls::ClientConfig::builder().with_root_certificates(roots).with_no_client_auth();
+    let name = ServerName::try_from("localhost").unwrap();
+    let conn = rustls::ClientConnection::new(Arc::new(config), name).unwrap();
+    let tcp = TcpStream::connect(example.addr).unwrap();
+    tcp.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    let mut tls = rustls::StreamOwned::new(conn, tcp);
+    tls.write_all(b"GET /hello.txt HTTP/1.1\r\nhost: localhost\r\nconnection: close\r\n\r\n").unwrap();
+    let mut response = Vec::new();
+    // The server may close without close_notify once the response is out.
+    let _ = tls.read_to_end(&mut response);
+    let response = String::from_utf8(response).unwrap();
+    assert!(response.starts_with("HTTP/1.1 200"), "{response}");
+    assert!(response.ends_with("\r\n\r\nhello from disk\n"), "{response}");
+    let _ = std::fs::remove_dir_all(root);
+}
@@ -0,0 +1,93 @@
+use std::io::Read;
+use std::io::Write;
+use std::net::SocketAddr;
+use std::net::TcpStream;
+use std::path::PathBuf;
+use std::thread;
+
+use blocking_http_server::files::ServeDir;
+use blocking_http_server::*;
+
+const SECRET: &str = "top secret";
+
+/// A fresh directory holding `secret` next to the served `public/`, which has a
+/// file in `a/` and, on unix, a `link` to the secret.
+fn root(name: &str) -> PathBuf {
+    let root = std::env::temp_dir().join(format!("serve-dir-{name}-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&root);
+    std::fs::create_dir_all(root.join("public/a")).unwrap();
+    std::fs::write(root.join("secret"), SECRET).unwrap();
+    std::fs::write(root.join("public/a/file.txt"), "public").unwrap();
+    #[cfg(unix)]
+    std::os::unix::fs::symlink(root.join("secret"), root.join("public/link")).unwrap();
+    root
+}
+
+fn serve(dir: ServeDir) -> SocketAddr {
+    let mut server = Server::bind("127.0.0.1:0").unwrap();
+    let addr = server.local_addr().unwrap();
+    thread::spawn(move || {
+        for req in server.incoming().flatten() {
+            let _ = dir.send(&req);
+        }
+    });
+    addr
+}
+
+/// Sends `target` as the raw request-target and returns the status and body.
+fn get(addr: SocketAddr, target: &str) -> (u16, String) {
+    let mut stream = TcpStream::connect(addr).unwrap();
+    write!(stream, "GET {target} HTTP/1.1\r\nhost: x\r\nconnection: close\r\n\r\n").unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    let (head, body) = response.split_once("\r\n\r\n").unwrap_or((&response, ""));
+    (head[9..12].parse().unwrap(), body.to_owned())
+}
+
+const ESCAPES: [&str; 7] = [
+    "/../secret",
+    "/%2e%2e/secret",
+    "/%2E%2e%2fsecret",
+    "/..%5csecret",
+    "/a/%2e%2e/%2e%2e/secret",
+    "//etc/passwd",
+    "/a/file.txt%00.png",
+];
+
+#[test]
+fn traversal_never_leaves_the_root() {
+    let root = root("traversal");
+    let addr = serve(ServeDir::new(root.join("public")).unwrap());
+    assert_eq!(get(addr, "/a/file.txt"), (200, "public".into()));
+    for target in ESCAPES {
+        let (status, body) = get(addr, target);
+        assert!(status == 403 || status == 404, "{target}: {status}");
+        assert!(!body.contains(SECRET) && !body.contains("root:"), "{target}");
+    }
+    std::fs::remove_dir_all(&root).unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn symlinks_out_of_the_root_are_forbidden() {
+    let root = root("symlink");
+    let addr = serve(ServeDir::new(root.join("public")).unwrap());
+    let (status, body) = get(addr, "/link");
+    assert_eq!(status, 403);
+    assert!(!body.contains(SECRET));
+    std::fs::remove_dir_all(&root).unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn symlink_escapes_only_follow_symlinks() {
+    let root = root("escapes");
+    let addr = serve(ServeDir::new(root.join("public")).unwrap().with_symlink_escapes(true));
+    assert_eq!(get(addr, "/link"), (200, SECRET.into()));
+    for target in ESCAPES {
+        let (status, body) = get(addr, target);
+        assert!(status == 403 || status == 404, "{target}: {status}");
+        assert!(!body.contains(SECRET) && !body.contains("root:"), "{target}");
+    }
+    std::fs::remove_dir_all(&root).unwrap();
+}
@@ -0,0 +1,114 @@
+#![cfg(all(unix, feature = "h2"))]
+
+use std::io::Read;
+use std::io::Write;
+use std::net::SocketAddr;
+use std::net::TcpStream;
+use std::thread;
+use std::time::Duration;
+
+use blocking_http_server::*;
+use bytes::Bytes;
+
+/// A plaintext server answering `<method> <path> <body>`.
+fn serve() -> SocketAddr {
+    let mut server = Server::bind("127.0.0.1:0").unwrap();
+    server.set_chunked_body_limit(Some(1024));
+    let addr = server.local_addr().unwrap();
+    thread::spawn(move || {
+        for req in server.incoming().flatten() {
+            let body = format!("{} {} {}", req.method(), req.uri().path(), String::from_utf8_lossy(req.body()));
+            let _ = req.respond(Response::new(body.into_bytes()));
+        }
+    });
+    addr
+}
+
+#[test]
+fn prior_knowledge_is_served() {
+    let addr = serve();
+    tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap().block_on(async {
+        let tcp = tokio::net::TcpStream::connect(addr).await.unwrap();
+        let (client, conn) = h2::client::handshake(tcp).await.unwrap();
+        tokio::spawn(conn);
+        let mut client = client.ready().await.unwrap();
+        let post = Request::post("http://localhost/grpc").body(()).unwrap();
+        let (response, mut upload) = client.send_request(post, false).unwrap();
+        upload.send_data(Bytes::from_static(b"hello"), true).unwrap();
+
+        let response = response.await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let mut body = response.into_body();
+        let mut received = Vec::new();
+        while let Some(data) = body.data().await {
+            received.extend_from_slice(&data.unwrap());
+        }
+        assert_eq!(received, b"POST /grpc hello");
+    });
+}
+
+/// The next frame's type, flags, stream and payload.
+fn read_frame(stream: &mut TcpStream) -> (u8, u8, u32, Vec<u8>) {
+    let mut head = [0; 9];
+    stream.read_exact(&mut head).unwrap();
+    let length = u32::from_be_bytes([0, head[0], head[1], head[2]]) as usize;
+    let id = u32::from_be_bytes([head[5], head[6], head[7], head[8]]) & 0x7fff_ffff;
+    let mut payload = vec![0; length];
+    stream.read_exact(&mut payload).unwrap();
+    (head[3], head[4], id, payload)
+}
+
+#[test]
+fn upgraded_requests_are_answered_on_stream_1() {
+    let addr = serve();
+    let mut stream = TcpStream::connect(addr).unwrap();
+    stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    stream
+        .write_all(
+            b"GET /up HTTP/1.1\r\nhost: x\r\nconnection: Upgrade, HTTP2-Settings\r\nupgrade: h2c\r\n\
+              http2-settings: AAMAAABkAAQCAAAAAAIAAAAA\r\n\r\n",
+        )
+        .unwrap();
+    let mut head = Vec::new();
+    let mut byte = [0];
+    while !head.ends_with(b"\r\n\r\n") {
+        stream.read_exact(&mut byte).unwrap();
+        head.push(byte[0]);
+    }
+    let head = String::from_utf8(head).unwrap();
+    assert!(head.starts_with("HTTP/1.1 101"), "{head}");
+    assert!(head.to_ascii_lowercase().contains("upgrade: h2c"), "{head}");
+
+    // The preface and an empty SETTINGS frame.
+    stream.write_all(b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n\0\0\0\x04\0\0\0\0\0").unwrap();
+    let mut status = None;
+    loop {
+        match read_frame(&mut stream) {
+            // HEADERS; `:status: 200` is entry 8 of the static table.
+            (0x1, _, 1, block) => status = block.first().copied(),
+            // DATA
+            (0x0, flags, 1, payload) if !payload.is_empty() || flags & 0x1 != 0 => {
+                assert_eq!(payload, b"GET /up ");
+                break;
+            }
+            _ => {}
+        }
+    }
+    assert_eq!(status, Some(0x88));
+}
+
+#[test]
+fn upgrades_with_a_body_stay_on_http_1_1() {
+    let addr = serve();
+    let mut stream = TcpStream::connect(addr).unwrap();
+    stream
+        .write_all(
+            b"POST /form HTTP/1.1\r\nhost: x\r\nconnection: Upgrade, HTTP2-Settings, close\r\nupgrade: h2c\r\n\
+              http2-settings: AAMAAABkAAQCAAAAAAIAAAAA\r\ncontent-length: 5\r\n\r\nhello",
+        )
+        .unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    assert!(response.starts_with("HTTP/1.1 200"), "{response}");
+    assert!(response.ends_with("POST /form hello"), "{response}");
+}
@@ -0,0 +1,185 @@
+use std::io::Read;
+use std::io::Write;
+use std::net::SocketAddr;
+use std::net::TcpStream;
+use std::thread;
+use std::time::Duration;
+
+use blocking_http_server::*;
+
+/// A kept-alive server answering `<method> <path>`, without reading bodies.
+fn serve(configure: impl FnOnce(&mut Server)) -> SocketAddr {
+    let mut server = Server::bind("127.0.0.1:0").unwrap();
+    server.set_keep_alive(Some(Duration::from_secs(5)));
+    configure(&mut server);
+    let addr = server.local_addr().unwrap();
+    thread::spawn(move || {
+        for req in server.incoming().flatten() {
+            let body = format!("{} {}", req.method(), req.uri().path());
+            let _ = req.respond(Response::new(body.into_bytes()));
+        }
+    });
+    addr
+}
+
+fn connect(addr: SocketAddr) -> TcpStream {
+    let stream = TcpStream::connect(addr).unwrap();
+    stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    stream
+}
+
+/// The status and body of the next response, `None` once the server has closed
+/// the connection.
+fn read_response(stream: &mut TcpStream) -> Option<(u16, String)> {
+    let mut head = Vec::new();
+    let mut byte = [0];
+    while !head.ends_with(b"\r\n\r\n") {
+        match stream.read(&mut byte) {
+            Ok(0) | Err(_) => return None,
+            Ok(_) => head.push(byte[0]),
+        }
+    }
+    let head = String::from_utf8(head).unwrap();
+    let status = head[9..12].parse().unwrap();
+    let length = head
+        .lines()
+        .find_map(|line| {
+            let (name, value) = line.split_once(':')?;
+            name.eq_ignore_ascii_case("content-length").then(|| value.trim().parse::<usize>().unwrap())
+        })
+        .unwrap_or(0);
+    let mut body = vec![0; length];
+    stream.read_exact(&mut body).ok()?;
+    Some((status, String::from_utf8(body).unwrap()))
+}
+
+fn closed(stream: &mut TcpStream) -> bool {
+    read_response(stream).is_none()
+}
+
+#[test]
+fn content_length_bodies_keep_the_connection() {
+    let addr = serve(|_| {});
+    let mut stream = connect(addr);
+    stream
+        .write_all(b"POST /a HTTP/1.1\r\nhost: x\r\ncontent-length: 5\r\n\r\nhello")
+        .unwrap();
+    assert_eq!(read_response(&mut stream), Some((200, "POST /a".into())));
+    stream.write_all(b"GET /b HTTP/1.1\r\nhost: x\r\n\r\n").unwrap();
+    assert_eq!(read_response(&mut stream), Some((200, "GET /b".into())));
+}
+
+#[test]
+fn invalid_content_length_is_rejected() {
+    let addr = serve(|_| {});
+    for length in ["+5", "-5", "0x5", "5 5", "", "5, 6", "99999999999999999999999"] {
+        let mut stream = connect(addr);
+        let request = format!("POST / HTTP/1.1\r\nhost: x\r\ncontent-length: {length}\r\n\r\nhello");
+        stream.write_all(request.as_bytes()).unwrap();
+        assert_eq!(read_response(&mut stream).map(|(status, _)| status), Some(400), "{length:?}");
+        assert!(closed(&mut stream), "{length:?}");
+    }
+}
+
+#[test]
+fn conflicting_content_lengths_are_rejected() {
+    let addr = serve(|_| {});
+    let mut stream = connect(addr);
+    stream
+        .write_all(b"POST / HTTP/1.1\r\nhost: x\r\ncontent-length: 5\r\ncontent-length: 0\r\n\r\nhello")
+        .unwrap();
+    assert_eq!(read_response(&mut stream).map(|(status, _)| status), Some(400));
+    assert!(closed(&mut stream));
+}
+
+#[test]
+fn repeated_content_lengths_are_one() {
+    let addr = serve(|_| {});
+    let mut stream = connect(addr);
+    stream
+        .write_all(b"POST /a HTTP/1.1\r\nhost: x\r\ncontent-length: 5, 5\r\ncontent-length: 5\r\n\r\nhello")
+        .unwrap();
+    assert_eq!(read_response(&mut stream), Some((200, "POST /a".into())));
+    stream.write_all(b"GET /b HTTP/1.1\r\nhost: x\r\n\r\n").unwrap();
+    assert_eq!(read_response(&mut stream), Some((200, "GET /b".into())));
+}
+
+#[test]
+fn decoded_chunked_bodies_count_against_the_memory_budget() {
+    let addr = serve(|server| {
+        server.set_request_size_limit(4096);
+        server.set_memory_budget(Some(8192));
+        server.set_chunked_body_limit(Some(64 * 1024));
+    });
+    let mut stream = connect(addr);
+    let mut request = b"POST / HTTP/1.1\r\nhost: x\r\ntransfer-encoding: chunked\r\n\r\n4000\r\n".to_vec();
+    request.extend_from_slice(&[b'a'; 0x4000]);
+    request.extend_from_slice(b"\r\n0\r\n\r\n");
+    stream.write_all(&request).unwrap();
+    assert_eq!(read_response(&mut stream).map(|(status, _)| status), Some(503));
+
+    let mut stream = connect(addr);
+    stream
+        .write_all(b"POST /small HTTP/1.1\r\nhost: x\r\ntransfer-encoding: chunked\r\n\r\n5\r\nhello\r\n0\r\n\r\n")
+        .unwrap();
+    assert_eq!(read_response(&mut stream), Some((200, "POST /small".into())));
+}
+
+#[test]
+fn chunk_sizes_are_read_strictly() {
+    let addr = serve(|server| server.set_chunked_body_limit(Some(1024)));
+    let head = b"POST / HTTP/1.1\r\nhost: x\r\ntransfer-encoding: chunked\r\n\r\n".to_vec();
+    let malformed: [&[u8]; 6] = [
+        b"+5\r\nhello\r\n0\r\n\r\n",
+        b" 5\r\nhello\r\n0\r\n\r\n",
+        b"5 \r\nhello\r\n0\r\n\r\n",
+        b"5\nhello\r\n0\r\n\r\n",
+        b"5\r\nhello\n0\r\n\r\n",
+        b"5\xff\r\nhello\r\n0\r\n\r\n",
+    ];
+    for body in malformed {
+        let mut stream = connect(addr);
+        stream.write_all(&[head.as_slice(), body].concat()).unwrap();
+        let status = read_response(&mut stream).map(|(status, _)| status);
+        assert_eq!(status, Some(400), "{}", body.escape_ascii());
+    }
+
+    let mut stream = connect(addr);
+    stream
+        .write_all(&[head.as_slice(), b"5 ;name=value\r\nhello\r\n0\r\n\r\n"].concat())
+        .unwrap();
+    assert_eq!(read_response(&mut stream), Some((200, "POST /".into())));
+}
+
+const UNREAD_CHUNKED: &[u8] =
+    b"POST /upload HTTP/1.1\r\nhost: x\r\ntransfer-encoding: chunked\r\n\r\n5\r\nhello\r\n6\r\n world\r\n0\r\n\r\n";
+
+#[test]
+fn unread_chunked_bodies_close_the_connection() {
+    let addr = serve(|server| server.set_unread_body(UnreadBody::Close));
+    let mut stream = connect(addr);
+    stream.write_all(UNREAD_CHUNKED).unwrap();
+    assert_eq!(read_response(&mut stream), Some((200, "POST /upload".into())));
+    assert!(closed(&mut stream));
+}
+
+#[test]
+fn unread_chunked_bodies_are_drained_within_the_limit() {
+    let addr = serve(|server| server.set_unread_body(UnreadBody::Drain(1024)));
+    let mut stream = connect(addr);
+    stream.write_all(UNREAD_CHUNKED).unwrap();
+    assert_eq!(read_response(&mut stream), Some((200, "POST /upload".into())));
+    stream.write_all(b"GET /next HTTP/1.1\r\nhost: x\r\n\r\n").unwrap();
+    assert_eq!(read_response(&mut stream), Some((200, "GET /next".into())));
+}
+
+#[test]
+fn unread_chunked_bodies_over_the_limit_close_the_connection() {
+    let addr = serve(|server| server.set_unread_body(UnreadBody::Drain(8)));
+    let mut stream = connect(addr);
+    stream.write_all(UNREAD_CHUNKED).unwrap();
+    assert_eq!(read_response(&mut stream), Some((200, "POST /upload".into())));
+    // Were the rest of the body read as the next request, this would get a 400.
+    let _ = stream.write_all(b"GET /next HTTP/1.1\r\nhost: x\r\n\r\n");
+    assert!(closed(&mut stream));
+}
@@ -0,0 +1,69 @@
+use std::io::Read;
+use std::io::Write;
+use std::net::TcpStream;
+use std::sync::mpsc;
+use std::thread;
+
+use blocking_http_server::*;
+
+/// The response to a `GET` for `path`, read to the end.
+fn get(stream: &mut TcpStream, path: &str) -> String {
+    write!(stream, "GET {path} HTTP/1.1\r\nhost: x\r\nconnection: close\r\n\r\n").unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    response
+}
+
+#[test]
+fn requests_over_the_limit_get_503_with_retry_after() {
+    let mut server = Server::bind("127.0.0.1:0").unwrap();
+    server.set_overload_limit(Some(1));
+    #[cfg(feature = "metrics")]
+    let metrics = metrics::Metrics::new();
+    #[cfg(feature = "metrics")]
+    metrics.instrument(&mut server);
+    let addr = server.local_addr().unwrap();
+    let (held, hold) = mpsc::channel();
+    thread::spawn(move || {
+        for req in server.incoming().flatten() {
+            if req.uri().path() == "/slow" {
+                // Alive until the test says so, taking up the only slot.
+                held.send(req).unwrap();
+            } else {
+                let _ = req.respond(Response::new(b"ok".to_vec()));
+            }
+        }
+    });
+
+    let mut slow = TcpStream::connect(addr).unwrap();
+    write!(slow, "GET /slow HTTP/1.1\r\nhost: x\r\n\r\n").unwrap();
+    let (release, held) = mpsc::channel::<()>();
+    let waiter = thread::spawn(move || {
+        let req = hold.recv().unwrap();
+        held.recv().unwrap();
+        req.respond(Response::new(b"slow".to_vec())).unwrap();
+    });
+
+    let response = get(&mut TcpStream::connect(addr).unwrap(), "/fast");
+    assert!(response.starts_with("HTTP/1.1 503"), "{response}");
+    let retry_after = response
+        .lines()
+        .find_map(|line| line.strip_prefix("retry-after: "))
+        .expect("no retry-after");
+    assert!(retry_after.parse::<u64>().unwrap() >= 1);
+
+    #[cfg(feature = "metrics")]
+    {
+        let exported = metrics.to_prometheus();
+        assert!(exported.contains("\nhttp_connections_active 1\n"), "{exported}");
+        assert!(exported.contains("\nhttp_requests_shed_total 1\n"), "{exported}");
+    }
+
+    release.send(()).unwrap();
+    waiter.join().unwrap();
+    let mut slow_response = String::new();
+    slow.read_to_string(&mut slow_response).ok();
+    assert!(slow_response.starts_with("HTTP/1.1 200"));
+    let response = get(&mut TcpStream::connect(addr).unwrap(), "/fast");
+    assert!(response.starts_with("HTTP/1.1 200"), "{response}");
+}
@@ -0,0 +1,273 @@
+use std::io;
+use std::io::Read;
+use std::io::Write;
+use std::net::SocketAddr;
+use std::net::TcpListener;
+use std::net::TcpStream;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::thread;
+
+use blocking_http_server::proxy::UpstreamPool;
+use blocking_http_server::*;
+
+/// An upstream answering with the forwarding headers it got.
+fn upstream() -> SocketAddr {
+    let mut server = Server::bind("127.0.0.1:0").unwrap();
+    let addr = server.local_addr().unwrap();
+    thread::spawn(move || {
+        for req in server.incoming().flatten() {
+            let value = |name| req.headers().get(name).map_or("-", |v| v.to_str().unwrap()).to_owned();
+            let body = format!("{}|{}", value("x-forwarded-for"), value("x-forwarded-proto"));
+            let _ = req.respond(Response::new(body.into_bytes()));
+        }
+    });
+    addr
+}
+
+fn proxy(upstream: SocketAddr, trusted: &str) -> SocketAddr {
+    let mut server = Server::bind("127.0.0.1:0").unwrap();
+    server.set_trusted_proxies([trusted.parse().unwrap()]);
+    let addr = server.local_addr().unwrap();
+    thread::spawn(move || {
+        for req in server.incoming().flatten() {
+            proxy::forward(&req, &upstream.to_string()).unwrap();
+        }
+    });
+    addr
+}
+
+fn body(addr: SocketAddr, request: &str) -> String {
+    let mut stream = TcpStream::connect(addr).unwrap();
+    stream.write_all(request.as_bytes()).unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    response.split_once("\r\n\r\n").unwrap().1.to_owned()
+}
+
+const REQUEST: &str = "GET / HTTP/1.1\r\nhost: x\r\nx-forwarded-for: 1.1.1.1\r\nx-forwarded-for: 2.2.2.2, 3.3.3.3\r\nx-forwarded-proto: https\r\n\r\n";
+
+#[test]
+fn untrusted_peers_cannot_set_the_protocol() {
+    let addr = proxy(upstream(), "10.0.0.0/8");
+    assert_eq!(body(addr, REQUEST), "1.1.1.1, 2.2.2.2, 3.3.3.3, 127.0.0.1|http");
+}
+
+#[test]
+fn trusted_peers_keep_their_protocol() {
+    let addr = proxy(upstream(), "127.0.0.0/8");
+    assert_eq!(body(addr, REQUEST), "1.1.1.1, 2.2.2.2, 3.3.3.3, 127.0.0.1|https");
+}
+
+/// A proxy answering `502` when `forward` fails.
+fn proxy_with(
+    configure: impl FnOnce(&mut Server),
+    forward: impl Fn(&HttpRequest) -> io::Result<()> + Send + 'static,
+) -> SocketAddr {
+    let mut server = Server::bind("127.0.0.1:0").unwrap();
+    configure(&mut server);
+    let addr = server.local_addr().unwrap();
+    thread::spawn(move || {
+        for req in server.incoming().flatten() {
+            if forward(&req).is_err() {
+                let _ = req.respond(Response::new(Vec::new()).with_status(StatusCode::BAD_GATEWAY));
+            }
+        }
+    });
+    addr
+}
+
+/// An upstream that hands each connection, numbered from 0, to `serve`. Also
+/// returns how many connections it has accepted.
+fn raw_upstream(serve: impl Fn(usize, TcpStream) + Send + Sync + 'static) -> (SocketAddr, Arc<AtomicUsize>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let accepted = Arc::new(AtomicUsize::new(0));
+    let counter = accepted.clone();
+    let serve = Arc::new(serve);
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let n = counter.fetch_add(1, Ordering::SeqCst);
+            let serve = serve.clone();
+            thread::spawn(move || serve(n, stream));
+        }
+    });
+    (addr, accepted)
+}
+
+/// The next request head on `stream`, `None` once it is closed.
+fn read_head(stream: &mut TcpStream) -> Option<String> {
+    let mut head = Vec::new();
+    let mut byte = [0];
+    while !head.ends_with(b"\r\n\r\n") {
+        match stream.read(&mut byte) {
+            Ok(0) | Err(_) => return None,
+            Ok(_) => head.push(byte[0]),
+        }
+    }
+    Some(String::from_utf8(head).unwrap())
+}
+
+/// The status line, head and body of the response to `request`, the body
+/// decoded if chunked, with any trailers appended to the head.
+fn exchange(addr: SocketAddr, request: &[u8]) -> (String, String) {
+    let mut stream = TcpStream::connect(addr).unwrap();
+    stream.write_all(request).unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    let (head, body) = response.split_once("\r\n\r\n").unwrap();
+    if !head.to_ascii_lowercase().contains("transfer-encoding: chunked") {
+        return (head.to_owned(), body.to_owned());
+    }
+    let (mut head, mut rest, mut decoded) = (head.to_owned(), body, String::new());
+    loop {
+        let (size, after) = rest.split_once("\r\n").unwrap();
+        let size = usize::from_str_radix(size.split(';').next().unwrap().trim(), 16).unwrap();
+        if size == 0 {
+            head.push_str("\r\n");
+            head.push_str(after.trim_end());
+            return (head, decoded);
+        }
+        decoded.push_str(&after[..size]);
+        rest = &after[size + 2..];
+    }
+}
+
+#[test]
+fn chunked_request_bodies_are_forwarded_once_read() {
+    let upstream = || {
+        let mut server = Server::bind("127.0.0.1:0").unwrap();
+        server.set_chunked_body_limit(Some(1024));
+        let addr = server.local_addr().unwrap();
+        thread::spawn(move || {
+            for req in server.incoming().flatten() {
+                let _ = req.respond(Response::new(req.body().to_vec()));
+            }
+        });
+        addr
+    };
+    const REQUEST: &[u8] =
+        b"POST / HTTP/1.1\r\nhost: x\r\ntransfer-encoding: chunked\r\nconnection: close\r\n\r\n5\r\nhello\r\n6\r\n world\r\n0\r\n\r\n";
+
+    let upstream_addr = upstream();
+    let read = proxy_with(
+        |server| {
+            server.set_chunked_body_limit(Some(1024));
+        },
+        move |req| proxy::forward(req, &upstream_addr.to_string()),
+    );
+    assert_eq!(exchange(read, REQUEST).1, "hello world");
+
+    let upstream_addr = upstream();
+    let unread = proxy_with(|_| {}, move |req| proxy::forward(req, &upstream_addr.to_string()));
+    assert_eq!(exchange(unread, REQUEST).1, "");
+}
+
+#[test]
+fn chunked_responses_keep_their_trailers() {
+    let (upstream, _) = raw_upstream(|_, mut stream| {
+        read_head(&mut stream);
+        let _ = stream.write_all(
+            b"HTTP/1.1 200 OK\r\ntransfer-encoding: chunked\r\ntrailer: x-checksum\r\n\r\n\
+              5\r\nhello\r\n6\r\n world\r\n0\r\nx-checksum: abc\r\nx-undeclared: 1\r\n\r\n",
+        );
+    });
+    let addr = proxy_with(|_| {}, move |req| proxy::forward(req, &upstream.to_string()));
+    let (head, body) = exchange(addr, b"GET / HTTP/1.1\r\nhost: x\r\nte: trailers\r\nconnection: close\r\n\r\n");
+    assert_eq!(body, "hello world");
+    assert!(head.ends_with("x-checksum: abc"), "{head}");
+    assert!(!head.contains("x-undeclared"), "{head}");
+}
+
+#[test]
+fn headers_named_in_connection_are_stripped() {
+    let (upstream, _) = raw_upstream(|_, mut stream| {
+        let head = read_head(&mut stream).unwrap().to_ascii_lowercase();
+        let names: Vec<&str> = head.lines().skip(1).filter_map(|line| line.split_once(':')).map(|(n, _)| n).collect();
+        let body = names.join(",");
+        let _ = write!(
+            stream,
+            "HTTP/1.1 200 OK\r\nconnection: x-internal\r\nx-internal: 1\r\nx-public: 1\r\ncontent-length: {}\r\n\r\n{body}",
+            body.len()
+        );
+    });
+    let addr = proxy_with(|_| {}, move |req| proxy::forward(req, &upstream.to_string()));
+    let (head, body) = exchange(
+        addr,
+        b"GET / HTTP/1.1\r\nhost: x\r\nconnection: foo, close\r\nfoo: 1\r\nbar: 1\r\nkeep-alive: 5\r\n\r\n",
+    );
+    let forwarded: Vec<&str> = body.split(',').collect();
+    assert!(forwarded.contains(&"bar"), "{body}");
+    assert!(!forwarded.contains(&"foo") && !forwarded.contains(&"keep-alive"), "{body}");
+    assert!(head.contains("x-public: 1"), "{head}");
+    assert!(!head.contains("x-internal"), "{head}");
+}
+
+#[test]
+fn unframed_responses_run_until_the_upstream_closes() {
+    let (upstream, _) = raw_upstream(|_, mut stream| {
+        read_head(&mut stream);
+        let _ = stream.write_all(b"HTTP/1.1 200 OK\r\ncontent-type: text/plain\r\n\r\nall of it");
+    });
+    let pool = UpstreamPool::new();
+    let addr = proxy_with(|_| {}, move |req| pool.forward(req, &upstream.to_string()));
+    for _ in 0..2 {
+        let (head, body) = exchange(addr, b"GET / HTTP/1.1\r\nhost: x\r\nconnection: close\r\n\r\n");
+        assert!(head.starts_with("HTTP/1.1 200"), "{head}");
+        assert_eq!(body, "all of it");
+    }
+}
+
+/// Answers every request on a connection with its number, kept alive.
+fn keep_alive(n: usize, mut stream: TcpStream) {
+    while read_head(&mut stream).is_some() {
+        let body = n.to_string();
+        let _ = write!(stream, "HTTP/1.1 200 OK\r\ncontent-length: {}\r\n\r\n{body}", body.len());
+    }
+}
+
+#[test]
+fn pooled_connections_are_reused() {
+    let (upstream, accepted) = raw_upstream(keep_alive);
+    let pool = UpstreamPool::new();
+    let addr = proxy_with(|_| {}, move |req| pool.forward(req, &upstream.to_string()));
+    for _ in 0..3 {
+        assert_eq!(exchange(addr, b"GET / HTTP/1.1\r\nhost: x\r\nconnection: close\r\n\r\n").1, "0");
+    }
+    assert_eq!(accepted.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn stale_pooled_connections_are_retried_once() {
+    // The first connection answers once, then drops the next request unanswered.
+    let (upstream, accepted) = raw_upstream(|n, mut stream| {
+        if n > 0 {
+            return keep_alive(n, stream);
+        }
+        read_head(&mut stream);
+        let _ = stream.write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 1\r\n\r\n0");
+        read_head(&mut stream);
+    });
+    let pool = UpstreamPool::new();
+    let addr = proxy_with(|_| {}, move |req| pool.forward(req, &upstream.to_string()));
+    const GET: &[u8] = b"GET / HTTP/1.1\r\nhost: x\r\nconnection: close\r\n\r\n";
+    assert_eq!(exchange(addr, GET).1, "0");
+    assert_eq!(exchange(addr, GET).1, "1");
+    assert_eq!(accepted.load(Ordering::SeqCst), 2);
+}
+
+#[test]
+fn stale_pooled_connections_are_not_retried_for_post() {
+    let (upstream, accepted) = raw_upstream(|_, mut stream| {
+        read_head(&mut stream);
+        let _ = stream.write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 1\r\n\r\n0");
+        read_head(&mut stream);
+    });
+    let pool = UpstreamPool::new();
+    let addr = proxy_with(|_| {}, move |req| pool.forward(req, &upstream.to_string()));
+    assert_eq!(exchange(addr, b"GET / HTTP/1.1\r\nhost: x\r\nconnection: close\r\n\r\n").1, "0");
+    let (head, _) = exchange(addr, b"POST / HTTP/1.1\r\nhost: x\r\ncontent-length: 0\r\nconnection: close\r\n\r\n");
+    assert!(head.starts_with("HTTP/1.1 502"), "{head}");
+    assert_eq!(accepted.load(Ordering::SeqCst), 1);
+}
@@ -0,0 +1,76 @@
+use std::io::Read;
+use std::io::Write;
+use std::net::SocketAddr;
+use std::net::TcpListener;
+use std::net::TcpStream;
+
+use blocking_http_server::*;
+
+/// A free loopback port, for listeners that do not report the one they got.
+fn free_port() -> u16 {
+    TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port()
+}
+
+/// The status and `Location` of the response to `head`.
+fn redirect(addr: SocketAddr, head: &str) -> (u16, Option<String>) {
+    let mut stream = TcpStream::connect(addr).unwrap();
+    write!(stream, "{head}connection: close\r\n\r\n").unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    let location = response.lines().find_map(|line| {
+        let (name, value) = line.split_once(':')?;
+        name.eq_ignore_ascii_case("location").then(|| value.trim().to_owned())
+    });
+    (response[9..12].parse().unwrap(), location)
+}
+
+#[test]
+fn locations_keep_the_host_and_path() {
+    let addr = SocketAddr::from(([127, 0, 0, 1], free_port()));
+    let handle = Server::spawn_https_redirect(addr, 8443).unwrap();
+    let location = |head| redirect(addr, head);
+
+    assert_eq!(
+        location("GET /a?b=c HTTP/1.1\r\nhost: example.com:8080\r\n"),
+        (301, Some("https://example.com:8443/a?b=c".into()))
+    );
+    assert_eq!(
+        location("GET / HTTP/1.1\r\nhost: [::1]:8080\r\n"),
+        (301, Some("https://[::1]:8443/".into()))
+    );
+    assert_eq!(
+        location("GET / HTTP/1.1\r\nhost: [2001:db8::1]\r\n"),
+        (301, Some("https://[2001:db8::1]:8443/".into()))
+    );
+    assert_eq!(
+        location("GET http://example.org:8080/x HTTP/1.1\r\nhost: ignored.example\r\n"),
+        (301, Some("https://example.org:8443/x".into()))
+    );
+    assert_eq!(
+        location("POST /form HTTP/1.1\r\nhost: example.com\r\ncontent-length: 0\r\n"),
+        (308, Some("https://example.com:8443/form".into()))
+    );
+    assert_eq!(location("GET / HTTP/1.0\r\n"), (400, None));
+    handle.shutdown();
+}
+
+#[test]
+fn redirects_name_the_https_port_unless_it_is_443() {
+    let server = Server::bind("127.0.0.1:0").unwrap();
+    let port = free_port();
+    let handle = server.with_http_redirect(port).unwrap();
+    let https = server.local_addr().unwrap().port();
+    assert_eq!(
+        redirect(([127, 0, 0, 1], port).into(), "GET /a HTTP/1.1\r\nhost: example.com\r\n"),
+        (301, Some(format!("https://example.com:{https}/a")))
+    );
+    handle.shutdown();
+
+    let addr = SocketAddr::from(([127, 0, 0, 1], free_port()));
+    let handle = Server::spawn_https_redirect(addr, 443).unwrap();
+    assert_eq!(
+        redirect(addr, "GET /a HTTP/1.1\r\nhost: example.com:80\r\n"),
+        (301, Some("https://example.com/a".into()))
+    );
+    handle.shutdown();
+}
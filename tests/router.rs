@@ -0,0 +1,23 @@
+use blocking_http_server::router::assert_route_semantics;
+use blocking_http_server::*;
+
+#[test]
+fn automatic_responses_follow_the_rfc() {
+    let router = Router::new()
+        .get("/", |_| Response::new(b"home".to_vec()))
+        .post("/items", |_| Response::new(Vec::new()).with_status(StatusCode::CREATED))
+        .get("/items/:id", |req| {
+            let id = req.extensions().get::<router::Params>().and_then(|p| p.get("id")).unwrap_or("");
+            Response::new(format!("item {id}").into_bytes())
+        });
+    assert_route_semantics(&router);
+}
+
+#[test]
+#[should_panic(expected = "HEAD response has a body")]
+fn head_routes_with_a_body_are_caught() {
+    let router = Router::new()
+        .get("/", |_| Response::new(b"home".to_vec()))
+        .route(Method::HEAD, "/", |_| Response::new(b"home".to_vec()));
+    assert_route_semantics(&router);
+}
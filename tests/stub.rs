@@ -0,0 +1,75 @@
+use std::io::Read;
+use std::io::Write;
+use std::net::SocketAddr;
+use std::net::TcpStream;
+
+use blocking_http_server::stub::Stub;
+use blocking_http_server::stub::TestServer;
+use blocking_http_server::*;
+
+/// The status and body of the response to `method path`.
+fn request(addr: SocketAddr, method: &str, path: &str) -> (u16, String) {
+    let mut stream = TcpStream::connect(addr).unwrap();
+    write!(stream, "{method} {path} HTTP/1.1\r\nhost: x\r\ncontent-length: 2\r\nconnection: close\r\n\r\n{{}}").unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    let (head, body) = response.split_once("\r\n\r\n").unwrap();
+    (head[9..12].parse().unwrap(), body.to_owned())
+}
+
+fn status(status: StatusCode) -> Response<Vec<u8>> {
+    Response::new(status.as_str().as_bytes().to_vec()).with_status(status)
+}
+
+#[test]
+fn sequences_repeat_their_last_response() {
+    let server = TestServer::spawn(Stub::new().on_sequence(
+        Method::GET,
+        "/flaky",
+        [status(StatusCode::SERVICE_UNAVAILABLE), status(StatusCode::BAD_GATEWAY), status(StatusCode::OK)],
+    ))
+    .unwrap();
+    let statuses: Vec<u16> = (0..5).map(|_| request(server.addr(), "GET", "/flaky").0).collect();
+    assert_eq!(statuses, [503, 502, 200, 200, 200]);
+    server.assert_received_times(&Method::GET, "/flaky", 5);
+}
+
+#[test]
+fn unmatched_requests_are_captured() {
+    let server = TestServer::spawn(Stub::new().on(Method::GET, "/known", status(StatusCode::OK))).unwrap();
+    assert_eq!(request(server.addr(), "POST", "/unknown?x=1").0, 404);
+    let captured = server.assert_received(&Method::POST, "/unknown");
+    assert_eq!(captured.uri.query(), Some("x=1"));
+    assert_eq!(captured.body, b"{}");
+    server.assert_received_times(&Method::GET, "/known", 0);
+}
+
+#[test]
+fn assert_received_times_counts_method_and_path() {
+    let server = TestServer::spawn(Stub::new().on(Method::GET, "/a", status(StatusCode::OK))).unwrap();
+    request(server.addr(), "GET", "/a");
+    request(server.addr(), "GET", "/a?page=2");
+    request(server.addr(), "POST", "/a");
+    request(server.addr(), "GET", "/b");
+    server.assert_received_times(&Method::GET, "/a", 2);
+    server.assert_received_times(&Method::POST, "/a", 1);
+    server.assert_received_times(&Method::GET, "/b", 1);
+}
+
+#[test]
+#[should_panic(expected = "expected 2 GET /a requests")]
+fn assert_received_times_panics_on_a_mismatch() {
+    let server = TestServer::spawn(Stub::new()).unwrap();
+    request(server.addr(), "GET", "/a");
+    server.assert_received_times(&Method::GET, "/a", 2);
+}
+
+#[test]
+fn dropping_the_server_stops_it() {
+    let server = TestServer::spawn(Stub::new().on(Method::GET, "/", status(StatusCode::OK))).unwrap();
+    let addr = server.addr();
+    assert_eq!(request(addr, "GET", "/").0, 200);
+    // Returns once the serving thread has been joined, listener closed.
+    drop(server);
+    assert!(TcpStream::connect(addr).is_err());
+}
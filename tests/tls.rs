@@ -0,0 +1,111 @@
+#![cfg(all(unix, feature = "tls"))]
+
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::thread;
+
+use blocking_http_server::rustls::pki_types::CertificateDer;
+use blocking_http_server::rustls::pki_types::PrivateKeyDer;
+use blocking_http_server::rustls::pki_types::ServerName;
+use blocking_http_server::*;
+use tokio::io::AsyncReadExt;
+use tokio::io::AsyncWriteExt;
+use tokio_rustls::client::TlsStream;
+use tokio_rustls::TlsConnector;
+
+/// An HTTPS server for `localhost` answering `<method> <path> <peer ip> <body>`,
+/// and its certificate.
+fn serve() -> (SocketAddr, CertificateDer<'static>) {
+    let key = rcgen::generate_simple_self_signed(vec!["localhost".into()]).unwrap();
+    let cert = key.cert.der().clone();
+    let config = rustls::ServerConfig::builder()
+        .with_no_client_auth()
+        .with_single_cert(vec![cert.clone()], PrivateKeyDer::Pkcs8(key.key_pair.serialize_der().into()))
+        .unwrap();
+    let mut server = Server::unbound().unwrap();
+    server.set_chunked_body_limit(Some(1024));
+    let addr = server.bind_tls("127.0.0.1:0", config).unwrap();
+    thread::spawn(move || {
+        for req in server.incoming().flatten() {
+            let peer = req.peer_addr.as_tcp().map_or("-".into(), |addr| addr.ip().to_string());
+            let body = format!(
+                "{} {} {peer} {}",
+                req.method(),
+                req.uri().path(),
+                String::from_utf8_lossy(req.body())
+            );
+            let _ = req.respond(Response::new(body.into_bytes()).with_header("x-served", HeaderValue::from_static("1")));
+        }
+    });
+    (addr, cert)
+}
+
+/// Connects to `addr` trusting `cert`, offering `alpn`.
+async fn connect(addr: SocketAddr, cert: CertificateDer<'static>, alpn: &[&[u8]]) -> TlsStream<tokio::net::TcpStream> {
+    let mut roots = rustls::RootCertStore::empty();
+    roots.add(cert).unwrap();
+    let mut config = rustls::ClientConfig::builder().with_root_certificates(roots).with_no_client_auth();
+    config.alpn_protocols = alpn.iter().map(|p| p.to_vec()).collect();
+    let tcp = tokio::net::TcpStream::connect(addr).await.unwrap();
+    let name = ServerName::try_from("localhost").unwrap();
+    TlsConnector::from(Arc::new(config)).connect(name, tcp).await.unwrap()
+}
+
+fn block_on<F: std::future::Future>(future: F) -> F::Output {
+    tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap().block_on(future)
+}
+
+#[test]
+fn https_requests_are_served() {
+    let (addr, cert) = serve();
+    let response = block_on(async {
+        let mut tls = connect(addr, cert, &[b"http/1.1"]).await;
+        assert_eq!(tls.get_ref().1.alpn_protocol(), Some(&b"http/1.1"[..]));
+        tls.write_all(b"POST /echo HTTP/1.1\r\nhost: localhost\r\ncontent-length: 5\r\nconnection: close\r\n\r\nhello")
+            .await
+            .unwrap();
+        let mut response = String::new();
+        tls.read_to_string(&mut response).await.unwrap();
+        response
+    });
+    assert!(response.starts_with("HTTP/1.1 200"), "{response}");
+    assert!(response.ends_with("\r\n\r\nPOST /echo 127.0.0.1 hello"), "{response}");
+}
+
+#[cfg(feature = "h2")]
+#[test]
+fn h2_streams_are_served_as_requests() {
+    use bytes::Bytes;
+
+    let (addr, cert) = serve();
+    block_on(async {
+        let tls = connect(addr, cert, &[b"h2", b"http/1.1"]).await;
+        assert_eq!(tls.get_ref().1.alpn_protocol(), Some(&b"h2"[..]));
+        let (client, conn) = h2::client::handshake(tls).await.unwrap();
+        tokio::spawn(conn);
+        let mut client = client.ready().await.unwrap();
+
+        let get = Request::get("https://localhost/a").body(()).unwrap();
+        let (get, _) = client.send_request(get, true).unwrap();
+        // No content-length, so the body goes to the server chunked.
+        let post = Request::post("https://localhost/b").body(()).unwrap();
+        let (post, mut upload) = client.send_request(post, false).unwrap();
+        upload.send_data(Bytes::from_static(b"hel"), false).unwrap();
+        upload.send_data(Bytes::from_static(b"lo"), true).unwrap();
+
+        for (response, expected) in [(get, "GET /a 127.0.0.1 "), (post, "POST /b 127.0.0.1 hello")] {
+            let response = response.await.unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+            assert_eq!(response.headers()["x-served"], "1");
+            assert!(!response.headers().contains_key(header::CONNECTION));
+            let mut body = response.into_body();
+            let mut received = Vec::new();
+            while let Some(data) = body.data().await {
+                let data = data.unwrap();
+                let _ = body.flow_control().release_capacity(data.len());
+                received.extend_from_slice(&data);
+            }
+            assert_eq!(String::from_utf8(received).unwrap(), expected);
+        }
+    });
+}